@@ -7,13 +7,18 @@
 //! - `start`: launches the application
 //! - `version`: print application version
 //!
+//! Additionally, `feeder delegate` builds an unsigned feeder delegation
+//! transaction, and `vote` builds an unsigned oracle vote transaction.
+//!
 //! See the `impl Configurable` below for how to specify the path to the
 //! application's configuration file.
 
+mod feeder;
 mod start;
 mod version;
+mod vote;
 
-use self::{start::StartCmd, version::VersionCmd};
+use self::{feeder::FeederCmd, start::StartCmd, version::VersionCmd, vote::VoteCmd};
 use crate::config::DelphiConfig;
 use abscissa_core::{Command, Configurable, Help, Options, Runnable};
 use std::path::PathBuf;
@@ -28,10 +33,18 @@ pub enum DelphiCmd {
     #[options(help = "get usage information")]
     Help(Help<Self>),
 
+    /// The `feeder` subcommand
+    #[options(help = "manage the oracle feeder delegation")]
+    Feeder(FeederCmd),
+
     /// The `start` subcommand
     #[options(help = "start the application")]
     Start(StartCmd),
 
+    /// The `vote` subcommand
+    #[options(help = "output an unsigned oracle vote transaction")]
+    Vote(VoteCmd),
+
     /// The `version` subcommand
     #[options(help = "display version information")]
     Version(VersionCmd),
@@ -0,0 +1,186 @@
+//! `feeder` subcommand
+
+use crate::{
+    application::APP,
+    config::network::{TerraConfig, TxFormat, VoterConfig},
+    networks::terra::{
+        lcd::LcdClient,
+        msg::{MsgDelegateFeedConsent, OracleMsg},
+        parse_address, tx,
+    },
+    prelude::*,
+    Error,
+};
+use abscissa_core::{Command, Help, Options, Runnable};
+use std::{fs, path::PathBuf, process};
+use stdtx::amino::types::StdFee;
+
+/// `feeder` subcommand
+#[derive(Command, Debug, Options, Runnable)]
+pub enum FeederCmd {
+    /// The `help` subcommand
+    #[options(help = "get usage information")]
+    Help(Help<Self>),
+
+    /// The `delegate` subcommand
+    #[options(help = "delegate the validator's oracle votes to the feeder")]
+    Delegate(DelegateCmd),
+}
+
+/// `feeder delegate` subcommand
+///
+/// Outputs an unsigned transaction containing a `MsgDelegateFeedConsent`
+/// from the configured validator to the configured feeder (selected by
+/// `--context` if several are configured), to be signed offline with the
+/// validator operator's key.
+#[derive(Command, Debug, Default, Options)]
+pub struct DelegateCmd {
+    /// Chain ID of the network (required if multiple are configured)
+    #[options(no_short, help = "chain ID of the network to delegate on")]
+    chain_id: Option<String>,
+
+    /// Request context selecting the validator (defaults to the top-level
+    /// `feeder`/`validator`)
+    #[options(no_short, help = "context of the validator to delegate for")]
+    context: String,
+
+    /// Transaction format (defaults to the configured `tx_format`)
+    #[options(help = "transaction format: amino or protobuf")]
+    format: Option<TxFormat>,
+
+    /// File to write the unsigned transaction to (defaults to stdout)
+    #[options(short = "o", help = "file to write the unsigned transaction to")]
+    output: Option<PathBuf>,
+
+    /// Only check whether the delegation is in place
+    #[options(no_short, help = "only check whether the delegation is in place")]
+    check: bool,
+
+    /// Output a transaction even if the delegation is already in place
+    #[options(no_short, help = "output a transaction even if already delegated")]
+    force: bool,
+}
+
+impl Runnable for DelegateCmd {
+    /// Build the feeder delegation transaction
+    fn run(&self) {
+        let config = APP.config();
+
+        let terra_config = config
+            .network
+            .terra(self.chain_id.as_deref())
+            .unwrap_or_else(|e| {
+                status_err!("{}", e);
+                process::exit(1);
+            });
+
+        let voter_config = terra_config
+            .voter_config(&self.context)
+            .unwrap_or_else(|e| {
+                status_err!("{}", e);
+                process::exit(1);
+            });
+
+        let delegated =
+            match &terra_config.lcd {
+                Some(lcd_config) => {
+                    let lcd = LcdClient::new(lcd_config, &config.https).unwrap_or_else(|e| {
+                        status_err!("{}", e);
+                        process::exit(1);
+                    });
+
+                    let validator = voter_config.validator.clone();
+
+                    let feeder = abscissa_tokio::run(&APP, async move {
+                        lcd.feeder_delegation(&validator).await
+                    })
+                    .unwrap_or_else(|e| {
+                        status_err!("executor exited with error: {}", e);
+                        process::exit(1);
+                    })
+                    .unwrap_or_else(|e| {
+                        status_err!("error querying feeder delegation: {}", e);
+                        process::exit(1);
+                    });
+
+                    Some(feeder == voter_config.feeder)
+                }
+                None => None,
+            };
+
+        match delegated {
+            Some(true) => status_ok!(
+                "Delegated",
+                "{} already delegates to {}",
+                voter_config.validator,
+                voter_config.feeder
+            ),
+            Some(false) => status_warn!(
+                "{} doesn't delegate to {}",
+                voter_config.validator,
+                voter_config.feeder
+            ),
+            None if self.check => {
+                status_err!("checking the delegation requires [network.terra] lcd");
+                process::exit(1);
+            }
+            None => status_warn!("no LCD configured; not checking the existing delegation"),
+        }
+
+        if self.check {
+            process::exit(if delegated == Some(true) { 0 } else { 1 });
+        }
+
+        if delegated == Some(true) && !self.force {
+            return;
+        }
+
+        let unsigned_tx = self
+            .unsigned_tx(terra_config, &voter_config)
+            .unwrap_or_else(|e| {
+                status_err!("{}", e);
+                process::exit(1);
+            });
+
+        let unsigned_tx_json =
+            serde_json::to_string_pretty(&unsigned_tx).expect("JSON serialization error");
+
+        match &self.output {
+            Some(path) => {
+                fs::write(path, unsigned_tx_json).unwrap_or_else(|e| {
+                    status_err!("couldn't write {}: {}", path.display(), e);
+                    process::exit(1);
+                });
+
+                status_ok!("Wrote", "unsigned transaction to {}", path.display());
+            }
+            None => println!("{}", unsigned_tx_json),
+        }
+    }
+}
+
+impl DelegateCmd {
+    /// Build the unsigned delegation transaction
+    fn unsigned_tx(
+        &self,
+        terra_config: &TerraConfig,
+        voter_config: &VoterConfig,
+    ) -> Result<serde_json::Value, Error> {
+        let msg = MsgDelegateFeedConsent {
+            operator: parse_address(&voter_config.validator)?,
+            delegate: parse_address(&voter_config.feeder)?,
+        };
+
+        let format = self.format.unwrap_or(terra_config.tx_format);
+        let fee = StdFee::from(&terra_config.fee);
+
+        // Fee payer/granter options apply to the feeder's transactions, not
+        // the validator operator's
+        tx::unsigned_tx(
+            format,
+            &[OracleMsg::DelegateFeedConsent(msg)],
+            &fee,
+            &Default::default(),
+        )
+    }
+}
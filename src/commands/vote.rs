@@ -0,0 +1,86 @@
+//! `vote` subcommand
+
+use crate::{
+    application::APP, config::network::TxFormat, networks::terra::oracle::ExchangeRateOracle,
+    prelude::*, sources::Sources,
+};
+use abscissa_core::{Command, Options, Runnable};
+use std::{fs, path::PathBuf, process, sync::Arc};
+
+/// `vote` subcommand
+///
+/// Computes an oracle vote and outputs it as an unsigned transaction, to be
+/// signed and broadcast with the chain's CLI (e.g. `terrad tx sign`) when
+/// Tendermint KMS is unavailable.
+#[derive(Command, Debug, Default, Options)]
+pub struct VoteCmd {
+    /// Chain ID of the network (required if multiple are configured)
+    #[options(no_short, help = "chain ID of the network to vote on")]
+    chain_id: Option<String>,
+
+    /// Request context selecting the validator (defaults to the top-level
+    /// `feeder`/`validator`)
+    #[options(no_short, help = "context of the validator to vote for")]
+    context: String,
+
+    /// Transaction format (defaults to the configured `tx_format`)
+    #[options(help = "transaction format: amino or protobuf")]
+    format: Option<TxFormat>,
+
+    /// File to write the unsigned transaction to (defaults to stdout)
+    #[options(short = "o", help = "file to write the unsigned transaction to")]
+    output: Option<PathBuf>,
+}
+
+impl Runnable for VoteCmd {
+    /// Build the unsigned oracle vote transaction
+    fn run(&self) {
+        let config = APP.config();
+
+        let terra_config = config
+            .network
+            .terra(self.chain_id.as_deref())
+            .unwrap_or_else(|e| {
+                status_err!("{}", e);
+                process::exit(1);
+            });
+
+        let format = self.format.unwrap_or(terra_config.tx_format);
+
+        let oracle = Sources::new(&config)
+            .and_then(|sources| ExchangeRateOracle::new(&config, terra_config, Arc::new(sources)))
+            .unwrap_or_else(|e| {
+                status_err!("{}", e);
+                process::exit(1);
+            });
+
+        let context = self.context.clone();
+
+        let unsigned_tx = abscissa_tokio::run(&APP, async move {
+            oracle.unsigned_vote_tx(&context, format).await
+        })
+        .unwrap_or_else(|e| {
+            status_err!("executor exited with error: {}", e);
+            process::exit(1);
+        })
+        .unwrap_or_else(|e| {
+            status_err!("error building vote: {}", e);
+            process::exit(1);
+        });
+
+        let unsigned_tx_json =
+            serde_json::to_string_pretty(&unsigned_tx).expect("JSON serialization error");
+
+        match &self.output {
+            Some(path) => {
+                fs::write(path, unsigned_tx_json).unwrap_or_else(|e| {
+                    status_err!("couldn't write {}: {}", path.display(), e);
+                    process::exit(1);
+                });
+
+                status_ok!("Wrote", "unsigned vote transaction to {}", path.display());
+            }
+            None => println!("{}", unsigned_tx_json),
+        }
+    }
+}
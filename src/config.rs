@@ -8,9 +8,14 @@ pub mod https;
 pub mod listen;
 pub mod network;
 pub mod source;
+pub mod stream;
 
 pub use self::{
-    https::HttpsConfig, listen::ListenConfig, network::NetworkConfig, source::SourceConfig,
+    https::HttpsConfig,
+    listen::ListenConfig,
+    network::NetworkConfig,
+    source::{JsonSourceConfig, SourceConfig},
+    stream::StreamConfig,
 };
 
 use serde::{Deserialize, Serialize};
@@ -34,4 +39,8 @@ pub struct DelphiConfig {
     /// Source configuration
     #[serde(default)]
     pub source: SourceConfig,
+
+    /// Streaming market data configuration
+    #[serde(default)]
+    pub stream: StreamConfig,
 }
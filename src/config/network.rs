@@ -1,15 +1,73 @@
 //! Network configuration
 
-use crate::networks::terra::Denom;
-use serde::{Deserialize, Serialize};
+use crate::{networks::terra::Denom, prelude::*, Error, ErrorKind};
+use rust_decimal::Decimal;
+use serde::{
+    de::{self, value::MapAccessDeserializer, value::SeqAccessDeserializer},
+    Deserialize, Deserializer, Serialize,
+};
+use std::{fmt, path::PathBuf, str::FromStr};
 use stdtx::amino::types::{Coin, StdFee};
 
 /// Network/chain specific configuration
 #[derive(Clone, Debug, Default, Deserialize, Serialize)]
 #[serde(deny_unknown_fields)]
 pub struct NetworkConfig {
-    /// Terra configuration
-    pub terra: Option<TerraConfig>,
+    /// Terra configuration: either a single `[network.terra]` table, or one
+    /// `[[network.terra]]` table per chain (e.g. mainnet and testnet)
+    #[serde(default, deserialize_with = "one_or_many")]
+    pub terra: Vec<TerraConfig>,
+}
+
+impl NetworkConfig {
+    /// Get the Terra config for the given chain ID, or if none is given, the
+    /// only Terra network configured
+    pub fn terra(&self, chain_id: Option<&str>) -> Result<&TerraConfig, Error> {
+        match chain_id {
+            Some(chain_id) => self
+                .terra
+                .iter()
+                .find(|terra_config| terra_config.chain_id == chain_id)
+                .ok_or_else(|| {
+                    format_err!(ErrorKind::Config, "no [network.terra] for {}", chain_id).into()
+                }),
+            None => match self.terra.as_slice() {
+                [terra_config] => Ok(terra_config),
+                [] => fail!(ErrorKind::Config, "missing [network.terra] config"),
+                _ => fail!(
+                    ErrorKind::Config,
+                    "multiple [network.terra] configs; select one by chain ID"
+                ),
+            },
+        }
+    }
+}
+
+/// Deserialize either a single table or an array of tables
+fn one_or_many<'de, D, T>(deserializer: D) -> Result<Vec<T>, D::Error>
+where
+    D: Deserializer<'de>,
+    T: Deserialize<'de>,
+{
+    struct OneOrMany<T>(std::marker::PhantomData<T>);
+
+    impl<'de, T: Deserialize<'de>> de::Visitor<'de> for OneOrMany<T> {
+        type Value = Vec<T>;
+
+        fn expecting(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            f.write_str("a table or an array of tables")
+        }
+
+        fn visit_map<A: de::MapAccess<'de>>(self, map: A) -> Result<Vec<T>, A::Error> {
+            T::deserialize(MapAccessDeserializer::new(map)).map(|value| vec![value])
+        }
+
+        fn visit_seq<A: de::SeqAccess<'de>>(self, seq: A) -> Result<Vec<T>, A::Error> {
+            Vec::deserialize(SeqAccessDeserializer::new(seq))
+        }
+    }
+
+    deserializer.deserialize_any(OneOrMany(std::marker::PhantomData))
 }
 
 /// Terra configuration
@@ -25,12 +83,358 @@ pub struct TerraConfig {
     /// Validator address (Bech32)
     pub validator: String,
 
+    /// Additional feeder/validator identities voting on this network, each
+    /// selected by the context of requests from Tendermint KMS (requests
+    /// without a context use the `feeder`/`validator` above)
+    #[serde(default)]
+    pub voters: Vec<VoterConfig>,
+
     /// Oracle transaction fee
     #[serde(default)]
     pub fee: TerraOracleFee,
 
+    /// Estimate the gas and fee for each transaction (instead of using the
+    /// fixed `fee`)
+    pub fee_estimation: Option<FeeEstimationConfig>,
+
+    /// Account paying the fees, if not the feeder (Bech32). The fee payer
+    /// must also sign transactions.
+    pub fee_payer: Option<String>,
+
+    /// Account which granted the feeder a fee allowance (x/feegrant) to pay
+    /// fees from (Bech32)
+    pub fee_granter: Option<String>,
+
+    /// Wrap oracle messages in an authz `MsgExec`, executing them on behalf
+    /// of the validator's account (which must have granted the feeder
+    /// authorization to send them)
+    #[serde(default)]
+    pub authz: bool,
+
+    /// Transaction format: legacy amino JSON (`"amino"`, the default) or
+    /// protobuf (`"protobuf"`, for post-Stargate chains)
+    #[serde(default)]
+    pub tx_format: TxFormat,
+
+    /// Oracle messages to vote with: aggregate (`"aggregate"`, the default)
+    /// or legacy per-denom prevotes/votes (`"per_denom"`)
+    #[serde(default)]
+    pub oracle_mode: OracleMode,
+
     /// Timeout for an oracle vote in seconds (default 10)
     pub timeout_secs: Option<u64>,
+
+    /// Number of blocks in an oracle vote period (default 5)
+    pub vote_period: Option<u64>,
+
+    /// File in which to persist the unrevealed vote across restarts
+    pub state_file: Option<PathBuf>,
+
+    /// Terra LCD (light client daemon) REST API
+    pub lcd: Option<TerraLcdConfig>,
+
+    /// Guard against voting rates far from the on-chain rates (requires `lcd`)
+    pub deviation_guard: Option<DeviationGuardConfig>,
+
+    /// Limit how far rates may move between vote periods
+    pub circuit_breaker: Option<CircuitBreakerConfig>,
+
+    /// Check implied cross rates between denoms against direct FX quotes
+    pub triangulation: Option<TriangulationConfig>,
+
+    /// Refuse to vote while the node is catching up or its clock is skewed
+    #[serde(default)]
+    pub sync_guard: SyncGuardConfig,
+
+    /// Sign and broadcast oracle transactions directly (instead of serving
+    /// them to Tendermint KMS)
+    pub signer: Option<SignerConfig>,
+}
+
+impl TerraConfig {
+    /// Get all feeder/validator identities: the top-level `feeder` and
+    /// `validator` (with an empty context and the top-level `state_file`),
+    /// followed by the additional `voters`
+    pub fn voter_configs(&self) -> Vec<VoterConfig> {
+        let default_voter = VoterConfig {
+            context: String::new(),
+            feeder: self.feeder.clone(),
+            validator: self.validator.clone(),
+            state_file: self.state_file.clone(),
+        };
+
+        std::iter::once(default_voter)
+            .chain(self.voters.iter().cloned())
+            .collect()
+    }
+
+    /// Get the feeder/validator identity for the given request context
+    pub fn voter_config(&self, context: &str) -> Result<VoterConfig, Error> {
+        self.voter_configs()
+            .into_iter()
+            .find(|voter_config| voter_config.context == context)
+            .ok_or_else(|| {
+                format_err!(ErrorKind::Config, "no voter for context: {:?}", context).into()
+            })
+    }
+}
+
+/// Feeder/validator identity
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct VoterConfig {
+    /// Request context selecting this identity
+    pub context: String,
+
+    /// Feeder address (Bech32)
+    pub feeder: String,
+
+    /// Validator address (Bech32)
+    pub validator: String,
+
+    /// File in which to persist this identity's unrevealed vote
+    pub state_file: Option<PathBuf>,
+}
+
+/// Transaction format
+#[derive(Copy, Clone, Debug, Default, Deserialize, Eq, PartialEq, Serialize)]
+pub enum TxFormat {
+    /// Legacy amino JSON `StdTx`
+    #[default]
+    #[serde(rename = "amino")]
+    Amino,
+
+    /// Protobuf `TxBody`/`AuthInfo` in a `TxSigningRequest` (`SIGN_MODE_DIRECT`)
+    #[serde(rename = "protobuf")]
+    Protobuf,
+}
+
+impl FromStr for TxFormat {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Error> {
+        match s {
+            "amino" => Ok(TxFormat::Amino),
+            "protobuf" => Ok(TxFormat::Protobuf),
+            _ => fail!(ErrorKind::Parse, "unknown transaction format: {}", s),
+        }
+    }
+}
+
+/// Oracle voting mode
+#[derive(Copy, Clone, Debug, Default, Deserialize, Eq, PartialEq, Serialize)]
+pub enum OracleMode {
+    /// `MsgAggregateExchangeRatePrevote`/`MsgAggregateExchangeRateVote`
+    #[default]
+    #[serde(rename = "aggregate")]
+    Aggregate,
+
+    /// Legacy `MsgExchangeRatePrevote`/`MsgExchangeRateVote` for each denom,
+    /// for chains which still use the non-aggregate oracle
+    #[serde(rename = "per_denom")]
+    PerDenom,
+}
+
+/// Fee estimation configuration
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct FeeEstimationConfig {
+    /// How to estimate gas (default `"model"`)
+    #[serde(default)]
+    pub gas: GasEstimation,
+
+    /// Gas model (used by `"model"`, and as a fallback by `"simulate"`)
+    #[serde(default)]
+    pub gas_model: GasModelConfig,
+
+    /// Multiplier applied to the estimated gas (default 1.4)
+    #[serde(default = "default_gas_adjustment")]
+    pub gas_adjustment: Decimal,
+
+    /// Gas price, in any denom the chain accepts fees in
+    pub gas_price: GasPriceConfig,
+
+    /// Maximum fee amount (in the gas price's denom)
+    pub max_fee: Option<u64>,
+}
+
+/// Default gas adjustment: 1.4
+fn default_gas_adjustment() -> Decimal {
+    Decimal::new(14, 1)
+}
+
+/// Gas estimation method
+#[derive(Copy, Clone, Debug, Default, Deserialize, Eq, PartialEq, Serialize)]
+pub enum GasEstimation {
+    /// Simulate the transaction via the LCD (requires `lcd`)
+    #[serde(rename = "simulate")]
+    Simulate,
+
+    /// Estimate from the number of messages and denoms
+    #[default]
+    #[serde(rename = "model")]
+    Model,
+}
+
+/// Gas model: `base + per_msg × messages + per_denom × voted denoms`
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct GasModelConfig {
+    /// Gas per transaction (default 50000)
+    pub base: u64,
+
+    /// Gas per message (default 25000)
+    pub per_msg: u64,
+
+    /// Gas per denom in each vote (default 2000)
+    pub per_denom: u64,
+}
+
+impl Default for GasModelConfig {
+    fn default() -> Self {
+        Self {
+            base: 50_000,
+            per_msg: 25_000,
+            per_denom: 2_000,
+        }
+    }
+}
+
+/// Gas price
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct GasPriceConfig {
+    /// Fee denom, e.g. `uusd`
+    pub denom: String,
+
+    /// Price per unit of gas, e.g. `0.15`
+    pub amount: Decimal,
+}
+
+/// Node sync guard configuration
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct SyncGuardConfig {
+    /// Vote even if the node reports it is catching up (default false)
+    #[serde(default)]
+    pub allow_catching_up: bool,
+
+    /// Maximum difference in seconds between the latest block time and the
+    /// local clock (default 60)
+    #[serde(default = "default_max_time_skew_secs")]
+    pub max_time_skew_secs: u64,
+}
+
+impl Default for SyncGuardConfig {
+    fn default() -> Self {
+        Self {
+            allow_catching_up: false,
+            max_time_skew_secs: default_max_time_skew_secs(),
+        }
+    }
+}
+
+/// Default maximum block time skew: 60 seconds
+fn default_max_time_skew_secs() -> u64 {
+    60
+}
+
+/// Standalone signer configuration
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct SignerConfig {
+    /// File containing the feeder's hex-encoded secp256k1 private key
+    pub key_file: PathBuf,
+
+    /// Tendermint RPC URL, e.g. `http://127.0.0.1:26657`
+    pub rpc: String,
+
+    /// Interval in milliseconds at which to poll the RPC for new blocks
+    /// (default 1000)
+    #[serde(default = "default_poll_interval_ms")]
+    pub poll_interval_ms: u64,
+}
+
+/// Default block height polling interval: one second
+fn default_poll_interval_ms() -> u64 {
+    1000
+}
+
+/// Terra LCD configuration
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct TerraLcdConfig {
+    /// Base URL of the LCD, e.g. `https://lcd.terra.dev`
+    pub url: String,
+
+    /// Interval in seconds at which to re-sync the oracle params (denom
+    /// whitelist, vote period and reward band) from the LCD (default 3600)
+    #[serde(default = "default_params_refresh_secs")]
+    pub params_refresh_secs: u64,
+}
+
+/// Default oracle params refresh interval: one hour
+fn default_params_refresh_secs() -> u64 {
+    3600
+}
+
+/// Deviation guard configuration
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct DeviationGuardConfig {
+    /// Maximum relative deviation from the on-chain rate, e.g. `0.1` for 10%
+    pub max_deviation: Decimal,
+
+    /// Action to take when a rate exceeds the maximum deviation
+    pub action: DeviationAction,
+}
+
+/// Action to take when a computed rate deviates too far from the on-chain rate
+#[derive(Copy, Clone, Debug, Deserialize, Eq, PartialEq, Serialize)]
+pub enum DeviationAction {
+    /// Abstain from voting on the denom
+    #[serde(rename = "abstain")]
+    Abstain,
+
+    /// Clamp the rate to the maximum deviation from the on-chain rate
+    #[serde(rename = "clamp")]
+    Clamp,
+
+    /// Vote the rate only if a second independent source confirms it,
+    /// otherwise abstain
+    #[serde(rename = "confirm")]
+    Confirm,
+}
+
+/// Circuit breaker configuration
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct CircuitBreakerConfig {
+    /// Maximum relative change from the last voted rate, e.g. `0.2` for 20%
+    pub max_change: Decimal,
+}
+
+/// Triangulation consistency check configuration
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct TriangulationConfig {
+    /// Maximum relative difference between implied and direct FX rates
+    pub tolerance: Decimal,
+
+    /// Action to take on the legs most likely to be wrong
+    pub action: TriangulationAction,
+}
+
+/// Action to take when implied cross rates are inconsistent
+#[derive(Copy, Clone, Debug, Deserialize, Eq, PartialEq, Serialize)]
+pub enum TriangulationAction {
+    /// Log the inconsistency but vote anyway
+    #[serde(rename = "flag")]
+    Flag,
+
+    /// Abstain from voting on the denoms most likely to be wrong
+    #[serde(rename = "abstain")]
+    Abstain,
 }
 
 /// Terra oracle fee configuration
@@ -67,3 +471,71 @@ impl From<&TerraOracleFee> for StdFee {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::NetworkConfig;
+
+    fn terra(chain_id: &str) -> String {
+        format!(
+            "chain_id = \"{}\"\nfeeder = \"terra1...\"\nvalidator = \"terravaloper1...\"\n",
+            chain_id
+        )
+    }
+
+    #[test]
+    fn single_network() {
+        let config: NetworkConfig =
+            toml::from_str(&format!("[terra]\n{}", terra("columbus-5"))).unwrap();
+
+        assert_eq!(config.terra.len(), 1);
+        assert_eq!(config.terra(None).unwrap().chain_id, "columbus-5");
+    }
+
+    #[test]
+    fn multiple_networks() {
+        let config: NetworkConfig = toml::from_str(&format!(
+            "[[terra]]\n{}\n[[terra]]\n{}",
+            terra("columbus-5"),
+            terra("bombay-12")
+        ))
+        .unwrap();
+
+        assert_eq!(config.terra.len(), 2);
+        assert!(config.terra(None).is_err());
+        assert_eq!(
+            config.terra(Some("bombay-12")).unwrap().chain_id,
+            "bombay-12"
+        );
+        assert!(config.terra(Some("columbus-4")).is_err());
+    }
+
+    #[test]
+    fn multiple_voters() {
+        let config: NetworkConfig = toml::from_str(&format!(
+            "[terra]\n{}voters = [{{ context = \"validator-2\", feeder = \"terra1..2\", validator = \"terravaloper1..2\" }}]\n",
+            terra("columbus-5")
+        ))
+        .unwrap();
+
+        let terra_config = config.terra(None).unwrap();
+        assert_eq!(terra_config.voter_configs().len(), 2);
+        assert_eq!(terra_config.voter_config("").unwrap().feeder, "terra1...");
+        assert_eq!(
+            terra_config.voter_config("validator-2").unwrap().feeder,
+            "terra1..2"
+        );
+        assert!(terra_config.voter_config("validator-3").is_err());
+    }
+
+    #[test]
+    fn rejects_unknown_fields() {
+        let err = toml::from_str::<NetworkConfig>(&format!(
+            "[terra]\n{}bogus = true\n",
+            terra("columbus-5")
+        ))
+        .unwrap_err();
+
+        assert!(err.to_string().contains("bogus"));
+    }
+}
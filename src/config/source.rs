@@ -1,5 +1,7 @@
 //! Source configuration
 
+use crate::{Map, TradingPair};
+use rust_decimal::Decimal;
 use serde::{Deserialize, Serialize};
 
 /// Source Configuration
@@ -10,6 +12,13 @@ pub struct SourceConfig {
     pub alphavantage: Option<AlphavantageConfig>,
     /// Currencylayer
     pub currencylayer: Option<CurrencylayerConfig>,
+
+    /// Kraken
+    pub kraken: Option<KrakenConfig>,
+
+    /// Generic JSON-over-HTTP sources, keyed by name
+    #[serde(default)]
+    pub json: Map<String, JsonSourceConfig>,
 }
 
 /// AlphaVantage Configuration
@@ -27,3 +36,147 @@ pub struct CurrencylayerConfig {
     /// API key
     pub access_key: String,
 }
+
+/// Kraken Configuration
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct KrakenConfig {
+    /// OHLC candle interval in minutes (one of 1, 5, 15, 30, 60, 240, 1440,
+    /// 10080 or 21600)
+    #[serde(default = "default_kraken_ohlc_interval")]
+    pub ohlc_interval: u32,
+
+    /// Number of most recent committed OHLC candles to compute the windowed
+    /// VWAP over (the in-progress candle is excluded)
+    #[serde(default = "default_kraken_ohlc_window")]
+    pub ohlc_window: usize,
+
+    /// Weight (between 0 and 1) given to the windowed VWAP in LUNA/USD, which
+    /// is otherwise priced from Binance alone (default 0, i.e. unused)
+    #[serde(default)]
+    pub luna_usd_weight: Decimal,
+}
+
+impl Default for KrakenConfig {
+    fn default() -> Self {
+        Self {
+            ohlc_interval: default_kraken_ohlc_interval(),
+            ohlc_window: default_kraken_ohlc_window(),
+            luna_usd_weight: Decimal::default(),
+        }
+    }
+}
+
+/// Default Kraken OHLC interval: 15 minutes
+fn default_kraken_ohlc_interval() -> u32 {
+    15
+}
+
+/// Default Kraken OHLC window: 4 candles (i.e. one hour at 15 minutes)
+fn default_kraken_ohlc_window() -> usize {
+    4
+}
+
+/// Generic JSON-over-HTTP source configuration.
+///
+/// Prices for the listed `pairs` (e.g. `LUNA/KRW`) are averaged with the
+/// built-in sources when computing exchange rates for the corresponding
+/// denom (e.g. `ukrw`).
+///
+/// The `path` and `query` values are templates which may contain the
+/// `{base}` and `{quote}` placeholders (or `{base_lower}`/`{quote_lower}`),
+/// which are replaced with the currencies of the requested [`TradingPair`].
+///
+/// Fields are located in the response using JSON pointers (RFC 6901),
+/// e.g. `/data/0/price`. Either `price` or both `bid` and `ask` must be set.
+/// If a `timestamp` is located, quotes older than `max_age_secs` are ignored.
+///
+/// [`TradingPair`]: crate::TradingPair
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct JsonSourceConfig {
+    /// Hostname of the API (or a base URL, e.g. `http://127.0.0.1:8080`)
+    pub host: String,
+
+    /// Trading pairs to include in exchange rates
+    #[serde(default)]
+    pub pairs: Vec<TradingPair>,
+
+    /// Request path template
+    pub path: String,
+
+    /// Query parameter templates
+    #[serde(default)]
+    pub query: Map<String, String>,
+
+    /// API key to send as an HTTP header
+    pub api_key: Option<ApiKeyConfig>,
+
+    /// JSON pointer to the price
+    pub price: Option<String>,
+
+    /// JSON pointer to the best bid
+    pub bid: Option<String>,
+
+    /// JSON pointer to the best ask
+    pub ask: Option<String>,
+
+    /// JSON pointer to the quote's timestamp
+    pub timestamp: Option<String>,
+
+    /// Unit of numeric timestamps
+    #[serde(default)]
+    pub timestamp_unit: TimestampUnit,
+
+    /// Maximum age of a timestamped quote in seconds
+    #[serde(default = "default_json_max_age_secs")]
+    pub max_age_secs: u64,
+}
+
+impl Default for JsonSourceConfig {
+    fn default() -> Self {
+        Self {
+            host: String::default(),
+            pairs: Vec::default(),
+            path: String::default(),
+            query: Map::default(),
+            api_key: None,
+            price: None,
+            bid: None,
+            ask: None,
+            timestamp: None,
+            timestamp_unit: TimestampUnit::default(),
+            max_age_secs: default_json_max_age_secs(),
+        }
+    }
+}
+
+/// Default maximum age of JSON source quotes: 60 seconds
+fn default_json_max_age_secs() -> u64 {
+    60
+}
+
+/// API key sent as an HTTP request header
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct ApiKeyConfig {
+    /// Header name, e.g. `X-API-Key`
+    pub header: String,
+
+    /// API key
+    pub value: String,
+}
+
+/// Unit of numeric timestamps (which may be fractional). String timestamps
+/// which aren't numeric are parsed as RFC 3339.
+#[derive(Copy, Clone, Debug, Default, Deserialize, Eq, PartialEq, Serialize)]
+pub enum TimestampUnit {
+    /// Seconds since the Unix epoch
+    #[default]
+    #[serde(rename = "seconds")]
+    Seconds,
+
+    /// Milliseconds since the Unix epoch
+    #[serde(rename = "milliseconds")]
+    Milliseconds,
+}
@@ -0,0 +1,127 @@
+//! Streaming market data configuration
+
+use serde::{Deserialize, Serialize};
+use std::time::Duration;
+
+/// Default maximum age of streamed market data in milliseconds
+pub const DEFAULT_MAX_AGE_MS: u64 = 5_000;
+
+/// Default initial delay before reconnecting a stream in milliseconds
+pub const DEFAULT_RECONNECT_MIN_MS: u64 = 500;
+
+/// Default maximum delay before reconnecting a stream in milliseconds
+pub const DEFAULT_RECONNECT_MAX_MS: u64 = 30_000;
+
+/// Streaming market data configuration
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct StreamConfig {
+    /// Maximum age of streamed data before sources fall back to REST
+    #[serde(default = "default_max_age_ms")]
+    pub max_age_ms: u64,
+
+    /// Initial delay before reconnecting (doubles on each failed attempt)
+    #[serde(default = "default_reconnect_min_ms")]
+    pub reconnect_min_ms: u64,
+
+    /// Maximum delay before reconnecting
+    #[serde(default = "default_reconnect_max_ms")]
+    pub reconnect_max_ms: u64,
+
+    /// Binance diff-depth orderbooks
+    pub binance: Option<BinanceStreamConfig>,
+
+    /// Upbit tickers
+    pub upbit: Option<UpbitStreamConfig>,
+
+    /// Bithumb tickers
+    pub bithumb: Option<BithumbStreamConfig>,
+}
+
+impl StreamConfig {
+    /// Maximum age of streamed data
+    pub fn max_age(&self) -> Duration {
+        Duration::from_millis(self.max_age_ms)
+    }
+}
+
+impl Default for StreamConfig {
+    fn default() -> Self {
+        Self {
+            max_age_ms: DEFAULT_MAX_AGE_MS,
+            reconnect_min_ms: DEFAULT_RECONNECT_MIN_MS,
+            reconnect_max_ms: DEFAULT_RECONNECT_MAX_MS,
+            binance: None,
+            upbit: None,
+            bithumb: None,
+        }
+    }
+}
+
+/// Binance diff-depth stream configuration
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct BinanceStreamConfig {
+    /// Symbols to maintain orderbooks for, e.g. `LUNAUSDT`
+    pub symbols: Vec<String>,
+
+    /// WebSocket base URL
+    #[serde(default = "default_binance_ws_url")]
+    pub ws_url: String,
+
+    /// REST base URL used to fetch depth snapshots
+    #[serde(default = "default_binance_rest_url")]
+    pub rest_url: String,
+}
+
+/// Upbit ticker stream configuration
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct UpbitStreamConfig {
+    /// Market codes to subscribe to, e.g. `KRW-LUNA`
+    pub markets: Vec<String>,
+
+    /// WebSocket URL
+    #[serde(default = "default_upbit_ws_url")]
+    pub ws_url: String,
+}
+
+/// Bithumb ticker stream configuration
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct BithumbStreamConfig {
+    /// Symbols to subscribe to, e.g. `LUNA_KRW`
+    pub symbols: Vec<String>,
+
+    /// WebSocket URL
+    #[serde(default = "default_bithumb_ws_url")]
+    pub ws_url: String,
+}
+
+fn default_max_age_ms() -> u64 {
+    DEFAULT_MAX_AGE_MS
+}
+
+fn default_reconnect_min_ms() -> u64 {
+    DEFAULT_RECONNECT_MIN_MS
+}
+
+fn default_reconnect_max_ms() -> u64 {
+    DEFAULT_RECONNECT_MAX_MS
+}
+
+fn default_binance_ws_url() -> String {
+    "wss://stream.binance.com:9443".to_owned()
+}
+
+fn default_binance_rest_url() -> String {
+    "https://api.binance.com".to_owned()
+}
+
+fn default_upbit_ws_url() -> String {
+    "wss://api.upbit.com/websocket/v1".to_owned()
+}
+
+fn default_bithumb_ws_url() -> String {
+    "wss://pubwss.bithumb.com/pub/ws".to_owned()
+}
@@ -174,6 +174,12 @@ impl FromStr for Currency {
 
 impl From<Denom> for Currency {
     fn from(denom: Denom) -> Currency {
+        Currency::from(&denom)
+    }
+}
+
+impl From<&Denom> for Currency {
+    fn from(denom: &Denom) -> Currency {
         match denom {
             Denom::Ueur => Currency::Eur,
             Denom::Ucny => Currency::Cny,
@@ -194,6 +200,7 @@ impl From<Denom> for Currency {
             Denom::Udkk => Currency::Dkk,
             Denom::Uidr => Currency::Idr,
             Denom::Uphp => Currency::Php,
+            Denom::Other(other) => other.currency(),
         }
     }
 }
@@ -212,40 +219,3 @@ impl Serialize for Currency {
         self.to_string().serialize(serializer)
     }
 }
-
-impl Currency {
-    ///Long name for IMF csv
-    pub fn imf_long_name(&self) -> String {
-        match self {
-            Currency::Krw => "Korean won".to_string(),
-            Currency::Usd => "U.S. dollar".to_string(),
-            Currency::Luna => "N/A".to_string(),
-            Currency::Sdr => "XDR".to_string(),
-            Currency::Other(other) => other.to_string(),
-            Currency::Atom => "N/A".to_string(),
-            Currency::Bnb => "N/A".to_string(),
-            Currency::Bkrw => "N/A".to_string(),
-            Currency::Btc => "N/A".to_string(),
-            Currency::Busd => "N/A".to_string(),
-            Currency::Eth => "N/A".to_string(),
-            Currency::Eur => "EUR".to_string(),
-            Currency::Gbp => "N/A".to_string(),
-            Currency::Usdc => "N/A".to_string(),
-            Currency::Usdt => "N/A".to_string(),
-            Currency::Mnt => "N/A".to_string(),
-            Currency::Cny => "N/A".to_string(),
-            Currency::Jpy => "N/A".to_string(),
-            Currency::Inr => "N/A".to_string(),
-            Currency::Cad => "N/A".to_string(),
-            Currency::Chf => "N/A".to_string(),
-            Currency::Hkd => "N/A".to_string(),
-            Currency::Aud => "N/A".to_string(),
-            Currency::Sgd => "N/A".to_string(),
-            Currency::Thb => "N/A".to_string(),
-            Currency::Sek => "N/A".to_string(),
-            Currency::Dkk => "N/A".to_string(),
-            Currency::Idr => "N/A".to_string(),
-            Currency::Php => "N/A".to_string(),
-        }
-    }
-}
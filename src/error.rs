@@ -103,6 +103,12 @@ impl From<rust_decimal::Error> for Error {
     }
 }
 
+impl From<tokio_tungstenite::tungstenite::Error> for Error {
+    fn from(err: tokio_tungstenite::tungstenite::Error) -> Self {
+        ErrorKind::Http.context(err).into()
+    }
+}
+
 impl From<stdtx::Error> for Error {
     fn from(err: stdtx::Error) -> Error {
         Context::new(ErrorKind::Parse, Some(err.into())).into()
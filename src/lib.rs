@@ -14,6 +14,7 @@ pub mod price;
 pub mod protos;
 pub mod router;
 pub mod sources;
+pub mod streaming;
 pub mod trading_pair;
 
 pub use self::{
@@ -1,18 +1,42 @@
 //! Terra stablecoin project schema
 //! <https://terra.money/>
 
+pub mod circuit_breaker;
 pub mod denom;
+pub mod deviation;
+pub mod fee;
+pub mod lcd;
 pub mod msg;
 pub mod oracle;
+pub mod pending_vote;
 pub mod protos;
+pub mod sequence;
+pub mod signer;
+pub mod sync_guard;
+pub mod triangulation;
+pub mod tx;
+pub mod tx_outcome;
+pub mod voter;
 
 pub use self::{denom::Denom, oracle::ExchangeRateOracle};
 
+use crate::{prelude::*, Error, ErrorKind};
 use once_cell::sync::Lazy;
+use stdtx::Address;
 
 /// Memo to include in transactions
 pub const MEMO: &str = concat!("delphi/", env!("CARGO_PKG_VERSION"));
 
+/// Default number of blocks in an oracle vote period
+pub const DEFAULT_VOTE_PERIOD: u64 = 5;
+
 /// StdTx schema as parsed from `schema.toml`
 static SCHEMA: Lazy<stdtx::amino::Schema> =
     Lazy::new(|| include_str!("terra/schema.toml").parse().unwrap());
+
+/// Parse a Bech32 address
+pub fn parse_address(address: &str) -> Result<Address, Error> {
+    Address::from_bech32(address)
+        .map(|(_, address)| address)
+        .map_err(|e| format_err!(ErrorKind::Parse, "invalid address {}: {}", address, e).into())
+}
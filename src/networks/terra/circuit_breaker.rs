@@ -0,0 +1,197 @@
+//! Circuit breaker
+//!
+//! Limits how far each denom's rate may move from the last voted rate in a
+//! single vote period. A rate which moves further is re-fetched from all
+//! sources to rule out a glitch; if it is still anomalous the oracle
+//! abstains from voting on the denom and raises an alert.
+
+use super::{
+    denom::LunaUsd,
+    deviation::{abstain, relative_deviation, to_decimal},
+    msg::ExchangeRates,
+};
+use crate::{config::network::CircuitBreakerConfig, prelude::*, sources::Sources};
+use rust_decimal::Decimal;
+use std::time::Duration;
+use tokio::time::timeout;
+
+/// Circuit breaker for per-period rate moves
+#[derive(Clone, Debug)]
+pub struct CircuitBreaker {
+    /// Maximum relative change from the last voted rate
+    max_change: Decimal,
+}
+
+impl CircuitBreaker {
+    /// Create a new circuit breaker from config
+    pub fn new(config: &CircuitBreakerConfig) -> Self {
+        Self {
+            max_change: config.max_change,
+        }
+    }
+
+    /// Has a rate moved too far from the last voted rate?
+    pub fn is_tripped(&self, rate: Decimal, last: Decimal) -> bool {
+        let zero = Decimal::from(0u8);
+
+        // Abstain votes carry no price information
+        rate > zero && last > zero && relative_deviation(rate, last) > self.max_change
+    }
+
+    /// Apply the circuit breaker to a set of exchange rates
+    pub async fn apply(
+        &self,
+        exchange_rates: &ExchangeRates,
+        last_voted: &ExchangeRates,
+        sources: &Sources,
+        timeout_duration: Duration,
+    ) -> ExchangeRates {
+        let mut checked = ExchangeRates::new();
+
+        // Re-fetched at most once, however many denoms priced from it trip
+        let luna_usd = LunaUsd::new();
+
+        for (denom, &rate) in exchange_rates.iter() {
+            let last = match last_voted.get(denom) {
+                Some(last) => to_decimal(last),
+                None => {
+                    checked.add(denom.clone(), rate).expect("duplicate denom");
+                    continue;
+                }
+            };
+
+            let computed = to_decimal(rate);
+
+            if !self.is_tripped(computed, last) {
+                checked.add(denom.clone(), rate).expect("duplicate denom");
+                continue;
+            }
+
+            warn!(
+                "{}: computed {} moved more than {} from last vote {}; re-fetching",
+                denom, computed, self.max_change, last
+            );
+
+            let refetched = timeout(
+                timeout_duration,
+                denom.get_exchange_rate(sources, &luna_usd),
+            )
+            .await;
+
+            let checked_rate = match refetched {
+                Ok(Ok(refetched)) if !self.is_tripped(to_decimal(refetched), last) => {
+                    info!(
+                        "{}: re-fetched {} is within {} of last vote {}; voting",
+                        denom, refetched, self.max_change, last
+                    );
+                    refetched
+                }
+                Ok(Ok(refetched)) => {
+                    error!(
+                        "ALERT: {} circuit breaker tripped: computed {}, re-fetched {}, last vote {}; abstaining",
+                        denom, computed, refetched, last
+                    );
+                    abstain()
+                }
+                Ok(Err(e)) => {
+                    error!(
+                        "ALERT: {} circuit breaker tripped: computed {}, last vote {}, re-fetch failed: {}; abstaining",
+                        denom, computed, last, e
+                    );
+                    abstain()
+                }
+                Err(_) => {
+                    error!(
+                        "ALERT: {} circuit breaker tripped: computed {}, last vote {}, re-fetch timed out; abstaining",
+                        denom, computed, last
+                    );
+                    abstain()
+                }
+            };
+
+            checked
+                .add(denom.clone(), checked_rate)
+                .expect("duplicate denom");
+        }
+
+        checked
+    }
+}
+
+/// Merge newly voted rates into the last voted rates. Abstained denoms keep
+/// their previous rate so the next period is still compared to a real price.
+pub fn merge_last_voted(last_voted: &ExchangeRates, voted: &ExchangeRates) -> ExchangeRates {
+    let zero = Decimal::from(0u8);
+    let mut merged = ExchangeRates::new();
+
+    for (denom, &rate) in voted.iter() {
+        if to_decimal(rate) > zero {
+            merged.add(denom.clone(), rate).expect("duplicate denom");
+        }
+    }
+
+    for (denom, &rate) in last_voted.iter() {
+        if merged.get(denom).is_none() {
+            merged.add(denom.clone(), rate).expect("duplicate denom");
+        }
+    }
+
+    merged
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{merge_last_voted, CircuitBreaker};
+    use crate::{
+        config::network::CircuitBreakerConfig,
+        networks::terra::{deviation::abstain, msg::ExchangeRates, Denom},
+    };
+    use rust_decimal::Decimal;
+
+    fn dec(s: &str) -> Decimal {
+        s.parse().unwrap()
+    }
+
+    #[test]
+    fn trips_on_large_moves() {
+        let breaker = CircuitBreaker::new(&CircuitBreakerConfig {
+            max_change: dec("0.2"),
+        });
+
+        assert!(!breaker.is_tripped(dec("120"), dec("100")));
+        assert!(!breaker.is_tripped(dec("80"), dec("100")));
+        assert!(breaker.is_tripped(dec("150"), dec("100")));
+        assert!(breaker.is_tripped(dec("50"), dec("100")));
+
+        // Abstain votes never trip the breaker
+        assert!(!breaker.is_tripped(dec("0"), dec("100")));
+        assert!(!breaker.is_tripped(dec("150"), dec("0")));
+    }
+
+    #[test]
+    fn merge_keeps_rates_for_abstained_denoms() {
+        let last_voted = ExchangeRates::from_exchange_rates(
+            [
+                (Denom::Ukrw, "1500".parse().unwrap()),
+                (Denom::Uusd, "1".parse().unwrap()),
+            ]
+            .iter(),
+        )
+        .unwrap();
+
+        let voted = ExchangeRates::from_exchange_rates(
+            [
+                (Denom::Ukrw, abstain()),
+                (Denom::Uusd, "2".parse().unwrap()),
+                (Denom::Umnt, "3000".parse().unwrap()),
+            ]
+            .iter(),
+        )
+        .unwrap();
+
+        let merged = merge_last_voted(&last_voted, &voted);
+        assert_eq!(merged.get(&Denom::Ukrw).unwrap(), "1500".parse().unwrap());
+        assert_eq!(merged.get(&Denom::Uusd).unwrap(), "2".parse().unwrap());
+        assert_eq!(merged.get(&Denom::Umnt).unwrap(), "3000".parse().unwrap());
+    }
+}
@@ -4,8 +4,9 @@ use crate::{
     currency::Currency,
     error::{Error, ErrorKind},
     prelude::*,
-    sources::Sources,
+    sources::{midpoint, Sources},
     trading_pair::TradingPair,
+    Price,
 };
 use rust_decimal::Decimal;
 use serde::{de, ser, Deserialize, Serialize};
@@ -14,10 +15,10 @@ use std::{
     fmt::{self, Display},
     str::FromStr,
 };
-use tokio::try_join;
+use tokio::{join, sync::OnceCell, try_join};
 
 /// Denomination
-#[derive(Copy, Clone, Debug, Eq, PartialEq, PartialOrd, Ord)]
+#[derive(Clone, Debug, Eq, PartialEq, PartialOrd, Ord)]
 pub enum Denom {
     /// Korean Wan
     Ukrw,
@@ -75,10 +76,33 @@ pub enum Denom {
 
     ///Philippine peso
     Uphp,
+
+    /// Other (open-ended), e.g. denoms whitelisted on-chain after this
+    /// release. Priced as LUNA/USD × USD/currency.
+    Other(OtherDenom),
+}
+
+/// Micro-unit denom for a 3-letter currency code without dedicated support,
+/// e.g. `utwd`. Only constructed by parsing, which validates it.
+#[derive(Clone, Debug, Eq, PartialEq, PartialOrd, Ord)]
+pub struct OtherDenom(String);
+
+impl OtherDenom {
+    /// Get the denom's code
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+
+    /// Get the currency this denom is a micro-unit of
+    pub fn currency(&self) -> Currency {
+        let code = &self.0[1..];
+        code.parse()
+            .unwrap_or_else(|_| Currency::Other(code.to_ascii_uppercase()))
+    }
 }
 
 impl Denom {
-    /// Get a slice of the [`Denom`] kinds
+    /// Get a slice of the [`Denom`] kinds with dedicated support
     pub fn kinds() -> &'static [Denom] {
         &[
             Denom::Ukrw,
@@ -104,7 +128,7 @@ impl Denom {
     }
 
     /// Get the code corresponding to a [`Denom`]
-    pub fn as_str(self) -> &'static str {
+    pub fn as_str(&self) -> &str {
         match self {
             Denom::Ukrw => "ukrw",
             Denom::Umnt => "umnt",
@@ -125,29 +149,38 @@ impl Denom {
             Denom::Udkk => "udkk",
             Denom::Uidr => "uidr",
             Denom::Uphp => "uphp",
+            Denom::Other(other) => other.as_str(),
         }
     }
 
-    /// Get the exchange rate for this [`Denom`]
-    pub async fn get_exchange_rate(self, sources: &Sources) -> Result<stdtx::Decimal, Error> {
-        match self {
-            Denom::Ukrw => {
-                let bithumb_response = sources
-                    .bithumb
-                    .trading_pairs(&TradingPair(Currency::Luna, Currency::Krw))
-                    .await?;
-
-                let mut luna_krw: Decimal = bithumb_response.into();
+    /// Get the exchange rate for this [`Denom`]: the price from its built-in
+    /// sources, averaged with any JSON sources configured to quote LUNA in
+    /// its currency. LUNA/USD is shared by all the denoms priced in one go.
+    pub async fn get_exchange_rate(
+        &self,
+        sources: &Sources,
+        luna_usd: &LunaUsd,
+    ) -> Result<stdtx::Decimal, Error> {
+        let pair = TradingPair(Currency::Luna, self.into());
+
+        let (builtin, json) = join!(
+            self.builtin_price(sources, luna_usd),
+            sources.json_prices(&pair)
+        );
+
+        rate_from_price(aggregate(builtin, json)?)
+    }
 
-                luna_krw.rescale(18);
-                Ok(luna_krw.try_into().map_err(|_| ErrorKind::Parse)?)
-            }
+    /// Get the LUNA price in this [`Denom`]'s currency from built-in sources
+    async fn builtin_price(&self, sources: &Sources, luna_usd: &LunaUsd) -> Result<Price, Error> {
+        match self {
+            Denom::Ukrw => luna_krw(sources).await,
 
             Denom::Umnt => {
                 let (
                     currencylayer_response_usd,
                     currencylayer_response_krw,
-                    binance_response,
+                    luna_usd_price,
                     coinone_midpoint,
                 ) = try_join!(
                     sources
@@ -156,34 +189,18 @@ impl Denom {
                     sources
                         .alphavantage
                         .trading_pairs(&TradingPair(Currency::Krw, Currency::Mnt)),
-                    sources
-                        .binance
-                        .approx_price_for_pair(&TradingPair(Currency::Luna, Currency::Usd)),
+                    luna_usd.get(sources),
                     sources
                         .coinone
                         .trading_pairs(&TradingPair(Currency::Luna, Currency::Krw))
                 )?;
 
-                let mut luna_mnt = Decimal::from(
-                    (binance_response * currencylayer_response_usd
-                        + coinone_midpoint * currencylayer_response_krw)
-                        / 2,
-                );
-
-                luna_mnt.rescale(18);
-                Ok(luna_mnt.try_into().map_err(|_| ErrorKind::Parse)?)
+                Ok((luna_usd_price * currencylayer_response_usd
+                    + coinone_midpoint * currencylayer_response_krw)
+                    / 2)
             }
 
-            Denom::Uusd => {
-                let binance_response = sources
-                    .binance
-                    .approx_price_for_pair(&TradingPair(Currency::Luna, Currency::Usd))
-                    .await?;
-
-                let mut luna_usd: Decimal = binance_response.into();
-                luna_usd.rescale(18);
-                Ok(luna_usd.try_into().map_err(|_| ErrorKind::Parse)?)
-            }
+            Denom::Uusd => luna_usd.get(sources).await,
 
             Denom::Usdr => {
                 let (imf_sdr_response, coinone_midpoint) = try_join!(
@@ -195,30 +212,219 @@ impl Denom {
                         .trading_pairs(&TradingPair(Currency::Luna, Currency::Krw))
                 )?;
 
-                let mut luna_sdr = Decimal::from(coinone_midpoint * imf_sdr_response);
-                luna_sdr.rescale(18);
-                Ok(luna_sdr.try_into().map_err(|_| ErrorKind::Parse)?)
+                Ok(coinone_midpoint * imf_sdr_response)
+            }
+
+            _ => luna_price_via_usd(sources, luna_usd, self.into()).await,
+        }
+    }
+
+    /// Is this [`Denom`]'s built-in price derived (at least in part) from
+    /// LUNA/USD and a USD FX rate? Only LUNA/KRW and LUNA/SDR are priced
+    /// from their own LUNA markets.
+    pub fn is_priced_via_usd(&self) -> bool {
+        !matches!(self, Denom::Ukrw | Denom::Usdr | Denom::Uusd)
+    }
+
+    /// Get the exchange rate for this [`Denom`] from a second route which
+    /// avoids the sources used by [`Denom::get_exchange_rate`], using live
+    /// prices rather than averages.
+    ///
+    /// There's no LUNA/USD venue outside the primary route, so every denom
+    /// but KRW is confirmed via Upbit/Bithumb's LUNA/KRW and FX rates from
+    /// the ECB/Currencylayer (or the IMF, for SDR) instead of AlphaVantage.
+    /// LUNA/KRW is confirmed on Coinone.
+    ///
+    /// Used to confirm rates which deviate from the on-chain rate.
+    pub async fn get_confirmation_rate(&self, sources: &Sources) -> Result<stdtx::Decimal, Error> {
+        let price = match self {
+            Denom::Ukrw => {
+                sources
+                    .coinone
+                    .trading_pairs(&TradingPair(Currency::Luna, Currency::Krw))
+                    .await?
+            }
+            Denom::Uusd => {
+                let (luna_krw, usd_krw) = try_join!(
+                    luna_krw(sources),
+                    usd_fx_confirmation(sources, Currency::Krw)
+                )?;
+
+                luna_krw / *usd_krw
             }
+            Denom::Usdr => {
+                let (luna_krw, krw_sdr) = try_join!(
+                    luna_krw(sources),
+                    sources
+                        .imf_sdr
+                        .trading_pairs(&TradingPair(Currency::Krw, Currency::Sdr))
+                )?;
 
-            _ => luna_rate_via_usd(sources, self.into()).await,
+                luna_krw * krw_sdr.price
+            }
+            _ => {
+                let (luna_krw, usd_krw, usd_cur) = try_join!(
+                    luna_krw(sources),
+                    usd_fx_confirmation(sources, Currency::Krw),
+                    usd_fx_confirmation(sources, self.into())
+                )?;
+
+                luna_krw / *usd_krw * usd_cur
+            }
+        };
+
+        rate_from_price(price)
+    }
+}
+
+/// USD/`cur` from the ECB's reference rates, falling back to Currencylayer
+/// for currencies the ECB doesn't publish
+async fn usd_fx_confirmation(sources: &Sources, cur: Currency) -> Result<Price, Error> {
+    let pair = TradingPair(Currency::Usd, cur);
+
+    match sources.ecb.trading_pairs(&pair).await {
+        Ok(quote) => Ok(quote.price),
+        Err(e) => {
+            debug!("ECB has no rate for {} ({}); using Currencylayer", pair, e);
+            sources.currencylayer.trading_pairs(&pair).await
         }
     }
 }
 
-async fn luna_rate_via_usd(sources: &Sources, cur: Currency) -> Result<stdtx::Decimal, Error> {
+/// Convert a price into an 18 decimal place exchange rate
+fn rate_from_price(price: Price) -> Result<stdtx::Decimal, Error> {
+    let mut rate = Decimal::from(price);
+    rate.rescale(18);
+    Ok(rate.try_into().map_err(|_| ErrorKind::Parse)?)
+}
+
+/// Average a price from built-in sources with prices from JSON sources, each
+/// given equal weight. Built-in source errors are only fatal if no JSON
+/// source quoted a price.
+fn aggregate(builtin: Result<Price, Error>, json: Vec<Price>) -> Result<Price, Error> {
+    let mut prices = json;
+
+    match builtin {
+        Ok(price) => prices.push(price),
+        Err(e) if prices.is_empty() => return Err(e),
+        Err(e) => warn!("built-in source error (using JSON sources): {}", e),
+    }
+
+    let sum = prices[1..]
+        .iter()
+        .fold(prices[0], |sum, &price| sum + price);
+    Ok(sum / prices.len() as u64)
+}
+
+async fn luna_price_via_usd(
+    sources: &Sources,
+    luna_usd: &LunaUsd,
+    cur: Currency,
+) -> Result<Price, Error> {
     let pair_1 = TradingPair(Currency::Usd, cur);
 
-    let (currencylayer_response_usd, binance_response) = try_join!(
+    let (currencylayer_response_usd, luna_usd_price) = try_join!(
         sources.alphavantage.trading_pairs(&pair_1),
-        sources
-            .binance
-            .approx_price_for_pair(&TradingPair(Currency::Luna, Currency::Usd)),
+        luna_usd.get(sources),
     )?;
 
-    let mut luna_cur = Decimal::from(binance_response * currencylayer_response_usd);
+    Ok(luna_usd_price * currencylayer_response_usd)
+}
+
+/// LUNA/KRW: average of Upbit and Bithumb, preferring live tickers and
+/// falling back to each exchange's REST API if no fresh ticker is streaming
+async fn luna_krw(sources: &Sources) -> Result<Price, Error> {
+    let pair = TradingPair(Currency::Luna, Currency::Krw);
+
+    let (upbit, bithumb) = join!(
+        async {
+            match sources.streams.upbit_price("KRW-LUNA").await {
+                Some(price) => Ok(price),
+                None => sources.upbit.trading_pairs(&pair).await,
+            }
+        },
+        async {
+            match sources.streams.bithumb_price("LUNA_KRW").await {
+                Some(price) => Ok(price),
+                None => sources.bithumb.trading_pairs(&pair).await,
+            }
+        }
+    );
 
-    luna_cur.rescale(18);
-    Ok(luna_cur.try_into().map_err(|_| ErrorKind::Parse)?)
+    match (upbit, bithumb) {
+        (Ok(upbit), Ok(bithumb)) => Ok((upbit + bithumb) / 2),
+        (Ok(price), Err(e)) | (Err(e), Ok(price)) => {
+            warn!("LUNA/KRW source error (using remaining source): {}", e);
+            Ok(price)
+        }
+        (Err(e), Err(_)) => Err(e),
+    }
+}
+
+/// LUNA/USD shared by every denom priced from it: fetched on first use, then
+/// reused, so sources are only queried once for a whole set of rates
+#[derive(Debug, Default)]
+pub struct LunaUsd(OnceCell<Result<Price, String>>);
+
+impl LunaUsd {
+    /// Create a new (not yet fetched) LUNA/USD
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Get LUNA/USD, fetching it if this is the first use
+    pub async fn get(&self, sources: &Sources) -> Result<Price, Error> {
+        self.0
+            .get_or_init(|| async { luna_usd(sources).await.map_err(|e| e.to_string()) })
+            .await
+            .clone()
+            .map_err(|e| format_err!(ErrorKind::Source, "LUNA/USD unavailable: {}", e).into())
+    }
+}
+
+/// LUNA/USD: Binance, blended with Kraken's windowed VWAP if it's given a
+/// weight in the Kraken config
+async fn luna_usd(sources: &Sources) -> Result<Price, Error> {
+    let weight = sources.kraken.luna_usd_weight();
+
+    if weight.is_zero() {
+        return luna_usd_binance(sources).await;
+    }
+
+    let (binance, kraken) = join!(
+        luna_usd_binance(sources),
+        sources
+            .kraken
+            .windowed_vwap(&TradingPair(Currency::Luna, Currency::Usd))
+    );
+
+    match (binance, kraken) {
+        (Ok(binance), Ok(kraken)) => Ok(binance * (Decimal::from(1u8) - weight) + kraken * weight),
+        (Ok(price), Err(e)) | (Err(e), Ok(price)) => {
+            warn!("LUNA/USD source error (using remaining source): {}", e);
+            Ok(price)
+        }
+        (Err(e), Err(_)) => Err(e),
+    }
+}
+
+/// LUNA/USD on Binance: midpoint of live BUSD and USDT orderbooks, falling
+/// back to Binance's REST average price if the books aren't streaming
+async fn luna_usd_binance(sources: &Sources) -> Result<Price, Error> {
+    let (luna_busd, luna_usdt) = join!(
+        sources.streams.binance_book("LUNABUSD"),
+        sources.streams.binance_book("LUNAUSDT")
+    );
+
+    if let (Some(luna_busd), Some(luna_usdt)) = (luna_busd, luna_usdt) {
+        // Give BUSD and USDT equal weight
+        return Ok((midpoint(&luna_busd)? + midpoint(&luna_usdt)?) / 2);
+    }
+
+    sources
+        .binance
+        .approx_price_for_pair(&TradingPair(Currency::Luna, Currency::Usd))
+        .await
 }
 
 impl Display for Denom {
@@ -247,12 +453,21 @@ impl FromStr for Denom {
             "usek" => Ok(Denom::Usek),
             "udkk" => Ok(Denom::Udkk),
             "uidr" => Ok(Denom::Uidr),
+            "uhkd" => Ok(Denom::Uhkd),
+            "uaud" => Ok(Denom::Uaud),
+            "usgd" => Ok(Denom::Usgd),
             "uphp" => Ok(Denom::Uphp),
+            other if is_micro_denom(other) => Ok(Denom::Other(OtherDenom(other.to_owned()))),
             _ => fail!(ErrorKind::Currency, "unknown Terra denom: {}", s),
         }
     }
 }
 
+/// Is this a micro-unit denom for a 3-letter currency code, e.g. `utwd`?
+fn is_micro_denom(s: &str) -> bool {
+    s.len() == 4 && s.starts_with('u') && s.bytes().all(|b| b.is_ascii_lowercase())
+}
+
 impl<'de> Deserialize<'de> for Denom {
     fn deserialize<D: de::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
         use de::Error;
@@ -266,3 +481,96 @@ impl Serialize for Denom {
         self.to_string().serialize(serializer)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::{aggregate, rate_from_price, Denom};
+    use crate::{
+        config::{
+            source::{CurrencylayerConfig, JsonSourceConfig},
+            DelphiConfig,
+        },
+        sources::Sources,
+        streaming::tests::serve,
+        Currency, Error, ErrorKind,
+    };
+    use serde_json::json;
+    use warp::Filter;
+
+    fn json_source(addr: std::net::SocketAddr, path: &str, pairs: &[&str]) -> JsonSourceConfig {
+        JsonSourceConfig {
+            host: format!("http://{}", addr),
+            path: path.to_owned(),
+            pairs: pairs.iter().map(|pair| pair.parse().unwrap()).collect(),
+            price: Some("/price".to_owned()),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn other_denoms() {
+        let denom: Denom = "utwd".parse().unwrap();
+        assert_eq!(denom.as_str(), "utwd");
+        assert_eq!(Currency::from(&denom), Currency::Other("TWD".to_owned()));
+        assert_eq!(
+            Currency::from(&"ukrw".parse::<Denom>().unwrap()),
+            Currency::Krw
+        );
+
+        for invalid in &["", "u", "twdx", "utw", "utwdx", "u1wd"] {
+            assert!(invalid.parse::<Denom>().is_err(), "{:?}", invalid);
+        }
+    }
+
+    #[test]
+    fn aggregate_prices() {
+        let builtin = Ok("100".parse().unwrap());
+        let json = vec!["103".parse().unwrap(), "106".parse().unwrap()];
+        assert_eq!(aggregate(builtin, json).unwrap(), "103".parse().unwrap());
+
+        let failed = || Err(Error::from(ErrorKind::Source));
+        let json = vec!["103".parse().unwrap()];
+        assert_eq!(aggregate(failed(), json).unwrap(), "103".parse().unwrap());
+        assert!(aggregate(failed(), vec![]).is_err());
+    }
+
+    #[tokio::test]
+    async fn json_sources_priced_by_config() {
+        let ticker = warp::path!("ticker" / String).map(|pair: String| match pair.as_str() {
+            "LUNA-KRW" => warp::reply::json(&json!({"price": "45000"})),
+            _ => warp::reply::json(&json!({"price": "1"})),
+        });
+        let broken = warp::path!("broken" / String).map(|_| warp::reply::json(&json!({})));
+        let addr = serve(ticker.or(broken));
+
+        let mut config = DelphiConfig::default();
+        config.source.currencylayer = Some(CurrencylayerConfig {
+            access_key: "demo".to_owned(),
+        });
+
+        let sources = &mut config.source.json;
+        sources.insert(
+            "venue".to_owned(),
+            json_source(addr, "/ticker/{base}-{quote}", &["LUNA/KRW"]),
+        );
+        sources.insert(
+            "other".to_owned(),
+            json_source(addr, "/ticker/{base}-{quote}", &["LUNA/USD"]),
+        );
+        sources.insert(
+            "broken".to_owned(),
+            json_source(addr, "/broken/{base}-{quote}", &["LUNA/KRW"]),
+        );
+
+        let sources = Sources::new(&config).unwrap();
+        let pair = "LUNA/KRW".parse().unwrap();
+
+        // Only the working source configured for the pair is included
+        let json = sources.json_prices(&pair).await;
+        assert_eq!(json, vec!["45000".parse().unwrap()]);
+
+        let builtin = Ok("44000".parse().unwrap());
+        let rate = rate_from_price(aggregate(builtin, json).unwrap()).unwrap();
+        assert_eq!(rate.to_string(), "44500.000000000000000000");
+    }
+}
@@ -0,0 +1,257 @@
+//! Deviation guard
+//!
+//! Votes far from the weighted median get the validator penalized, so before
+//! voting each computed rate is compared with the current on-chain rate. If
+//! it deviates by more than the configured maximum, the guard abstains,
+//! clamps the rate, or requires confirmation from a second source.
+
+use super::{lcd, msg::ExchangeRates, Denom};
+use crate::{
+    config::network::{DeviationAction, DeviationGuardConfig},
+    prelude::*,
+    sources::Sources,
+    Error, ErrorKind, Map,
+};
+use rust_decimal::Decimal;
+use std::{convert::TryInto, time::Duration};
+use tokio::time::timeout;
+
+/// Guard against voting rates far from the on-chain rates
+#[derive(Clone, Debug)]
+pub struct DeviationGuard {
+    /// Maximum relative deviation from the on-chain rate
+    max_deviation: Decimal,
+
+    /// Action to take when the maximum deviation is exceeded
+    action: DeviationAction,
+}
+
+/// Decision made by the guard about a single rate
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum Decision {
+    /// Rate is within the maximum deviation (or can't be checked)
+    Accept,
+
+    /// Abstain from voting on this denom
+    Abstain,
+
+    /// Vote this (clamped) rate instead
+    Clamp(Decimal),
+
+    /// Vote the rate only if a second source confirms it
+    Confirm,
+}
+
+impl DeviationGuard {
+    /// Create a new deviation guard from config
+    pub fn new(config: &DeviationGuardConfig) -> Self {
+        Self {
+            max_deviation: config.max_deviation,
+            action: config.action,
+        }
+    }
+
+    /// Decide what to do with a computed rate given the on-chain rate
+    pub fn check(&self, rate: Decimal, on_chain: Decimal) -> Decision {
+        // The chain has no rate for this denom (e.g. everyone abstained)
+        if on_chain <= Decimal::from(0u8) {
+            return Decision::Accept;
+        }
+
+        if relative_deviation(rate, on_chain) <= self.max_deviation {
+            return Decision::Accept;
+        }
+
+        match self.action {
+            DeviationAction::Abstain => Decision::Abstain,
+            DeviationAction::Clamp => {
+                let max_change = on_chain * self.max_deviation;
+                Decision::Clamp(rate.max(on_chain - max_change).min(on_chain + max_change))
+            }
+            DeviationAction::Confirm => Decision::Confirm,
+        }
+    }
+
+    /// Is a rate confirmed by a rate from a second source?
+    pub fn is_confirmed(&self, rate: Decimal, confirmation: Decimal) -> bool {
+        confirmation > Decimal::from(0u8)
+            && relative_deviation(rate, confirmation) <= self.max_deviation
+    }
+
+    /// Apply the guard to a set of exchange rates, logging each decision
+    pub async fn apply(
+        &self,
+        exchange_rates: &ExchangeRates,
+        on_chain: &Map<Denom, lcd::Quote>,
+        sources: &Sources,
+        timeout_duration: Duration,
+    ) -> ExchangeRates {
+        let mut guarded = ExchangeRates::new();
+
+        for (denom, &rate) in exchange_rates.iter() {
+            let on_chain_rate = match on_chain.get(denom) {
+                Some(quote) => Decimal::from(quote.price),
+                None => {
+                    debug!("{}: no on-chain rate to check against", denom);
+                    guarded.add(denom.clone(), rate).expect("duplicate denom");
+                    continue;
+                }
+            };
+
+            let computed = to_decimal(rate);
+
+            let guarded_rate = match self.check(computed, on_chain_rate) {
+                Decision::Accept => rate,
+                Decision::Abstain => {
+                    warn!(
+                        "{}: computed {} deviates from on-chain {}; abstaining",
+                        denom, computed, on_chain_rate
+                    );
+                    abstain()
+                }
+                Decision::Clamp(clamped) => {
+                    warn!(
+                        "{}: computed {} deviates from on-chain {}; clamping to {}",
+                        denom, computed, on_chain_rate, clamped
+                    );
+
+                    match from_decimal(clamped) {
+                        Ok(clamped) => clamped,
+                        Err(e) => {
+                            error!("{}: error clamping rate: {}; abstaining", denom, e);
+                            abstain()
+                        }
+                    }
+                }
+                Decision::Confirm => {
+                    let confirmation =
+                        timeout(timeout_duration, denom.get_confirmation_rate(sources)).await;
+
+                    match confirmation {
+                        Ok(Ok(confirmation))
+                            if self.is_confirmed(computed, to_decimal(confirmation)) =>
+                        {
+                            info!(
+                                "{}: computed {} deviates from on-chain {} but is confirmed by {}; voting",
+                                denom, computed, on_chain_rate, confirmation
+                            );
+                            rate
+                        }
+                        Ok(Ok(confirmation)) => {
+                            warn!(
+                                "{}: computed {} deviates from on-chain {} and is not confirmed by {}; abstaining",
+                                denom, computed, on_chain_rate, confirmation
+                            );
+                            abstain()
+                        }
+                        Ok(Err(e)) => {
+                            warn!(
+                                "{}: computed {} deviates from on-chain {} and confirmation failed: {}; abstaining",
+                                denom, computed, on_chain_rate, e
+                            );
+                            abstain()
+                        }
+                        Err(_) => {
+                            warn!(
+                                "{}: computed {} deviates from on-chain {} and confirmation timed out; abstaining",
+                                denom, computed, on_chain_rate
+                            );
+                            abstain()
+                        }
+                    }
+                }
+            };
+
+            guarded
+                .add(denom.clone(), guarded_rate)
+                .expect("duplicate denom");
+        }
+
+        guarded
+    }
+}
+
+/// Relative deviation of `rate` from `reference`, e.g. `0.1` for 10%
+pub fn relative_deviation(rate: Decimal, reference: Decimal) -> Decimal {
+    ((rate - reference) / reference).abs()
+}
+
+/// Exchange rate representing an abstain vote
+pub fn abstain() -> stdtx::Decimal {
+    stdtx::Decimal::from(0u8)
+}
+
+/// Convert an exchange rate into a `Decimal`
+pub fn to_decimal(rate: stdtx::Decimal) -> Decimal {
+    rate.to_string()
+        .parse()
+        .expect("exchange rate is a valid decimal")
+}
+
+/// Convert a `Decimal` into an 18 decimal place exchange rate
+pub fn from_decimal(mut decimal: Decimal) -> Result<stdtx::Decimal, Error> {
+    decimal.rescale(18);
+    Ok(decimal.try_into().map_err(|_| ErrorKind::Parse)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{relative_deviation, Decision, DeviationGuard};
+    use crate::config::network::{DeviationAction, DeviationGuardConfig};
+    use rust_decimal::Decimal;
+
+    fn guard(action: DeviationAction) -> DeviationGuard {
+        DeviationGuard::new(&DeviationGuardConfig {
+            max_deviation: "0.1".parse().unwrap(),
+            action,
+        })
+    }
+
+    fn dec(s: &str) -> Decimal {
+        s.parse().unwrap()
+    }
+
+    #[test]
+    fn deviation() {
+        assert_eq!(relative_deviation(dec("110"), dec("100")), dec("0.1"));
+        assert_eq!(relative_deviation(dec("95"), dec("100")), dec("0.05"));
+    }
+
+    #[test]
+    fn accepts_within_max_deviation() {
+        let guard = guard(DeviationAction::Abstain);
+        assert_eq!(guard.check(dec("110"), dec("100")), Decision::Accept);
+        assert_eq!(guard.check(dec("90"), dec("100")), Decision::Accept);
+
+        // No on-chain rate to compare against
+        assert_eq!(guard.check(dec("500"), dec("0")), Decision::Accept);
+    }
+
+    #[test]
+    fn abstains() {
+        let guard = guard(DeviationAction::Abstain);
+        assert_eq!(guard.check(dec("111"), dec("100")), Decision::Abstain);
+    }
+
+    #[test]
+    fn clamps() {
+        let guard = guard(DeviationAction::Clamp);
+        assert_eq!(
+            guard.check(dec("150"), dec("100")),
+            Decision::Clamp(dec("110"))
+        );
+        assert_eq!(
+            guard.check(dec("50"), dec("100")),
+            Decision::Clamp(dec("90"))
+        );
+    }
+
+    #[test]
+    fn requires_confirmation() {
+        let guard = guard(DeviationAction::Confirm);
+        assert_eq!(guard.check(dec("150"), dec("100")), Decision::Confirm);
+        assert!(guard.is_confirmed(dec("150"), dec("148")));
+        assert!(!guard.is_confirmed(dec("150"), dec("100")));
+        assert!(!guard.is_confirmed(dec("150"), dec("0")));
+    }
+}
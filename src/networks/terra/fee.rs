@@ -0,0 +1,223 @@
+//! Fee estimation
+//!
+//! Rather than paying a fixed fee regardless of how many denoms are voted
+//! on, the gas for each transaction can be estimated (by simulating it, or
+//! from a per-message gas model). The estimate is scaled by the gas
+//! adjustment, priced in the configured denom, and capped at the maximum
+//! fee.
+
+use super::msg::OracleMsg;
+use crate::config::network::{FeeEstimationConfig, GasEstimation, GasModelConfig};
+use crate::prelude::*;
+use rust_decimal::{prelude::ToPrimitive, Decimal};
+use stdtx::amino::types::{Coin, StdFee};
+
+/// Fee estimator
+#[derive(Clone, Debug)]
+pub struct FeeEstimator {
+    /// Gas estimation method
+    gas: GasEstimation,
+
+    /// Gas model
+    gas_model: GasModelConfig,
+
+    /// Multiplier applied to the estimated gas
+    gas_adjustment: Decimal,
+
+    /// Fee denom
+    denom: String,
+
+    /// Price per unit of gas
+    gas_price: Decimal,
+
+    /// Maximum fee amount
+    max_fee: Option<u64>,
+}
+
+impl FeeEstimator {
+    /// Create a new fee estimator from config
+    pub fn new(config: &FeeEstimationConfig) -> Self {
+        Self {
+            gas: config.gas,
+            gas_model: config.gas_model.clone(),
+            gas_adjustment: config.gas_adjustment,
+            denom: config.gas_price.denom.clone(),
+            gas_price: config.gas_price.amount,
+            max_fee: config.max_fee,
+        }
+    }
+
+    /// Should gas be estimated by simulating transactions?
+    pub fn simulate(&self) -> bool {
+        self.gas == GasEstimation::Simulate
+    }
+
+    /// Estimate the gas used by a transaction containing the given messages
+    pub fn model_gas(&self, msgs: &[OracleMsg]) -> u64 {
+        let (msg_count, denom_count) = count(msgs);
+
+        self.gas_model
+            .base
+            .saturating_add(self.gas_model.per_msg.saturating_mul(msg_count))
+            .saturating_add(self.gas_model.per_denom.saturating_mul(denom_count))
+    }
+
+    /// Compute the fee for the given estimated gas.
+    ///
+    /// Increases made to `current` in response to failed transactions
+    /// (relative to `configured`) are applied on top of the estimate.
+    pub fn fee(&self, estimated_gas: u64, current: &StdFee, configured: &StdFee) -> StdFee {
+        let gas_bump = ratio(current.gas, configured.gas);
+        let fee_bump = match (current.amount.first(), configured.amount.first()) {
+            (Some(current), Some(configured)) => {
+                match (current.amount.parse(), configured.amount.parse()) {
+                    (Ok(current), Ok(configured)) => ratio(current, configured),
+                    _ => Decimal::from(1u8),
+                }
+            }
+            _ => Decimal::from(1u8),
+        };
+
+        let gas = to_u64(Decimal::from(estimated_gas) * self.gas_adjustment * gas_bump);
+        let mut amount = to_u64(Decimal::from(gas) * self.gas_price * fee_bump);
+
+        if let Some(max_fee) = self.max_fee {
+            if amount > max_fee {
+                warn!(
+                    "estimated fee {}{} exceeds maximum; capping at {}{}",
+                    amount, self.denom, max_fee, self.denom
+                );
+                amount = max_fee;
+            }
+        }
+
+        StdFee {
+            amount: vec![Coin {
+                denom: self.denom.clone(),
+                amount: amount.to_string(),
+            }],
+            gas,
+        }
+    }
+}
+
+/// Count messages (including those wrapped in `MsgExec`) and voted denoms
+fn count(msgs: &[OracleMsg]) -> (u64, u64) {
+    msgs.iter()
+        .fold((0, 0), |(msg_count, denom_count), msg| match msg {
+            OracleMsg::AggregateVote(vote) => (
+                msg_count + 1,
+                denom_count + vote.exchange_rates.iter().count() as u64,
+            ),
+            OracleMsg::Vote(_) => (msg_count + 1, denom_count + 1),
+            OracleMsg::AggregatePrevote(_)
+            | OracleMsg::Prevote(_)
+            | OracleMsg::DelegateFeedConsent(_) => (msg_count + 1, denom_count),
+            OracleMsg::Exec { msgs, .. } => {
+                let (inner_msgs, inner_denoms) = count(msgs);
+                (msg_count + 1 + inner_msgs, denom_count + inner_denoms)
+            }
+        })
+}
+
+/// Compute `value / base`, or 1 if `base` is zero
+fn ratio(value: u64, base: u64) -> Decimal {
+    if base == 0 {
+        Decimal::from(1u8)
+    } else {
+        Decimal::from(value) / Decimal::from(base)
+    }
+}
+
+/// Round a decimal up to an integer
+fn to_u64(value: Decimal) -> u64 {
+    value.ceil().to_u64().unwrap_or(u64::MAX)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::FeeEstimator;
+    use crate::{
+        config::network::{FeeEstimationConfig, GasEstimation, GasPriceConfig},
+        networks::terra::msg::{MsgAggregateExchangeRateVote, OracleMsg},
+    };
+    use stdtx::{
+        amino::types::{Coin, StdFee},
+        Address,
+    };
+
+    fn estimator(max_fee: Option<u64>) -> FeeEstimator {
+        FeeEstimator::new(&FeeEstimationConfig {
+            gas: GasEstimation::Model,
+            gas_model: Default::default(),
+            gas_adjustment: "1.5".parse().unwrap(),
+            gas_price: GasPriceConfig {
+                denom: "uusd".to_owned(),
+                amount: "0.15".parse().unwrap(),
+            },
+            max_fee,
+        })
+    }
+
+    fn configured_fee() -> StdFee {
+        StdFee {
+            amount: vec![Coin {
+                denom: "ukrw".to_owned(),
+                amount: "1000".to_owned(),
+            }],
+            gas: 100_000,
+        }
+    }
+
+    fn msgs() -> Vec<OracleMsg> {
+        let vote = MsgAggregateExchangeRateVote {
+            exchange_rates: "362.000000000000000000ukrw,1.000000000000000000uusd"
+                .parse()
+                .unwrap(),
+            salt: "abcd".to_owned(),
+            feeder: Address([1; 20]),
+            validator: Address([2; 20]),
+        };
+
+        vec![
+            OracleMsg::AggregateVote(vote.clone()),
+            OracleMsg::AggregatePrevote(vote.prevote()),
+        ]
+    }
+
+    #[test]
+    fn model_gas() {
+        // 50000 + 2 × 25000 + 2 × 2000
+        assert_eq!(estimator(None).model_gas(&msgs()), 104_000);
+    }
+
+    #[test]
+    fn fee_from_gas_price() {
+        let configured = configured_fee();
+        let fee = estimator(None).fee(104_000, &configured, &configured);
+
+        assert_eq!(fee.gas, 156_000);
+        assert_eq!(fee.amount[0].denom, "uusd");
+        assert_eq!(fee.amount[0].amount, "23400");
+    }
+
+    #[test]
+    fn fee_bumps_and_cap() {
+        let configured = configured_fee();
+        let bumped = StdFee {
+            amount: vec![Coin {
+                denom: "ukrw".to_owned(),
+                amount: "2000".to_owned(),
+            }],
+            gas: 125_000,
+        };
+
+        let fee = estimator(None).fee(100_000, &bumped, &configured);
+        assert_eq!(fee.gas, 187_500);
+        assert_eq!(fee.amount[0].amount, "56250");
+
+        let fee = estimator(Some(30_000)).fee(100_000, &bumped, &configured);
+        assert_eq!(fee.gas, 187_500);
+        assert_eq!(fee.amount[0].amount, "30000");
+    }
+}
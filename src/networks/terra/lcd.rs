@@ -0,0 +1,556 @@
+//! Terra LCD (light client daemon) REST API client
+//! <https://lcd.terra.dev/swagger-ui/>
+//!
+//! Provides the chain's own view of prices: the current oracle exchange
+//! rates and the market module's swap simulation. These are intended as a
+//! sanity reference for the rates Delphi computes, not as a voting input.
+//!
+//! Also provides the oracle module's parameters (denom whitelist, vote
+//! period and reward band), the feeder account's number and sequence
+//! (used when signing transactions), and the latest block height.
+
+use super::Denom;
+use crate::{
+    config::{network::TerraLcdConfig, HttpsConfig},
+    prelude::*,
+    Error, ErrorKind, Map, Price,
+};
+use bytes::Buf;
+use iqhttp::{hyper, HttpsClient, Uri};
+use rust_decimal::Decimal;
+use serde::{de::DeserializeOwned, Deserialize};
+use serde_json::json;
+
+/// Micro-LUNA denom
+pub const ULUNA: &str = "uluna";
+
+/// Amount of uluna offered when simulating swaps (1 LUNA)
+pub const SWAP_OFFER_AMOUNT: u64 = 1_000_000;
+
+/// Terra LCD client
+pub struct LcdClient {
+    /// Base URL of the LCD (without trailing slash)
+    base_url: String,
+
+    /// HTTP(S) client
+    https_client: HttpsClient,
+}
+
+impl LcdClient {
+    /// Create a new LCD client
+    pub fn new(config: &TerraLcdConfig, https_config: &HttpsConfig) -> Result<Self, Error> {
+        let base_url = config.url.trim_end_matches('/').to_owned();
+
+        let uri: Uri = base_url
+            .parse()
+            .map_err(|e| format_err!(ErrorKind::Config, "invalid LCD URL {}: {}", base_url, e))?;
+
+        let host = uri
+            .host()
+            .ok_or_else(|| format_err!(ErrorKind::Config, "LCD URL has no host: {}", base_url))?;
+
+        let https_client = https_config.new_client(host)?;
+
+        Ok(Self {
+            base_url,
+            https_client,
+        })
+    }
+
+    /// `GET /oracle/denoms/exchange_rates` - current on-chain oracle exchange
+    /// rates (denom units per LUNA)
+    pub async fn oracle_quotes(&self) -> Result<Map<Denom, Quote>, Error> {
+        let response: Response<Vec<Coin>> = self.get("/oracle/denoms/exchange_rates").await?;
+        let height = response.height()?;
+        let mut quotes = Map::new();
+
+        for coin in response.result {
+            match coin.denom.parse::<Denom>() {
+                Ok(denom) => {
+                    let price = Price::new(coin.amount)?;
+                    quotes.insert(denom, Quote { price, height });
+                }
+                Err(_) => debug!("ignoring on-chain rate for unknown denom: {}", coin.denom),
+            }
+        }
+
+        Ok(quotes)
+    }
+
+    /// `GET /market/swap` - simulate swapping 1 LUNA for the given denom,
+    /// giving the market module's rate including its spread
+    pub async fn swap_quote(&self, denom: Denom) -> Result<Quote, Error> {
+        let path = format!(
+            "/market/swap?offer_coin={}{}&ask_denom={}",
+            SWAP_OFFER_AMOUNT, ULUNA, denom
+        );
+
+        let response: Response<Coin> = self.get(&path).await?;
+
+        ensure!(
+            response.result.denom == denom.as_str(),
+            ErrorKind::Source,
+            "LCD swap simulation returned {} (expected {})",
+            response.result.denom,
+            denom
+        );
+
+        Ok(Quote {
+            price: Price::new(response.result.amount / Decimal::from(SWAP_OFFER_AMOUNT))?,
+            height: response.height()?,
+        })
+    }
+
+    /// `GET /oracle/parameters` - oracle module parameters
+    pub async fn oracle_params(&self) -> Result<OracleParams, Error> {
+        let response: Response<RawOracleParams> = self.get("/oracle/parameters").await?;
+        let params = response.result;
+
+        let vote_period = params
+            .vote_period
+            .parse()
+            .map_err(|e| format_err!(ErrorKind::Parse, "bad vote period: {}", e))?;
+
+        let whitelist = params
+            .whitelist
+            .iter()
+            .map(|denom| denom.name.parse())
+            .collect::<Result<_, _>>()?;
+
+        Ok(OracleParams {
+            vote_period,
+            reward_band: params.reward_band,
+            whitelist,
+        })
+    }
+
+    /// `GET /auth/accounts/{address}` - account number and sequence
+    pub async fn account(&self, address: &str) -> Result<Account, Error> {
+        let response: Response<RawAccount> =
+            self.get(&format!("/auth/accounts/{}", address)).await?;
+
+        let account = response.result.value;
+
+        ensure!(
+            account.address == address,
+            ErrorKind::Source,
+            "LCD returned account {} (expected {})",
+            account.address,
+            address
+        );
+
+        let account_number = account
+            .account_number
+            .parse()
+            .map_err(|e| format_err!(ErrorKind::Parse, "bad account number: {}", e))?;
+
+        let sequence = account
+            .sequence
+            .parse()
+            .map_err(|e| format_err!(ErrorKind::Parse, "bad account sequence: {}", e))?;
+
+        Ok(Account {
+            account_number,
+            sequence,
+        })
+    }
+
+    /// `GET /oracle/voters/{validator}/feeder` - account the validator has
+    /// delegated its oracle votes to (the validator's own account if none)
+    pub async fn feeder_delegation(&self, validator: &str) -> Result<String, Error> {
+        let response: Response<String> = self
+            .get(&format!("/oracle/voters/{}/feeder", validator))
+            .await?;
+
+        Ok(response.result)
+    }
+
+    /// `GET /blocks/latest` - height of the latest block
+    pub async fn latest_height(&self) -> Result<u64, Error> {
+        let response: BlockResponse = self.get("/blocks/latest").await?;
+
+        response
+            .block
+            .header
+            .height
+            .parse()
+            .map_err(|e| format_err!(ErrorKind::Parse, "bad block height: {}", e).into())
+    }
+
+    /// `POST /cosmos/tx/v1beta1/simulate` - simulate a transaction, returning
+    /// the gas it used
+    pub async fn simulate(&self, tx_bytes: &[u8]) -> Result<u64, Error> {
+        let uri = format!("{}/cosmos/tx/v1beta1/simulate", self.base_url);
+        let body = serde_json::to_vec(&json!({ "tx_bytes": base64::encode(tx_bytes) }))?;
+
+        let request = hyper::Request::post(&uri)
+            .header(hyper::header::CONTENT_TYPE, "application/json")
+            .body(hyper::Body::from(body))
+            .map_err(|e| format_err!(ErrorKind::Http, "error building request: {}", e))?;
+
+        let response: SimulateResponse = self.send(request).await?;
+
+        response
+            .gas_info
+            .gas_used
+            .parse()
+            .map_err(|e| format_err!(ErrorKind::Parse, "bad simulated gas: {}", e).into())
+    }
+
+    /// Make a GET request to the LCD and parse the JSON response
+    async fn get<T: DeserializeOwned>(&self, path_and_query: &str) -> Result<T, Error> {
+        let uri = format!("{}{}", self.base_url, path_and_query);
+
+        let request = hyper::Request::get(&uri)
+            .body(hyper::Body::empty())
+            .map_err(|e| format_err!(ErrorKind::Http, "error building request: {}", e))?;
+
+        self.send(request).await
+    }
+
+    /// Send a request to the LCD and parse the JSON response
+    async fn send<T: DeserializeOwned>(
+        &self,
+        request: hyper::Request<hyper::Body>,
+    ) -> Result<T, Error> {
+        let uri = request.uri().to_string();
+        let response = self.https_client.request(request).await?;
+
+        ensure!(
+            response.status().is_success(),
+            ErrorKind::Source,
+            "LCD returned {} for {}",
+            response.status(),
+            uri
+        );
+
+        let body = hyper::body::aggregate(response.into_body())
+            .await
+            .map_err(|e| ErrorKind::Http.context(e))?;
+
+        Ok(serde_json::from_reader(body.reader())?)
+    }
+}
+
+/// On-chain quote (denom units per LUNA) along with the height it was read at
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub struct Quote {
+    /// Price
+    pub price: Price,
+
+    /// Block height
+    pub height: u64,
+}
+
+/// Oracle module parameters
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct OracleParams {
+    /// Number of blocks in a vote period
+    pub vote_period: u64,
+
+    /// Band around the weighted median within which votes are rewarded
+    pub reward_band: Decimal,
+
+    /// Denoms the oracle votes on
+    pub whitelist: Vec<Denom>,
+}
+
+/// Oracle module parameters as returned by the LCD
+#[derive(Clone, Debug, Deserialize)]
+struct RawOracleParams {
+    /// Vote period (as a string)
+    vote_period: String,
+
+    /// Reward band
+    reward_band: Decimal,
+
+    /// Whitelisted denoms
+    whitelist: Vec<WhitelistedDenom>,
+}
+
+/// Whitelisted denom
+#[derive(Clone, Debug, Deserialize)]
+struct WhitelistedDenom {
+    /// Denom name
+    name: String,
+}
+
+/// Latest block response
+#[derive(Clone, Debug, Deserialize)]
+struct BlockResponse {
+    /// Block
+    block: Block,
+}
+
+/// Block (only the header is parsed)
+#[derive(Clone, Debug, Deserialize)]
+struct Block {
+    /// Block header
+    header: BlockHeader,
+}
+
+/// Block header
+#[derive(Clone, Debug, Deserialize)]
+struct BlockHeader {
+    /// Block height (as a string)
+    height: String,
+}
+
+/// Simulation response
+#[derive(Clone, Debug, Deserialize)]
+struct SimulateResponse {
+    /// Gas info
+    gas_info: GasInfo,
+}
+
+/// Gas wanted and used
+#[derive(Clone, Debug, Deserialize)]
+struct GasInfo {
+    /// Gas used (as a string)
+    gas_used: String,
+}
+
+/// Account number and sequence
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub struct Account {
+    /// Account number
+    pub account_number: u64,
+
+    /// Sequence number of the next transaction
+    pub sequence: u64,
+}
+
+/// Account as returned by the LCD (amino JSON)
+#[derive(Clone, Debug, Deserialize)]
+struct RawAccount {
+    /// Account value
+    value: RawAccountValue,
+}
+
+/// Account value
+#[derive(Clone, Debug, Deserialize)]
+struct RawAccountValue {
+    /// Bech32 address
+    address: String,
+
+    /// Account number (as a string)
+    account_number: String,
+
+    /// Sequence (as a string)
+    sequence: String,
+}
+
+/// LCD response envelope
+#[derive(Clone, Debug, Deserialize)]
+pub struct Response<T> {
+    /// Block height (as a string)
+    pub height: String,
+
+    /// Result
+    pub result: T,
+}
+
+impl<T> Response<T> {
+    /// Parse the block height
+    pub fn height(&self) -> Result<u64, Error> {
+        self.height
+            .parse()
+            .map_err(|e| format_err!(ErrorKind::Parse, "bad LCD height: {}", e).into())
+    }
+}
+
+/// Amount of a given denom
+#[derive(Clone, Debug, Deserialize)]
+pub struct Coin {
+    /// Denom
+    pub denom: String,
+
+    /// Amount
+    pub amount: Decimal,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::LcdClient;
+    use crate::{config::network::TerraLcdConfig, networks::terra::Denom, streaming::tests::serve};
+    use serde_json::json;
+    use std::collections::HashMap;
+    use warp::Filter;
+
+    /// Stand-in for the LCD's oracle and market endpoints
+    fn lcd_stand_in() -> String {
+        let exchange_rates = warp::path!("oracle" / "denoms" / "exchange_rates").map(|| {
+            warp::reply::json(&json!({
+                "height": "4853025",
+                "result": [
+                    {"denom": "ukrw", "amount": "1491.252404537519456166"},
+                    {"denom": "uusd", "amount": "1.273599998754371287"},
+                    {"denom": "utwd", "amount": "36.97"},
+                    {"denom": "foo", "amount": "1.0"}
+                ]
+            }))
+        });
+
+        let params = warp::path!("oracle" / "parameters").map(|| {
+            warp::reply::json(&json!({
+                "height": "4853027",
+                "result": {
+                    "vote_period": "5",
+                    "vote_threshold": "0.500000000000000000",
+                    "reward_band": "0.020000000000000000",
+                    "reward_distribution_window": "5256000",
+                    "whitelist": [
+                        {"name": "ukrw", "tobin_tax": "0.002500000000000000"},
+                        {"name": "uusd", "tobin_tax": "0.002500000000000000"},
+                        {"name": "utwd", "tobin_tax": "0.002500000000000000"}
+                    ],
+                    "slash_fraction": "0.000100000000000000",
+                    "slash_window": "432000",
+                    "min_valid_per_window": "0.050000000000000000"
+                }
+            }))
+        });
+
+        let swap = warp::path!("market" / "swap")
+            .and(warp::query::<HashMap<String, String>>())
+            .map(|query: HashMap<String, String>| {
+                assert_eq!(query["offer_coin"], "1000000uluna");
+                warp::reply::json(&json!({
+                    "height": "4853026",
+                    "result": {"denom": query["ask_denom"], "amount": "1483796000"}
+                }))
+            });
+
+        let account = warp::path!("auth" / "accounts" / String).map(|address: String| {
+            warp::reply::json(&json!({
+                "height": "4853028",
+                "result": {
+                    "type": "core/Account",
+                    "value": {
+                        "address": address,
+                        "public_key": null,
+                        "account_number": "1107",
+                        "sequence": "42"
+                    }
+                }
+            }))
+        });
+
+        let feeder =
+            warp::path!("oracle" / "voters" / String / "feeder").map(|validator: String| {
+                assert!(validator.starts_with("terravaloper1"));
+                warp::reply::json(&json!({
+                    "height": "4853029",
+                    "result": "terra1dcegyrekltswvyy0xy69ydgxn9x8x32zdtapd8"
+                }))
+            });
+
+        let latest_block = warp::path!("blocks" / "latest").map(|| {
+            warp::reply::json(&json!({
+                "block_id": {"hash": "", "parts": {"total": 1, "hash": ""}},
+                "block": {"header": {"chain_id": "columbus-5", "height": "4853030"}}
+            }))
+        });
+
+        let simulate = warp::post()
+            .and(warp::path!("cosmos" / "tx" / "v1beta1" / "simulate"))
+            .and(warp::body::json())
+            .map(|body: serde_json::Value| {
+                assert_eq!(body["tx_bytes"], "AQID");
+                warp::reply::json(&json!({
+                    "gas_info": {"gas_wanted": "0", "gas_used": "87519"},
+                    "result": {"data": "", "log": "", "events": []}
+                }))
+            });
+
+        format!(
+            "http://{}/",
+            serve(
+                exchange_rates
+                    .or(swap)
+                    .or(params)
+                    .or(account)
+                    .or(feeder)
+                    .or(latest_block)
+                    .or(simulate)
+            )
+        )
+    }
+
+    fn config() -> TerraLcdConfig {
+        TerraLcdConfig {
+            url: lcd_stand_in(),
+            params_refresh_secs: 3600,
+        }
+    }
+
+    #[tokio::test]
+    async fn oracle_quotes() {
+        let lcd = LcdClient::new(&config(), &Default::default()).unwrap();
+        let quotes = lcd.oracle_quotes().await.unwrap();
+
+        // Invalid denoms are skipped
+        assert_eq!(quotes.len(), 3);
+        assert!(quotes.contains_key(&"utwd".parse::<Denom>().unwrap()));
+        assert_eq!(quotes[&Denom::Ukrw].height, 4853025);
+        assert_eq!(
+            quotes[&Denom::Uusd].price,
+            "1.273599998754371287".parse().unwrap()
+        );
+    }
+
+    #[tokio::test]
+    async fn swap_quote() {
+        let lcd = LcdClient::new(&config(), &Default::default()).unwrap();
+        let quote = lcd.swap_quote(Denom::Ukrw).await.unwrap();
+
+        assert_eq!(quote.price, "1483.796".parse().unwrap());
+        assert_eq!(quote.height, 4853026);
+    }
+
+    #[tokio::test]
+    async fn oracle_params() {
+        let lcd = LcdClient::new(&config(), &Default::default()).unwrap();
+        let params = lcd.oracle_params().await.unwrap();
+
+        assert_eq!(params.vote_period, 5);
+        assert_eq!(params.reward_band, "0.02".parse().unwrap());
+        assert_eq!(
+            params.whitelist,
+            vec![Denom::Ukrw, Denom::Uusd, "utwd".parse::<Denom>().unwrap()]
+        );
+    }
+
+    #[tokio::test]
+    async fn account() {
+        let lcd = LcdClient::new(&config(), &Default::default()).unwrap();
+        let address = "terra1dcegyrekltswvyy0xy69ydgxn9x8x32zdtapd8";
+        let account = lcd.account(address).await.unwrap();
+
+        assert_eq!(account.account_number, 1107);
+        assert_eq!(account.sequence, 42);
+    }
+
+    #[tokio::test]
+    async fn feeder_delegation() {
+        let lcd = LcdClient::new(&config(), &Default::default()).unwrap();
+        let feeder = lcd
+            .feeder_delegation("terravaloper1dcegyrekltswvyy0xy69ydgxn9x8x32zdtapd8")
+            .await
+            .unwrap();
+
+        assert_eq!(feeder, "terra1dcegyrekltswvyy0xy69ydgxn9x8x32zdtapd8");
+    }
+
+    #[tokio::test]
+    async fn latest_height() {
+        let lcd = LcdClient::new(&config(), &Default::default()).unwrap();
+        assert_eq!(lcd.latest_height().await.unwrap(), 4853030);
+    }
+
+    #[tokio::test]
+    async fn simulate() {
+        let lcd = LcdClient::new(&config(), &Default::default()).unwrap();
+        assert_eq!(lcd.simulate(&[1, 2, 3]).await.unwrap(), 87519);
+    }
+}
@@ -2,18 +2,21 @@
 
 // TODO(tarcieri): autogenerate this from the schema? (possibly after proto migration)
 
-use super::{Denom, SCHEMA};
+use super::{protos, Denom, SCHEMA};
 use crate::{
     error::{Error, ErrorKind},
     map,
     prelude::*,
     Map,
 };
+use cosmrs::tx::{MsgProto, MsgType};
 use rand::{distributions::Alphanumeric, thread_rng, Rng};
+use serde_json::json;
 use sha2::{Digest, Sha256};
 use std::{
     convert::TryFrom,
     fmt::{self, Display},
+    str::FromStr,
 };
 use stdtx::{Address, Decimal};
 
@@ -56,6 +59,18 @@ impl MsgAggregateExchangeRateVote {
         )
     }
 
+    /// Build a protobuf `/terra.oracle.v1beta1.MsgAggregateExchangeRateVote`
+    pub fn to_proto_msg(&self) -> eyre::Result<cosmrs::tx::Msg> {
+        let msg = protos::MsgAggregateExchangeRateVote {
+            salt: self.salt.clone(),
+            exchange_rates: self.exchange_rates.to_string(),
+            feeder: self.feeder.to_bech32("terra"),
+            validator: self.validator.to_bech32("terravaloper"),
+        };
+
+        msg.to_msg()
+    }
+
     /// Compute prevote from this vote
     pub fn prevote(&self) -> MsgAggregateExchangeRatePrevote {
         MsgAggregateExchangeRatePrevote {
@@ -65,12 +80,26 @@ impl MsgAggregateExchangeRateVote {
         }
     }
 
+    /// Split this vote into legacy per-denom votes (sharing its salt)
+    pub fn per_denom_votes(&self) -> Vec<MsgExchangeRateVote> {
+        self.exchange_rates
+            .iter()
+            .map(|(denom, rate)| MsgExchangeRateVote {
+                exchange_rate: *rate,
+                salt: self.salt.clone(),
+                denom: denom.clone(),
+                feeder: self.feeder,
+                validator: self.validator,
+            })
+            .collect()
+    }
+
     /// Generate hex encoded truncated SHA-256 of vote. Needed to generate prevote
     fn generate_vote_hash(&self) -> Hash {
         let data = format!(
             "{}:{}:{}",
             self.salt,
-            self.exchange_rates.to_string(),
+            self.exchange_rates,
             self.validator.to_bech32("terravaloper"),
         );
 
@@ -105,6 +134,322 @@ impl MsgAggregateExchangeRatePrevote {
                 .to_msg(),
         )
     }
+
+    /// Build a protobuf `/terra.oracle.v1beta1.MsgAggregateExchangeRatePrevote`
+    pub fn to_proto_msg(&self) -> eyre::Result<cosmrs::tx::Msg> {
+        let msg = protos::MsgAggregateExchangeRatePrevote {
+            hash: self.hex_hash(),
+            feeder: self.feeder.to_bech32("terra"),
+            validator: self.validator.to_bech32("terravaloper"),
+        };
+
+        msg.to_msg()
+    }
+
+    /// Get the hash as a lower case hex string
+    pub fn hex_hash(&self) -> String {
+        self.hash
+            .iter()
+            .map(|byte| format!("{:02x}", byte))
+            .collect()
+    }
+}
+
+/// Terra Oracle Vote Message (`oracle/MsgExchangeRateVote`)
+/// <https://docs.terra.money/dev/spec-oracle.html#msgexchangeratevote>
+///
+/// Legacy per-denom vote, superseded by [`MsgAggregateExchangeRateVote`]
+#[derive(Clone, Debug)]
+pub struct MsgExchangeRateVote {
+    /// Exchange rate to be voted on. Negative values are an abstain vote.
+    pub exchange_rate: Decimal,
+
+    /// Salt for commit reveal protocol
+    pub salt: String,
+
+    /// Denom the exchange rate is for
+    pub denom: Denom,
+
+    /// Origin of the Feed Msg
+    pub feeder: Address,
+
+    /// Validator voting on behalf of
+    pub validator: Address,
+}
+
+impl MsgExchangeRateVote {
+    /// Simple builder for an `oracle/MsgExchangeRateVote` message
+    pub fn to_stdtx_msg(&self) -> eyre::Result<stdtx::amino::Msg> {
+        Ok(
+            stdtx::amino::msg::Builder::new(&SCHEMA, "oracle/MsgExchangeRateVote")?
+                .decimal("exchange_rate", self.exchange_rate)?
+                .string("salt", &self.salt)?
+                .string("denom", self.denom.to_string())?
+                .acc_address("feeder", self.feeder)?
+                .val_address("validator", self.validator)?
+                .to_msg(),
+        )
+    }
+
+    /// Build a protobuf `/terra.oracle.v1beta1.MsgExchangeRateVote`
+    pub fn to_proto_msg(&self) -> eyre::Result<cosmrs::tx::Msg> {
+        let msg = protos::MsgExchangeRateVote {
+            exchange_rate: String::from_utf8(self.exchange_rate.to_amino_bytes())?,
+            salt: self.salt.clone(),
+            denom: self.denom.to_string(),
+            feeder: self.feeder.to_bech32("terra"),
+            validator: self.validator.to_bech32("terravaloper"),
+        };
+
+        msg.to_msg()
+    }
+
+    /// Compute prevote from this vote
+    pub fn prevote(&self) -> MsgExchangeRatePrevote {
+        MsgExchangeRatePrevote {
+            hash: self.generate_vote_hash(),
+            denom: self.denom.clone(),
+            feeder: self.feeder,
+            validator: self.validator,
+        }
+    }
+
+    /// Generate truncated SHA-256 of vote: `SHA256("{salt}:{rate}:{denom}:{validator}")`
+    fn generate_vote_hash(&self) -> Hash {
+        let data = format!(
+            "{}:{}:{}:{}",
+            self.salt,
+            self.exchange_rate,
+            self.denom,
+            self.validator.to_bech32("terravaloper"),
+        );
+
+        // Tendermint truncated sha256
+        let digest = Sha256::digest(data.as_bytes());
+        Hash::try_from(&digest[..20]).unwrap()
+    }
+}
+
+/// Terra Oracle Prevote Message (`oracle/MsgExchangeRatePrevote`)
+/// <https://docs.terra.money/dev/spec-oracle.html#msgexchangerateprevote>
+///
+/// Legacy per-denom prevote, superseded by [`MsgAggregateExchangeRatePrevote`]
+#[derive(Clone, Debug)]
+pub struct MsgExchangeRatePrevote {
+    /// Commitment to future vote
+    pub hash: Hash,
+
+    /// Denom the vote is for
+    pub denom: Denom,
+
+    /// Origin Address for vote
+    pub feeder: Address,
+
+    /// Validator voting on behalf of
+    pub validator: Address,
+}
+
+impl MsgExchangeRatePrevote {
+    /// Simple builder for an `oracle/MsgExchangeRatePrevote` message
+    pub fn to_stdtx_msg(&self) -> eyre::Result<stdtx::amino::Msg> {
+        Ok(
+            stdtx::amino::msg::Builder::new(&SCHEMA, "oracle/MsgExchangeRatePrevote")?
+                .string("hash", self.hex_hash())?
+                .string("denom", self.denom.to_string())?
+                .acc_address("feeder", self.feeder)?
+                .val_address("validator", self.validator)?
+                .to_msg(),
+        )
+    }
+
+    /// Build a protobuf `/terra.oracle.v1beta1.MsgExchangeRatePrevote`
+    pub fn to_proto_msg(&self) -> eyre::Result<cosmrs::tx::Msg> {
+        let msg = protos::MsgExchangeRatePrevote {
+            hash: self.hex_hash(),
+            denom: self.denom.to_string(),
+            feeder: self.feeder.to_bech32("terra"),
+            validator: self.validator.to_bech32("terravaloper"),
+        };
+
+        msg.to_msg()
+    }
+
+    /// Get the hash as a lower case hex string
+    pub fn hex_hash(&self) -> String {
+        self.hash
+            .iter()
+            .map(|byte| format!("{:02x}", byte))
+            .collect()
+    }
+}
+
+/// Terra Oracle Feeder Delegation Message (`oracle/MsgDelegateFeedConsent`)
+/// <https://docs.terra.money/dev/spec-oracle.html#msgdelegatefeedconsent>
+#[derive(Clone, Debug)]
+pub struct MsgDelegateFeedConsent {
+    /// Validator delegating its oracle votes
+    pub operator: Address,
+
+    /// Feeder allowed to vote on the validator's behalf
+    pub delegate: Address,
+}
+
+impl MsgDelegateFeedConsent {
+    /// Simple builder for an `oracle/MsgDelegateFeedConsent` message
+    pub fn to_stdtx_msg(&self) -> eyre::Result<stdtx::amino::Msg> {
+        Ok(
+            stdtx::amino::msg::Builder::new(&SCHEMA, "oracle/MsgDelegateFeedConsent")?
+                .val_address("operator", self.operator)?
+                .acc_address("delegate", self.delegate)?
+                .to_msg(),
+        )
+    }
+
+    /// Build a protobuf `/terra.oracle.v1beta1.MsgDelegateFeedConsent`
+    pub fn to_proto_msg(&self) -> eyre::Result<cosmrs::tx::Msg> {
+        let msg = protos::MsgDelegateFeedConsent {
+            operator: self.operator.to_bech32("terravaloper"),
+            delegate: self.delegate.to_bech32("terra"),
+        };
+
+        msg.to_msg()
+    }
+}
+
+/// Oracle message in either transaction format
+#[derive(Clone, Debug)]
+pub enum OracleMsg {
+    /// Aggregate exchange rate prevote
+    AggregatePrevote(MsgAggregateExchangeRatePrevote),
+
+    /// Aggregate exchange rate vote
+    AggregateVote(MsgAggregateExchangeRateVote),
+
+    /// Legacy per-denom exchange rate prevote
+    Prevote(MsgExchangeRatePrevote),
+
+    /// Legacy per-denom exchange rate vote
+    Vote(MsgExchangeRateVote),
+
+    /// Feeder delegation
+    DelegateFeedConsent(MsgDelegateFeedConsent),
+
+    /// Messages executed by the feeder on behalf of the validator's account
+    /// via an authz grant (`cosmos-sdk/MsgExec`)
+    Exec {
+        /// Grantee (feeder) executing the messages
+        grantee: Address,
+
+        /// Messages to execute
+        msgs: Vec<OracleMsg>,
+    },
+}
+
+impl OracleMsg {
+    /// Serialize as a legacy amino `StdTx` message
+    pub fn to_stdtx_msg(&self) -> eyre::Result<stdtx::amino::Msg> {
+        match self {
+            OracleMsg::AggregatePrevote(msg) => msg.to_stdtx_msg(),
+            OracleMsg::AggregateVote(msg) => msg.to_stdtx_msg(),
+            OracleMsg::Prevote(msg) => msg.to_stdtx_msg(),
+            OracleMsg::Vote(msg) => msg.to_stdtx_msg(),
+            OracleMsg::DelegateFeedConsent(msg) => msg.to_stdtx_msg(),
+            OracleMsg::Exec { .. } => Err(eyre::eyre!("MsgExec has no StdTx schema")),
+        }
+    }
+
+    /// Serialize as legacy amino JSON
+    pub fn to_amino_json(&self) -> eyre::Result<serde_json::Value> {
+        match self {
+            OracleMsg::Exec { grantee, msgs } => {
+                let msgs = msgs
+                    .iter()
+                    .map(OracleMsg::to_amino_json)
+                    .collect::<eyre::Result<Vec<_>>>()?;
+
+                Ok(json!({
+                    "type": "cosmos-sdk/MsgExec",
+                    "value": {
+                        "grantee": grantee.to_bech32("terra"),
+                        "msgs": msgs,
+                    }
+                }))
+            }
+            _ => Ok(self.to_stdtx_msg()?.to_json_value(&SCHEMA)),
+        }
+    }
+
+    /// Serialize as protobuf JSON (as used by `terrad`), with the type URL
+    /// in the `@type` field
+    pub fn to_proto_json(&self) -> eyre::Result<serde_json::Value> {
+        Ok(match self {
+            OracleMsg::AggregatePrevote(msg) => json!({
+                "@type": protos::MsgAggregateExchangeRatePrevote::TYPE_URL,
+                "hash": msg.hex_hash(),
+                "feeder": msg.feeder.to_bech32("terra"),
+                "validator": msg.validator.to_bech32("terravaloper"),
+            }),
+            OracleMsg::AggregateVote(msg) => json!({
+                "@type": protos::MsgAggregateExchangeRateVote::TYPE_URL,
+                "salt": msg.salt,
+                "exchange_rates": msg.exchange_rates.to_string(),
+                "feeder": msg.feeder.to_bech32("terra"),
+                "validator": msg.validator.to_bech32("terravaloper"),
+            }),
+            OracleMsg::Prevote(msg) => json!({
+                "@type": protos::MsgExchangeRatePrevote::TYPE_URL,
+                "hash": msg.hex_hash(),
+                "denom": msg.denom.to_string(),
+                "feeder": msg.feeder.to_bech32("terra"),
+                "validator": msg.validator.to_bech32("terravaloper"),
+            }),
+            OracleMsg::Vote(msg) => json!({
+                "@type": protos::MsgExchangeRateVote::TYPE_URL,
+                "exchange_rate": msg.exchange_rate.to_string(),
+                "salt": msg.salt,
+                "denom": msg.denom.to_string(),
+                "feeder": msg.feeder.to_bech32("terra"),
+                "validator": msg.validator.to_bech32("terravaloper"),
+            }),
+            OracleMsg::DelegateFeedConsent(msg) => json!({
+                "@type": protos::MsgDelegateFeedConsent::TYPE_URL,
+                "operator": msg.operator.to_bech32("terravaloper"),
+                "delegate": msg.delegate.to_bech32("terra"),
+            }),
+            OracleMsg::Exec { grantee, msgs } => json!({
+                "@type": protos::MsgExec::TYPE_URL,
+                "grantee": grantee.to_bech32("terra"),
+                "msgs": msgs
+                    .iter()
+                    .map(OracleMsg::to_proto_json)
+                    .collect::<eyre::Result<Vec<_>>>()?,
+            }),
+        })
+    }
+
+    /// Serialize as a protobuf `Any`-wrapped message
+    pub fn to_proto_msg(&self) -> eyre::Result<cosmrs::tx::Msg> {
+        match self {
+            OracleMsg::AggregatePrevote(msg) => msg.to_proto_msg(),
+            OracleMsg::AggregateVote(msg) => msg.to_proto_msg(),
+            OracleMsg::Prevote(msg) => msg.to_proto_msg(),
+            OracleMsg::Vote(msg) => msg.to_proto_msg(),
+            OracleMsg::DelegateFeedConsent(msg) => msg.to_proto_msg(),
+            OracleMsg::Exec { grantee, msgs } => {
+                let msgs = msgs
+                    .iter()
+                    .map(|msg| msg.to_proto_msg().map(Into::into))
+                    .collect::<eyre::Result<Vec<_>>>()?;
+
+                let msg = protos::MsgExec {
+                    grantee: grantee.to_bech32("terra"),
+                    msgs,
+                };
+
+                msg.to_msg()
+            }
+        }
+    }
 }
 
 /// Exchange rates
@@ -126,8 +471,8 @@ impl ExchangeRates {
     {
         let mut exchange_rates = ExchangeRates::new();
 
-        for &(denom, rate) in iter {
-            exchange_rates.add(denom, rate)?;
+        for (denom, rate) in iter {
+            exchange_rates.add(denom.clone(), *rate)?;
         }
 
         Ok(exchange_rates)
@@ -135,18 +480,22 @@ impl ExchangeRates {
 
     /// Add an exchange rate
     pub fn add(&mut self, denom: Denom, rate: Decimal) -> Result<(), Error> {
-        let duplicate = self.0.insert(denom, rate).is_some();
-
         ensure!(
-            !duplicate,
+            !self.0.contains_key(&denom),
             ErrorKind::Currency,
             "duplicate exchange rate for denom: {}",
             denom
         );
 
+        self.0.insert(denom, rate);
         Ok(())
     }
 
+    /// Get the exchange rate for a denom
+    pub fn get(&self, denom: &Denom) -> Option<Decimal> {
+        self.0.get(denom).copied()
+    }
+
     /// Iterate over the exchange rates
     pub fn iter(&self) -> map::Iter<'_, Denom, Decimal> {
         self.0.iter()
@@ -167,9 +516,40 @@ impl Display for ExchangeRates {
     }
 }
 
+impl FromStr for ExchangeRates {
+    type Err = Error;
+
+    /// Parse exchange rates in the format produced by `Display`, e.g.
+    /// `362.000000000000000000ukrw,1.000000000000000000uusd`
+    fn from_str(s: &str) -> Result<Self, Error> {
+        let mut exchange_rates = ExchangeRates::new();
+
+        for rate in s.split(',').filter(|rate| !rate.is_empty()) {
+            let denom_start = rate
+                .find(|c: char| c.is_ascii_alphabetic())
+                .ok_or_else(|| {
+                    format_err!(ErrorKind::Parse, "missing denom in exchange rate: {}", rate)
+                })?;
+
+            let (amount, denom) = rate.split_at(denom_start);
+            let amount = amount
+                .parse()
+                .map_err(|e| format_err!(ErrorKind::Parse, "bad exchange rate {}: {}", rate, e))?;
+
+            exchange_rates.add(denom.parse()?, amount)?;
+        }
+
+        Ok(exchange_rates)
+    }
+}
+
 #[cfg(test)]
 mod tests {
-    use super::{Denom, ExchangeRates};
+    use super::{Denom, ExchangeRates, MsgAggregateExchangeRateVote, OracleMsg};
+    use crate::networks::terra::protos;
+    use prost::Message;
+    use sha2::{Digest, Sha256};
+    use stdtx::Address;
 
     #[test]
     fn exchange_rate_to_string() {
@@ -193,4 +573,58 @@ mod tests {
             1.000000000000000000uusd"
         );
     }
+
+    #[test]
+    fn exchange_rates_round_trip() {
+        let serialized_rates = "362.000000000000000000ukrw,1.000000000000000000uusd";
+        let exchange_rates = serialized_rates.parse::<ExchangeRates>().unwrap();
+
+        assert_eq!(
+            exchange_rates.get(&Denom::Ukrw),
+            Some("362".parse().unwrap())
+        );
+        assert_eq!(exchange_rates.to_string(), serialized_rates);
+    }
+
+    #[test]
+    fn per_denom_votes() {
+        let aggregate = MsgAggregateExchangeRateVote {
+            exchange_rates: "362.000000000000000000ukrw,1.000000000000000000uusd"
+                .parse()
+                .unwrap(),
+            salt: "abcd".to_owned(),
+            feeder: Address([1; 20]),
+            validator: Address([2; 20]),
+        };
+
+        let votes = aggregate.per_denom_votes();
+        assert_eq!(votes.len(), 2);
+        assert_eq!(votes[0].denom, Denom::Ukrw);
+        assert_eq!(votes[0].salt, "abcd");
+
+        let validator = Address([2; 20]).to_bech32("terravaloper");
+        let prevote = votes[0].prevote();
+        let digest =
+            Sha256::digest(format!("abcd:362.000000000000000000:ukrw:{}", validator).as_bytes());
+        assert_eq!(&prevote.hash[..], &digest[..20]);
+        assert_eq!(prevote.denom, Denom::Ukrw);
+
+        let prevote_json = OracleMsg::Prevote(prevote.clone()).to_amino_json().unwrap();
+        assert_eq!(prevote_json["type"], "oracle/MsgExchangeRatePrevote");
+        assert_eq!(prevote_json["value"]["hash"], prevote.hex_hash());
+        assert_eq!(prevote_json["value"]["denom"], "ukrw");
+
+        let vote_json = OracleMsg::Vote(votes[1].clone()).to_amino_json().unwrap();
+        assert_eq!(vote_json["type"], "oracle/MsgExchangeRateVote");
+        assert_eq!(vote_json["value"]["exchange_rate"], "1.000000000000000000");
+        assert_eq!(vote_json["value"]["denom"], "uusd");
+        assert_eq!(vote_json["value"]["validator"], validator);
+
+        let vote_proto = protos::MsgExchangeRateVote::decode(
+            &*prost_types::Any::from(votes[1].to_proto_msg().unwrap()).value,
+        )
+        .unwrap();
+        assert_eq!(vote_proto.exchange_rate, "1000000000000000000");
+        assert_eq!(vote_proto.denom, "uusd");
+    }
 }
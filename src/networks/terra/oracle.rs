@@ -1,22 +1,48 @@
 //! Terra exchange rate oracle
 
 use super::{
-    denom::Denom,
-    msg::{self, MsgAggregateExchangeRateVote},
-    MEMO, SCHEMA,
+    circuit_breaker::{merge_last_voted, CircuitBreaker},
+    denom::{Denom, LunaUsd},
+    deviation::DeviationGuard,
+    fee::FeeEstimator,
+    lcd::{self, Account, LcdClient, OracleParams},
+    msg::{self, OracleMsg},
+    sequence::SequenceManager,
+    signer::{Signer, ACCOUNT_PREFIX},
+    sync_guard::{Refusal, SyncGuard},
+    triangulation::Triangulation,
+    tx::{self, FeeGrant},
+    tx_outcome::{Action, Counters, Outcome},
+    voter::{Voter, DEFAULT_CONTEXT},
+    DEFAULT_VOTE_PERIOD, MEMO,
+};
+use crate::{
+    config::{
+        network::{OracleMode, TerraConfig, TxFormat},
+        DelphiConfig,
+    },
+    prelude::*,
+    router::Request,
+    sources::Sources,
+    Currency, Error, ErrorKind, Map,
 };
-use crate::{config::DelphiConfig, prelude::*, router::Request, sources::Sources, Error};
 use futures::future::join_all;
+use rust_decimal::Decimal;
 use serde_json::json;
 use std::{
+    collections::BTreeSet,
     convert::Infallible,
     sync::Arc,
     time::{Duration, Instant},
 };
 use stdtx::address::Address;
 use stdtx::amino::types::StdFee;
-use tendermint_rpc::endpoint::broadcast::tx_commit;
-use tokio::{sync::Mutex, time::timeout};
+use tendermint::{block, Time};
+use tendermint_rpc::endpoint::{broadcast::tx_commit, status::SyncInfo};
+use tokio::{
+    sync::{Mutex, OnceCell},
+    time::timeout,
+};
 use warp::http::StatusCode;
 
 /// Number of seconds to wait for an oracle vote complete
@@ -27,37 +53,227 @@ pub const DEFAULT_TIMEOUT_SECS: u64 = 10;
 pub struct ExchangeRateOracle(Arc<Mutex<OracleState>>);
 
 impl ExchangeRateOracle {
-    /// Create a new [`ExchangeRateOracle`]
-    pub fn new(config: &DelphiConfig) -> Result<Self, Error> {
-        let state = OracleState::new(config)?;
+    /// Create a new [`ExchangeRateOracle`] for the given Terra network,
+    /// using the given (possibly shared) sources
+    pub fn new(
+        config: &DelphiConfig,
+        terra_config: &TerraConfig,
+        sources: Arc<Sources>,
+    ) -> Result<Self, Error> {
+        let state = OracleState::new(config, terra_config, sources)?;
         Ok(ExchangeRateOracle(Arc::new(Mutex::new(state))))
     }
 
+    /// Spawn a task which syncs the oracle params from the LCD (if
+    /// configured) now and then periodically
+    pub async fn spawn_params_sync(&self) {
+        let refresh_interval = {
+            let state = self.0.lock().await;
+
+            match &state.lcd {
+                Some(_) => state.params_refresh,
+                None => return,
+            }
+        };
+
+        let oracle = self.clone();
+
+        tokio::spawn(async move {
+            let mut interval = tokio::time::interval(refresh_interval);
+
+            loop {
+                interval.tick().await;
+                oracle.sync_params().await;
+            }
+        });
+    }
+
+    /// Sync the oracle params (denom whitelist, vote period and reward band)
+    /// from the LCD
+    pub async fn sync_params(&self) {
+        let mut state = self.0.lock().await;
+
+        let result = match &state.lcd {
+            Some(lcd) => timeout(state.timeout, lcd.oracle_params()).await,
+            None => return,
+        };
+
+        match result {
+            Ok(Ok(params)) => state.apply_params(params),
+            Ok(Err(e)) => warn!("error getting oracle params: {}", e),
+            Err(_) => warn!("timed out getting oracle params"),
+        }
+    }
+
+    /// Spawn a task which signs and broadcasts oracle transactions for the
+    /// default voter (if a signer is configured), voting as new blocks arrive
+    pub async fn spawn_signer(&self) {
+        let signer = match self.0.lock().await.signer.take() {
+            Some(signer) => signer,
+            None => return,
+        };
+
+        info!(
+            "signing and broadcasting oracle transactions as {}",
+            signer.address()
+        );
+
+        let oracle = self.clone();
+        tokio::spawn(async move { oracle.run_signer(signer).await });
+    }
+
+    /// Poll the RPC for new blocks, signing and broadcasting oracle
+    /// transactions for each one
+    async fn run_signer(self, signer: Signer) {
+        let mut interval = tokio::time::interval(signer.poll_interval());
+        let mut last_height = None;
+        let mut last_tx_response = None;
+
+        loop {
+            interval.tick().await;
+
+            let status = match signer.status().await {
+                Ok(status) => status,
+                Err(e) => {
+                    warn!("{}", e);
+                    continue;
+                }
+            };
+
+            if last_height == Some(status.latest_block_height) {
+                continue;
+            }
+
+            last_height = Some(status.latest_block_height);
+
+            if let Err(refusal) = self.check_sync(Some(&status)).await {
+                warn!("refusing to vote: {}", refusal);
+                continue;
+            }
+
+            let account = match self.sync_sequence(DEFAULT_CONTEXT).await {
+                Some(account) => account,
+                None => continue,
+            };
+
+            // Kept until the next transaction is broadcast: repeated responses
+            // are ignored once recorded
+            let msgs = self
+                .get_vote_msgs(DEFAULT_CONTEXT, last_tx_response.clone(), Some(status))
+                .await;
+
+            if msgs.is_empty() {
+                continue;
+            }
+
+            match self.sign_and_broadcast(&signer, &msgs, &account).await {
+                Ok(response) => {
+                    self.0
+                        .lock()
+                        .await
+                        .record_tx_response(DEFAULT_CONTEXT, &response);
+
+                    last_tx_response = Some(response);
+                }
+                Err(e) => {
+                    // The transaction may or may not have been accepted
+                    error!("{}", e);
+
+                    if let Some(sequence) = self.0.lock().await.sequence_mut(DEFAULT_CONTEXT) {
+                        sequence.invalidate();
+                    }
+                }
+            }
+        }
+    }
+
+    /// Sync the given voter's feeder account number and sequence from the
+    /// LCD (if configured) when needed, returning the current account
+    async fn sync_sequence(&self, context: &str) -> Option<Account> {
+        let mut state = self.0.lock().await;
+        let voter = state.voters.get(context)?;
+
+        if voter.sequence.needs_sync() {
+            let address = voter.feeder.to_bech32(ACCOUNT_PREFIX);
+
+            let account = match &state.lcd {
+                Some(lcd) => fetch_account(lcd, &address, state.timeout).await,
+                None => None,
+            };
+
+            if let (Some(account), Some(sequence)) = (account, state.sequence_mut(context)) {
+                sequence.sync(account);
+            }
+        }
+
+        state.voters[context].sequence.account()
+    }
+
+    /// Sign a transaction containing the given messages and broadcast it
+    async fn sign_and_broadcast(
+        &self,
+        signer: &Signer,
+        msgs: &[OracleMsg],
+        account: &Account,
+    ) -> Result<tx_commit::Response, Error> {
+        let fee = self.oracle_fee(DEFAULT_CONTEXT, msgs).await;
+
+        let (request, broadcast_timeout) = {
+            let state = self.0.lock().await;
+            let request = tx::signing_request(&state.chain_id, msgs, &fee, &state.fee_grant)?;
+            (request, state.timeout)
+        };
+
+        let tx = signer.sign(request, account)?;
+
+        let response = timeout(broadcast_timeout, signer.broadcast(tx))
+            .await
+            .map_err(|_| format_err!(ErrorKind::Http, "timed out broadcasting transaction"))??;
+
+        info!(
+            "broadcast oracle transaction {} (height {}, sequence {})",
+            response.hash, response.height, account.sequence
+        );
+
+        Ok(response)
+    }
+
     /// Handle an incoming oracle request, providing a set of transactions to
-    /// respond with.
+    /// respond with. The request's context selects the voter.
     pub async fn handle_request(self, req: Request) -> Result<impl warp::Reply, Infallible> {
-        let chain_id = self.get_chain_id().await;
-        let msgs = self.get_vote_msgs(req.last_tx_response).await;
+        if !self.0.lock().await.voters.contains_key(&req.context) {
+            warn!("no voter configured for context: {:?}", req.context);
+
+            return Ok(warp::reply::with_status(
+                warp::reply::json(&json!({
+                    "status": "error",
+                    "error": format!("unknown context: {:?}", req.context),
+                })),
+                StatusCode::NOT_FOUND,
+            ));
+        }
+
+        if let Err(refusal) = self.check_sync(req.status.as_ref()).await {
+            warn!("refusing to vote: {}", refusal);
+
+            return Ok(warp::reply::with_status(
+                warp::reply::json(&json!({"status": "ok", "refused": refusal.to_string()})),
+                StatusCode::OK,
+            ));
+        }
+
+        let msgs = self
+            .get_vote_msgs(&req.context, req.last_tx_response, req.status)
+            .await;
+
+        // Synced after the last transaction is recorded, as the chain's
+        // sequence already reflects it
+        self.sync_sequence(&req.context).await;
 
         let response = if msgs.is_empty() {
             json!({"status": "ok"})
         } else {
-            let msg_json = msgs
-                .iter()
-                .map(|msg| msg.to_json_value(&SCHEMA))
-                .collect::<Vec<_>>();
-
-            let tx = json!({
-                "chain_id": chain_id,
-                "fee": self.oracle_fee().await,
-                "memo": MEMO,
-                "msgs": msg_json,
-            });
-
-            json!({
-                "status": "ok",
-                "tx": tx
-            })
+            self.tx_response(&req.context, &msgs).await
         };
 
         Ok(warp::reply::with_status(
@@ -66,155 +282,992 @@ impl ExchangeRateOracle {
         ))
     }
 
-    /// Get the chain ID
-    async fn get_chain_id(&self) -> String {
+    /// Check whether the node is sufficiently in sync with the chain to vote
+    async fn check_sync(&self, status: Option<&SyncInfo>) -> Result<(), Refusal> {
+        match status {
+            Some(status) => {
+                let state = self.0.lock().await;
+                state.sync_guard.check(status, Time::now())
+            }
+            None => Ok(()),
+        }
+    }
+
+    /// Get the oracle's status
+    pub async fn status(&self) -> serde_json::Value {
+        let state = self.0.lock().await;
+
+        json!({
+            "chain_id": state.chain_id,
+            "vote_period": state.vote_period,
+            "whitelist": state.whitelist,
+            "reward_band": state.reward_band,
+            "excluded_denoms": state.excluded_denoms,
+            "tx_outcomes": state.tx_outcomes.as_map(),
+            "voters": state.voters.values().map(Voter::status).collect::<Vec<_>>(),
+        })
+    }
+
+    /// Build a response containing a transaction for the given voter's
+    /// messages in the configured format
+    async fn tx_response(&self, context: &str, msgs: &[OracleMsg]) -> serde_json::Value {
+        let fee = self.oracle_fee(context, msgs).await;
+        let state = self.0.lock().await;
+
+        match state.tx_format {
+            TxFormat::Amino => {
+                let msg_json = msgs
+                    .iter()
+                    .map(|msg| {
+                        msg.to_amino_json()
+                            .expect("can't serialize vote as amino JSON")
+                    })
+                    .collect::<Vec<_>>();
+
+                let tx = json!({
+                    "chain_id": state.chain_id,
+                    "fee": tx::amino_fee(&fee, &state.fee_grant),
+                    "memo": MEMO,
+                    "msgs": msg_json,
+                });
+
+                json!({
+                    "status": "ok",
+                    "tx": tx
+                })
+            }
+            TxFormat::Protobuf => {
+                let request = tx::signing_request(&state.chain_id, msgs, &fee, &state.fee_grant)
+                    .and_then(|request| tx::encode_signing_request(&request));
+
+                match request {
+                    Ok(request) => json!({
+                        "status": "ok",
+                        "tx_signing_request": request
+                    }),
+                    Err(e) => {
+                        error!("error building transaction signing request: {}", e);
+                        json!({"status": "ok"})
+                    }
+                }
+            }
+        }
+    }
+
+    /// Build an unsigned transaction containing the given voter's oracle vote
+    /// messages in the given format, to be signed offline (e.g. with
+    /// `terrad tx sign`).
+    ///
+    /// The voter's pending vote (in its `state_file`) is revealed on the
+    /// assumption that its prevote (from the previous unsigned transaction)
+    /// was signed and broadcast. If an LCD is configured, it's only revealed if the
+    /// chain is in the vote period immediately after that prevote.
+    pub async fn unsigned_vote_tx(
+        &self,
+        context: &str,
+        format: TxFormat,
+    ) -> Result<serde_json::Value, Error> {
+        self.sync_params().await;
+
+        {
+            let state = self.0.lock().await;
+
+            let voter = state.voters.get(context).ok_or_else(|| {
+                format_err!(
+                    ErrorKind::Config,
+                    "no voter configured for context: {:?}",
+                    context
+                )
+            })?;
+
+            if voter.state_file.is_none() {
+                warn!("no state_file configured; prevotes can't be revealed by a later vote");
+            }
+        }
+
+        let current_period = self.current_period().await?;
+
+        let msgs = self.vote_msgs(context, current_period, true).await;
+
+        ensure!(
+            !msgs.is_empty(),
+            ErrorKind::Source,
+            "couldn't compute an oracle vote"
+        );
+
+        let fee = self.oracle_fee(context, &msgs).await;
+        let state = self.0.lock().await;
+
+        tx::unsigned_tx(format, &msgs, &fee, &state.fee_grant)
+    }
+
+    /// Discard restored votes which can no longer be revealed, if the current
+    /// vote period can be determined from the LCD
+    pub async fn expire_pending_votes(&self) {
+        match self.current_period().await {
+            Ok(Some(current_period)) => {
+                for voter in self.0.lock().await.voters.values_mut() {
+                    voter.expire_pending_vote(current_period);
+                }
+            }
+            Ok(None) => (),
+            Err(e) => warn!("couldn't determine the current vote period: {}", e),
+        }
+    }
+
+    /// Get the current vote period from the LCD's latest block height (if an
+    /// LCD is configured)
+    async fn current_period(&self) -> Result<Option<u64>, Error> {
         let state = self.0.lock().await;
-        state.chain_id.clone()
+
+        let lcd = match &state.lcd {
+            Some(lcd) => lcd,
+            None => return Ok(None),
+        };
+
+        let height = timeout(state.timeout, lcd.latest_height())
+            .await
+            .map_err(|_| format_err!(ErrorKind::Http, "timed out getting height"))??;
+
+        Ok(Some(height / state.vote_period))
     }
 
-    /// Get oracle vote messages
+    /// Get the given voter's oracle vote messages
     async fn get_vote_msgs(
         &self,
+        context: &str,
         last_tx_response: Option<tx_commit::Response>,
-    ) -> Vec<stdtx::amino::Msg> {
-        let started_at = Instant::now();
+        status: Option<SyncInfo>,
+    ) -> Vec<OracleMsg> {
         let mut state = self.0.lock().await;
-        let mut exchange_rates = msg::ExchangeRates::new();
-        let mut exchange_rate_fut = vec![];
+        let current_period = status.map(|status| state.vote_period_at(status.latest_block_height));
 
-        for denom in Denom::kinds() {
-            exchange_rate_fut.push(denom.get_exchange_rate(&state.sources))
+        // Recorded even if the voter has already voted in this period, as
+        // the response may be for its prevote
+        if let Some(response) = &last_tx_response {
+            state.record_tx_response(context, response);
         }
 
-        let rates = match timeout(state.timeout, join_all(exchange_rate_fut)).await {
-            Ok(res) => res,
-            Err(e) => {
-                warn!("oracle vote timed out after {:?}: {}", state.timeout, e);
+        // Only reveal the pending vote if its prevote is known to have
+        // succeeded
+        let reveal = match state.voters.get(context) {
+            // Only send one prevote/vote pair per vote period
+            Some(voter) if voter.has_voted_in(current_period) => {
+                debug!(
+                    "{:?} already voted in vote period {}",
+                    context,
+                    voter.last_vote_period.unwrap_or_default()
+                );
                 return vec![];
             }
+            Some(voter) => voter.prevote_confirmed,
+            None => return vec![],
         };
 
-        for (rate, denom) in rates.iter().zip(Denom::kinds()) {
-            match rate {
-                Ok(rate) => exchange_rates.add(*denom, *rate).expect("duplicate denom"),
-                Err(err) => {
-                    error!("error getting exchange rate for {}: {}", denom, err);
-                    continue;
-                }
-            };
-        }
+        drop(state);
+        self.vote_msgs(context, current_period, reveal).await
+    }
 
-        info!(
-            "voting {} ({:?})",
-            exchange_rates
-                .iter()
-                .map(|(denom, decimal)| format!("{}={}", denom, decimal))
-                .collect::<Vec<_>>()
-                .join(", "),
-            Instant::now().duration_since(started_at)
-        );
+    /// Build the oracle vote messages for the voter with the given context:
+    /// the reveal of its pending vote (if `reveal` is set, i.e. its prevote
+    /// is known to have succeeded) and a new prevote.
+    ///
+    /// The exchange rates are computed once per vote period, without holding
+    /// the state lock, so other voters and status requests aren't blocked.
+    async fn vote_msgs(
+        &self,
+        context: &str,
+        current_period: Option<u64>,
+        reveal: bool,
+    ) -> Vec<OracleMsg> {
+        let (cell, computation) = {
+            let mut state = self.0.lock().await;
+            (state.rates_cell(current_period), state.rate_computation())
+        };
 
-        // Move all previously unrevealed votes into the result
-        let mut msgs = vec![];
+        let exchange_rates = match cell
+            .get_or_try_init(|| self.compute_exchange_rates(computation))
+            .await
+        {
+            Ok(exchange_rates) => exchange_rates.clone(),
+            Err(()) => return vec![],
+        };
 
-        if let Some(vote) = state.unrevealed_vote.take() {
-            // Determine if the last transaction we sent was successful
-            let last_tx_success = last_tx_response
-                .map(|tx| tx.check_tx.code.is_ok() && tx.deliver_tx.code.is_ok())
-                .unwrap_or(false);
+        let mut state = self.0.lock().await;
+        let (oracle_mode, authz) = (state.oracle_mode, state.authz);
 
-            if last_tx_success {
-                // Only include the previous vote if we succeeded in publishing
-                // an oracle prevote. Otherwise DeliverTx fails because we
-                // don't have a corresponding prevote
-                msgs.push(vote);
+        match state.voters.get_mut(context) {
+            // The voter may have voted while the rates were being computed
+            Some(voter) if !voter.has_voted_in(current_period) => {
+                voter.vote_msgs(exchange_rates, current_period, reveal, oracle_mode, authz)
             }
+            _ => vec![],
         }
+    }
+
+    /// Compute the exchange rates to vote, recording them as the last voted
+    /// rates
+    async fn compute_exchange_rates(
+        &self,
+        computation: RateComputation,
+    ) -> Result<msg::ExchangeRates, ()> {
+        let exchange_rates = computation.run().await.ok_or(())?;
+
+        let mut state = self.0.lock().await;
+        state.last_voted = merge_last_voted(&state.last_voted, &exchange_rates);
+
+        Ok(exchange_rates)
+    }
+
+    /// Compute the oracle fee for a transaction containing the given voter's
+    /// messages
+    pub async fn oracle_fee(&self, context: &str, msgs: &[OracleMsg]) -> StdFee {
+        // Simulation requires the account sequence
+        let account = self.sync_sequence(context).await;
+        let state = self.0.lock().await;
+
+        let estimator = match &state.fee_estimator {
+            Some(estimator) => estimator,
+            None => return state.voters[context].fee.current().clone(),
+        };
 
-        let vote_msg = MsgAggregateExchangeRateVote {
-            exchange_rates,
-            salt: MsgAggregateExchangeRateVote::random_salt(),
-            feeder: state.feeder,
-            validator: state.validator,
+        let simulated_gas = match (&state.lcd, account) {
+            (Some(lcd), Some(account)) if estimator.simulate() => {
+                simulate_gas(lcd, &state.chain_id, msgs, &account, state.timeout).await
+            }
+            _ => None,
         };
 
-        let prevote_msg_stdtx = vote_msg
-            .prevote()
-            .to_stdtx_msg()
-            .expect("can't serialize vote as stdtx");
+        let gas = simulated_gas.unwrap_or_else(|| estimator.model_gas(msgs));
+        let voter_fee = &state.voters[context].fee;
+        let fee = estimator.fee(gas, voter_fee.current(), voter_fee.configured());
+        debug!("estimated {} gas; fee {:?}", gas, fee.amount);
+        fee
+    }
+}
 
-        msgs.push(prevote_msg_stdtx);
+/// Fetch the current on-chain exchange rates, logging any errors
+async fn fetch_on_chain_rates(
+    lcd: &LcdClient,
+    timeout_duration: Duration,
+) -> Option<Map<Denom, lcd::Quote>> {
+    match timeout(timeout_duration, lcd.oracle_quotes()).await {
+        Ok(Ok(quotes)) => Some(quotes),
+        Ok(Err(e)) => {
+            warn!("error getting on-chain exchange rates: {}", e);
+            None
+        }
+        Err(_) => {
+            warn!("timed out getting on-chain exchange rates");
+            None
+        }
+    }
+}
 
-        let vote_msg_stdtx = vote_msg
-            .to_stdtx_msg()
-            .expect("can't serialize vote as stdtx");
+/// Fetch an account's number and sequence, logging any errors
+async fn fetch_account(
+    lcd: &LcdClient,
+    address: &str,
+    timeout_duration: Duration,
+) -> Option<Account> {
+    match timeout(timeout_duration, lcd.account(address)).await {
+        Ok(Ok(account)) => Some(account),
+        Ok(Err(e)) => {
+            warn!("error getting account {}: {}", address, e);
+            None
+        }
+        Err(_) => {
+            warn!("timed out getting account {}", address);
+            None
+        }
+    }
+}
 
-        state.unrevealed_vote = Some(vote_msg_stdtx);
+/// Simulate a transaction containing the given messages, returning the gas
+/// it used (logging any errors)
+async fn simulate_gas(
+    lcd: &LcdClient,
+    chain_id: &str,
+    msgs: &[OracleMsg],
+    account: &Account,
+    timeout_duration: Duration,
+) -> Option<u64> {
+    // The fee doesn't affect the gas used by oracle messages
+    let tx = tx::signing_request(chain_id, msgs, &StdFee::default(), &FeeGrant::default())
+        .and_then(|request| tx::simulation_tx(request, account.sequence));
 
-        msgs
+    let result = match tx {
+        Ok(tx) => timeout(timeout_duration, lcd.simulate(&tx)).await,
+        Err(e) => {
+            warn!("error building transaction for simulation: {}", e);
+            return None;
+        }
+    };
+
+    match result {
+        Ok(Ok(gas)) => Some(gas),
+        Ok(Err(e)) => {
+            warn!("error simulating transaction: {}", e);
+            None
+        }
+        Err(_) => {
+            warn!("timed out simulating transaction");
+            None
+        }
     }
+}
 
-    /// Compute the oracle fee
-    pub async fn oracle_fee(&self) -> StdFee {
-        let state = self.0.lock().await;
-        state.fee.clone()
+/// Log how the computed rates compare to the current on-chain rates
+fn log_reference_rates(exchange_rates: &msg::ExchangeRates, on_chain: &Map<Denom, lcd::Quote>) {
+    for (denom, rate) in exchange_rates.iter() {
+        if let Some(quote) = on_chain.get(denom) {
+            debug!(
+                "{}: computed {}, on-chain {} (height {})",
+                denom, rate, quote.price, quote.height
+            );
+        }
     }
 }
 
+/// Parse a Bech32 account address
+fn parse_account(address: &str) -> Result<String, Error> {
+    let (prefix, _) = Address::from_bech32(address)
+        .map_err(|e| format_err!(ErrorKind::Config, "invalid address {}: {}", address, e))?;
+
+    ensure!(
+        prefix == ACCOUNT_PREFIX,
+        ErrorKind::Config,
+        "expected a {} account address: {}",
+        ACCOUNT_PREFIX,
+        address
+    );
+
+    Ok(address.to_owned())
+}
+
 /// Inner (synchronized) oracle state
 struct OracleState {
     /// Chain ID
     chain_id: String,
 
-    /// Feeder address
-    feeder: Address,
+    /// Feeder/validator identities and their vote state, by request context
+    voters: Map<String, Voter>,
+
+    /// Exchange rates for the current vote period (shared by all voters),
+    /// computed without holding the state lock
+    period_rates: Option<(u64, Arc<OnceCell<msg::ExchangeRates>>)>,
 
-    /// Validator address
-    validator: Address,
+    /// Transaction format
+    tx_format: TxFormat,
 
-    /// Fee
-    fee: StdFee,
+    /// Aggregate or per-denom oracle messages
+    oracle_mode: OracleMode,
 
-    /// Sources
-    sources: Sources,
+    /// Fee estimator (if configured)
+    fee_estimator: Option<FeeEstimator>,
+
+    /// Accounts paying the fee, if not the feeder
+    fee_grant: FeeGrant,
+
+    /// Wrap oracle messages in an authz `MsgExec`
+    authz: bool,
+
+    /// Number of times each transaction outcome has been seen
+    tx_outcomes: Counters,
+
+    /// Denoms the chain has rejected as unknown
+    excluded_denoms: BTreeSet<Denom>,
+
+    /// Denoms whitelisted on-chain (if synced from the LCD)
+    whitelist: Option<Vec<Denom>>,
+
+    /// Reward band (if synced from the LCD)
+    reward_band: Option<Decimal>,
+
+    /// Interval at which to re-sync the oracle params from the LCD
+    params_refresh: Duration,
+
+    /// Sources (shared between networks)
+    sources: Arc<Sources>,
 
     /// Timeout
     timeout: Duration,
 
-    /// Previously unrevealed vote
-    unrevealed_vote: Option<stdtx::amino::Msg>,
+    /// Guard against voting while out of sync with the chain
+    sync_guard: SyncGuard,
+
+    /// Number of blocks in a vote period
+    vote_period: u64,
+
+    /// Terra LCD client for on-chain reference rates (if configured)
+    lcd: Option<Arc<LcdClient>>,
+
+    /// Guard against voting rates far from the on-chain rates
+    deviation_guard: Option<DeviationGuard>,
+
+    /// Limit on how far rates may move between vote periods
+    circuit_breaker: Option<CircuitBreaker>,
+
+    /// Consistency checks between cross rates and FX quotes
+    triangulation: Option<Triangulation>,
+
+    /// Last voted exchange rates (excluding abstentions)
+    last_voted: msg::ExchangeRates,
+
+    /// Standalone signer for the default voter (until its task is spawned)
+    signer: Option<Signer>,
 }
 
 impl OracleState {
     /// Initialize oracle state
-    fn new(config: &DelphiConfig) -> Result<Self, Error> {
-        let terra_config = config
-            .network
-            .terra
-            .as_ref()
-            .expect("missing [networks.terra] config");
+    fn new(
+        config: &DelphiConfig,
+        terra_config: &TerraConfig,
+        sources: Arc<Sources>,
+    ) -> Result<Self, Error> {
+        let fee = StdFee::from(&terra_config.fee);
+        let mut voters = Map::new();
 
-        let feeder = Address::from_bech32(&terra_config.feeder)
-            .expect("invalid terra feeder config")
-            .1;
+        for voter_config in terra_config.voter_configs() {
+            let voter = Voter::new(&voter_config, &fee)?;
 
-        let validator = Address::from_bech32(&terra_config.validator)
-            .expect("invalid terra validator config")
-            .1;
+            ensure!(
+                voters.insert(voter_config.context.clone(), voter).is_none(),
+                ErrorKind::Config,
+                "[network.terra] multiple voters with context {:?}",
+                voter_config.context
+            );
+        }
 
-        let fee = StdFee::from(&terra_config.fee);
-        let sources = Sources::new(config)?;
         let timeout =
             Duration::from_secs(terra_config.timeout_secs.unwrap_or(DEFAULT_TIMEOUT_SECS));
 
+        let vote_period = terra_config.vote_period.unwrap_or(DEFAULT_VOTE_PERIOD);
+
+        ensure!(
+            vote_period > 0,
+            ErrorKind::Config,
+            "[network.terra] vote_period must be at least 1"
+        );
+
+        let params_refresh = Duration::from_secs(
+            terra_config
+                .lcd
+                .as_ref()
+                .map(|lcd_config| lcd_config.params_refresh_secs)
+                .unwrap_or_default()
+                .max(1),
+        );
+
+        let lcd = terra_config
+            .lcd
+            .as_ref()
+            .map(|lcd_config| LcdClient::new(lcd_config, &config.https).map(Arc::new))
+            .transpose()?;
+
+        ensure!(
+            terra_config.deviation_guard.is_none() || lcd.is_some(),
+            ErrorKind::Config,
+            "[network.terra] deviation_guard requires an LCD to be configured"
+        );
+
+        let deviation_guard = terra_config
+            .deviation_guard
+            .as_ref()
+            .map(DeviationGuard::new);
+
+        let circuit_breaker = terra_config
+            .circuit_breaker
+            .as_ref()
+            .map(CircuitBreaker::new);
+
+        let triangulation = terra_config.triangulation.as_ref().map(Triangulation::new);
+
+        let fee_estimator = terra_config.fee_estimation.as_ref().map(FeeEstimator::new);
+
+        ensure!(
+            !fee_estimator
+                .as_ref()
+                .map(FeeEstimator::simulate)
+                .unwrap_or(false)
+                || lcd.is_some(),
+            ErrorKind::Config,
+            "[network.terra] gas simulation requires an LCD to be configured"
+        );
+
+        let fee_grant = FeeGrant {
+            payer: terra_config
+                .fee_payer
+                .as_deref()
+                .map(parse_account)
+                .transpose()?,
+            granter: terra_config
+                .fee_granter
+                .as_deref()
+                .map(parse_account)
+                .transpose()?,
+        };
+
+        if terra_config.signer.is_some() {
+            ensure!(
+                fee_grant.payer.is_none() || fee_grant.payer == Some(terra_config.feeder.clone()),
+                ErrorKind::Config,
+                "[network.terra] signer can't sign on behalf of a separate fee_payer"
+            );
+
+            ensure!(
+                terra_config.tx_format == TxFormat::Protobuf,
+                ErrorKind::Config,
+                "[network.terra] signer requires tx_format = \"protobuf\""
+            );
+
+            ensure!(
+                lcd.is_some(),
+                ErrorKind::Config,
+                "[network.terra] signer requires an LCD to be configured"
+            );
+        }
+
+        let signer = terra_config
+            .signer
+            .as_ref()
+            .map(|signer_config| Signer::new(signer_config, &voters[DEFAULT_CONTEXT].feeder))
+            .transpose()?;
+
         Ok(Self {
             chain_id: terra_config.chain_id.to_owned(),
-            feeder,
-            validator,
-            fee,
+            voters,
+            period_rates: None,
+            fee_estimator,
+            fee_grant,
+            authz: terra_config.authz,
+            tx_format: terra_config.tx_format,
+            oracle_mode: terra_config.oracle_mode,
+            tx_outcomes: Counters::default(),
+            excluded_denoms: BTreeSet::new(),
+            whitelist: None,
+            reward_band: None,
+            params_refresh,
             sources,
             timeout,
-            unrevealed_vote: None,
+            sync_guard: SyncGuard::new(&terra_config.sync_guard),
+            vote_period,
+            lcd,
+            deviation_guard,
+            circuit_breaker,
+            triangulation,
+            last_voted: msg::ExchangeRates::new(),
+            signer,
         })
     }
+
+    /// Apply oracle params synced from the LCD
+    fn apply_params(&mut self, params: OracleParams) {
+        if params.vote_period != self.vote_period {
+            warn!(
+                "on-chain vote period ({} blocks) differs from configured ({} blocks); using on-chain",
+                params.vote_period, self.vote_period
+            );
+            self.vote_period = params.vote_period;
+        }
+
+        if self.reward_band != Some(params.reward_band) {
+            info!("oracle reward band: {}", params.reward_band);
+            self.reward_band = Some(params.reward_band);
+        }
+
+        if self.whitelist.as_ref() == Some(&params.whitelist) {
+            return;
+        }
+
+        for denom in &params.whitelist {
+            if !Denom::kinds().contains(denom) {
+                warn!(
+                    "{} is whitelisted but has no dedicated pricing; trying LUNA/USD × USD/{}",
+                    denom,
+                    Currency::from(denom)
+                );
+            }
+        }
+
+        for denom in Denom::kinds() {
+            if !params.whitelist.contains(denom) {
+                info!("{} is not whitelisted; not voting on it", denom);
+            }
+        }
+
+        // The chain may have (re-)added denoms it previously rejected
+        self.excluded_denoms
+            .retain(|denom| !params.whitelist.contains(denom));
+
+        info!(
+            "oracle whitelist: {}",
+            params
+                .whitelist
+                .iter()
+                .map(Denom::as_str)
+                .collect::<Vec<_>>()
+                .join(", ")
+        );
+
+        self.whitelist = Some(params.whitelist);
+    }
+
+    /// Record the response to the last transaction sent by the voter with the
+    /// given context and act on its outcome. Responses which were already
+    /// recorded are ignored.
+    fn record_tx_response(&mut self, context: &str, response: &tx_commit::Response) {
+        let recorded = self
+            .sequence_mut(context)
+            .map(|sequence| sequence.record(response));
+
+        if recorded != Some(true) {
+            return;
+        }
+
+        let voted = self
+            .last_voted
+            .iter()
+            .map(|(denom, _)| denom.clone())
+            .collect::<Vec<_>>();
+
+        self.handle_tx_outcome(context, Outcome::classify(response, &voted));
+    }
+
+    /// Act on the outcome of the last transaction sent by the voter with the
+    /// given context, noting whether its prevote succeeded
+    fn handle_tx_outcome(&mut self, context: &str, outcome: Outcome) {
+        self.tx_outcomes.record(&outcome);
+
+        let voter = match self.voters.get_mut(context) {
+            Some(voter) => voter,
+            None => return,
+        };
+
+        voter.prevote_confirmed = outcome == Outcome::Success;
+        let fee = &mut voter.fee;
+
+        if outcome == Outcome::Success {
+            fee.record_success();
+            return;
+        }
+
+        fee.record_failure();
+
+        warn!(
+            "last oracle transaction failed: {} (seen {} times)",
+            outcome,
+            self.tx_outcomes.get(&outcome)
+        );
+
+        match outcome.action() {
+            Action::None => (),
+            Action::BumpGas => fee.bump_gas(),
+            Action::RaiseFee => fee.raise_fee(),
+            Action::ResendPrevote => {
+                info!("skipping reveal; sending a new prevote");
+            }
+            Action::RemoveDenom(denom) => {
+                warn!(
+                    "chain rejected {} as unknown; no longer voting on it",
+                    denom
+                );
+                self.excluded_denoms.insert(denom);
+            }
+        }
+    }
+
+    /// Get the feeder account sequence of the voter with the given context
+    fn sequence_mut(&mut self, context: &str) -> Option<&mut SequenceManager> {
+        self.voters
+            .get_mut(context)
+            .map(|voter| &mut voter.sequence)
+    }
+
+    /// Get the cell holding the exchange rates for the given vote period,
+    /// shared by all voters (a new one if the period is unknown)
+    fn rates_cell(&mut self, current_period: Option<u64>) -> Arc<OnceCell<msg::ExchangeRates>> {
+        if let (Some(current), Some((period, cell))) = (current_period, &self.period_rates) {
+            if current == *period {
+                return cell.clone();
+            }
+        }
+
+        let cell = Arc::new(OnceCell::new());
+
+        if let Some(period) = current_period {
+            self.period_rates = Some((period, cell.clone()));
+        }
+
+        cell
+    }
+
+    /// Snapshot everything needed to compute the exchange rates to vote
+    fn rate_computation(&self) -> RateComputation {
+        let denoms = self
+            .whitelist
+            .as_deref()
+            .unwrap_or_else(|| Denom::kinds())
+            .iter()
+            .filter(|denom| !self.excluded_denoms.contains(denom))
+            .cloned()
+            .collect();
+
+        RateComputation {
+            denoms,
+            sources: self.sources.clone(),
+            timeout: self.timeout,
+            lcd: self.lcd.clone(),
+            deviation_guard: self.deviation_guard.clone(),
+            circuit_breaker: self.circuit_breaker.clone(),
+            triangulation: self.triangulation.clone(),
+            last_voted: self.last_voted.clone(),
+        }
+    }
+
+    /// Compute the vote period for a given block height
+    fn vote_period_at(&self, height: block::Height) -> u64 {
+        height.value() / self.vote_period
+    }
+}
+
+/// Snapshot of the oracle state needed to compute the exchange rates to vote,
+/// so they can be computed without holding the state lock
+struct RateComputation {
+    /// Denoms to vote on
+    denoms: Vec<Denom>,
+
+    /// Sources (shared between networks)
+    sources: Arc<Sources>,
+
+    /// Timeout
+    timeout: Duration,
+
+    /// Terra LCD client for on-chain reference rates (if configured)
+    lcd: Option<Arc<LcdClient>>,
+
+    /// Guard against voting rates far from the on-chain rates
+    deviation_guard: Option<DeviationGuard>,
+
+    /// Limit on how far rates may move between vote periods
+    circuit_breaker: Option<CircuitBreaker>,
+
+    /// Consistency checks between cross rates and FX quotes
+    triangulation: Option<Triangulation>,
+
+    /// Last voted exchange rates (excluding abstentions)
+    last_voted: msg::ExchangeRates,
+}
+
+impl RateComputation {
+    /// Compute the exchange rates to vote
+    async fn run(&self) -> Option<msg::ExchangeRates> {
+        let started_at = Instant::now();
+
+        let luna_usd = LunaUsd::new();
+        let mut exchange_rates = msg::ExchangeRates::new();
+        let mut exchange_rate_fut = vec![];
+
+        for denom in &self.denoms {
+            exchange_rate_fut.push(denom.get_exchange_rate(&self.sources, &luna_usd))
+        }
+
+        let rates = match timeout(self.timeout, join_all(exchange_rate_fut)).await {
+            Ok(res) => res,
+            Err(e) => {
+                warn!("oracle vote timed out after {:?}: {}", self.timeout, e);
+                return None;
+            }
+        };
+
+        for (rate, denom) in rates.iter().zip(&self.denoms) {
+            match rate {
+                Ok(rate) => exchange_rates
+                    .add(denom.clone(), *rate)
+                    .expect("duplicate denom"),
+                Err(err) => {
+                    error!("error getting exchange rate for {}: {}", denom, err);
+                    continue;
+                }
+            };
+        }
+
+        if let Some(triangulation) = &self.triangulation {
+            exchange_rates = triangulation
+                .apply(&exchange_rates, &self.sources, self.timeout)
+                .await;
+        }
+
+        if let Some(breaker) = &self.circuit_breaker {
+            exchange_rates = breaker
+                .apply(
+                    &exchange_rates,
+                    &self.last_voted,
+                    &self.sources,
+                    self.timeout,
+                )
+                .await;
+        }
+
+        if let Some(lcd) = &self.lcd {
+            if let Some(on_chain) = fetch_on_chain_rates(lcd, self.timeout).await {
+                log_reference_rates(&exchange_rates, &on_chain);
+
+                if let Some(guard) = &self.deviation_guard {
+                    exchange_rates = guard
+                        .apply(&exchange_rates, &on_chain, &self.sources, self.timeout)
+                        .await;
+                }
+            }
+        }
+
+        info!(
+            "voting {} ({:?})",
+            exchange_rates
+                .iter()
+                .map(|(denom, decimal)| format!("{}={}", denom, decimal))
+                .collect::<Vec<_>>()
+                .join(", "),
+            Instant::now().duration_since(started_at)
+        );
+
+        Some(exchange_rates)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::ExchangeRateOracle;
+    use crate::{
+        config::{network::TerraConfig, source::CurrencylayerConfig, DelphiConfig},
+        networks::terra::{msg::OracleMsg, voter::DEFAULT_CONTEXT},
+        sources::Sources,
+    };
+    use serde_json::json;
+    use std::{sync::Arc, time::Duration};
+    use stdtx::Address;
+    use tendermint_rpc::endpoint::{broadcast::tx_commit, status::SyncInfo};
+    use tokio::sync::OnceCell;
+
+    fn oracle() -> ExchangeRateOracle {
+        let mut config = DelphiConfig::default();
+        config.source.currencylayer = Some(CurrencylayerConfig {
+            access_key: "demo".to_owned(),
+        });
+
+        let terra_config = TerraConfig {
+            chain_id: "columbus-5".to_owned(),
+            feeder: Address([1; 20]).to_bech32("terra"),
+            validator: Address([2; 20]).to_bech32("terravaloper"),
+            vote_period: Some(5),
+            ..Default::default()
+        };
+
+        let sources = Arc::new(Sources::new(&config).unwrap());
+        ExchangeRateOracle::new(&config, &terra_config, sources).unwrap()
+    }
+
+    fn status(height: u64) -> Option<SyncInfo> {
+        serde_json::from_value(json!({
+            "latest_block_hash": "",
+            "latest_app_hash": "",
+            "latest_block_height": height.to_string(),
+            "latest_block_time": "2021-10-01T12:00:00Z",
+            "catching_up": false
+        }))
+        .unwrap()
+    }
+
+    fn success(hash: u8) -> tx_commit::Response {
+        let result = json!({
+            "code": 0,
+            "data": null,
+            "log": "",
+            "info": "",
+            "gas_wanted": "200000",
+            "gas_used": "100000",
+            "events": [],
+            "codespace": ""
+        });
+
+        serde_json::from_value(json!({
+            "check_tx": result,
+            "deliver_tx": result,
+            "hash": format!("{:064X}", hash),
+            "height": "10"
+        }))
+        .unwrap()
+    }
+
+    /// Use fixed exchange rates for the given vote period
+    async fn set_period_rates(oracle: &ExchangeRateOracle, period: u64) {
+        let rates = "362.000000000000000000ukrw".parse().unwrap();
+        let cell = Arc::new(OnceCell::new_with(Some(rates)));
+        oracle.0.lock().await.period_rates = Some((period, cell));
+    }
+
+    #[tokio::test]
+    async fn reveals_prevote_confirmed_earlier_in_period() {
+        let oracle = oracle();
+
+        // First block of vote period 2: prevote only
+        set_period_rates(&oracle, 2).await;
+        let msgs = oracle
+            .get_vote_msgs(DEFAULT_CONTEXT, None, status(10))
+            .await;
+        assert!(matches!(msgs.as_slice(), [OracleMsg::AggregatePrevote(_)]));
+
+        // Next block in the same period carries the prevote's response
+        let msgs = oracle
+            .get_vote_msgs(DEFAULT_CONTEXT, Some(success(1)), status(11))
+            .await;
+        assert!(msgs.is_empty());
+
+        // First block of the next period: the response was already consumed,
+        // but the prevote is still known to have succeeded
+        set_period_rates(&oracle, 3).await;
+        let msgs = oracle
+            .get_vote_msgs(DEFAULT_CONTEXT, None, status(15))
+            .await;
+        assert!(matches!(
+            msgs.as_slice(),
+            [OracleMsg::AggregateVote(_), OracleMsg::AggregatePrevote(_)]
+        ));
+    }
+
+    #[tokio::test]
+    async fn rates_computed_without_holding_state_lock() {
+        let oracle = oracle();
+        let (tx, rx) = tokio::sync::oneshot::channel::<()>();
+
+        // Rates for vote period 2 which take a while to compute
+        let cell = Arc::new(OnceCell::new());
+        oracle.0.lock().await.period_rates = Some((2, cell.clone()));
+
+        tokio::spawn(async move {
+            cell.get_or_init(|| async {
+                rx.await.ok();
+                "362.000000000000000000ukrw".parse().unwrap()
+            })
+            .await;
+        });
+
+        let voting = {
+            let oracle = oracle.clone();
+            tokio::spawn(async move {
+                oracle
+                    .get_vote_msgs(DEFAULT_CONTEXT, None, status(10))
+                    .await
+            })
+        };
+
+        // The oracle's status is available while the voter waits for rates
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        let status = tokio::time::timeout(Duration::from_secs(1), oracle.status()).await;
+        assert!(status.is_ok());
+
+        tx.send(()).unwrap();
+        let msgs = voting.await.unwrap();
+        assert!(matches!(msgs.as_slice(), [OracleMsg::AggregatePrevote(_)]));
+    }
 }
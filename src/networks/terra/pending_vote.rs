@@ -0,0 +1,218 @@
+//! Pending (unrevealed) votes
+//!
+//! The vote committed to by a prevote has to be revealed in the following
+//! vote period. To survive a restart in between, the pending vote is
+//! persisted to a local state file which is reloaded at startup.
+
+use super::{
+    msg::{ExchangeRates, MsgAggregateExchangeRateVote},
+    parse_address,
+};
+use crate::{prelude::*, Error, ErrorKind};
+use serde::{Deserialize, Serialize};
+use std::{
+    fs::{self, OpenOptions, Permissions},
+    io::{self, Write},
+    os::unix::fs::{OpenOptionsExt, PermissionsExt},
+    path::Path,
+};
+
+/// Vote which has been committed to with a prevote but not yet revealed
+#[derive(Clone, Debug)]
+pub struct PendingVote {
+    /// Vote period in which the vote is to be revealed (if known)
+    pub vote_period: Option<u64>,
+
+    /// Vote message
+    pub vote: MsgAggregateExchangeRateVote,
+}
+
+impl PendingVote {
+    /// Can this vote be revealed in the current vote period? Votes can only
+    /// be revealed in the period immediately after their prevote, so votes
+    /// with an unknown vote period are never revealable.
+    pub fn is_revealable(&self, current_period: u64) -> bool {
+        self.vote_period == Some(current_period)
+    }
+
+    /// Load a pending vote from the given state file, if it exists
+    pub fn load(path: &Path) -> Result<Option<Self>, Error> {
+        let json = match fs::read_to_string(path) {
+            Ok(json) => json,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(None),
+            Err(e) => return Err(e.into()),
+        };
+
+        let state: StateFile = serde_json::from_str(&json)?;
+        state.into_pending_vote().map(Some)
+    }
+
+    /// Save this pending vote to the given state file, atomically replacing
+    /// any previous contents. The file is only readable by its owner (even if
+    /// a temporary file was left behind with other permissions).
+    pub fn save(&self, path: &Path) -> Result<(), Error> {
+        let json = serde_json::to_string_pretty(&StateFile::from(self))?;
+        let tmp_path = path.with_extension("tmp");
+
+        let mut file = OpenOptions::new()
+            .write(true)
+            .create(true)
+            .truncate(true)
+            .mode(0o600)
+            .open(&tmp_path)?;
+
+        // `mode` only applies when the file is created
+        file.set_permissions(Permissions::from_mode(0o600))?;
+        file.write_all(json.as_bytes())?;
+        file.sync_all()?;
+        fs::rename(&tmp_path, path)?;
+        Ok(())
+    }
+
+    /// Remove the state file (if it exists)
+    pub fn remove(path: &Path) -> Result<(), Error> {
+        match fs::remove_file(path) {
+            Err(e) if e.kind() != io::ErrorKind::NotFound => Err(e.into()),
+            _ => Ok(()),
+        }
+    }
+}
+
+/// Serialized state file contents
+#[derive(Clone, Debug, Deserialize, Serialize)]
+struct StateFile {
+    /// Vote period in which the vote is to be revealed
+    vote_period: Option<u64>,
+
+    /// Salt for commit reveal protocol
+    salt: String,
+
+    /// Exchange rates (in `ExchangeRates` string form)
+    exchange_rates: String,
+
+    /// Hex-encoded prevote hash
+    hash: String,
+
+    /// Feeder address (Bech32)
+    feeder: String,
+
+    /// Validator address (Bech32)
+    validator: String,
+}
+
+impl StateFile {
+    /// Reconstruct the pending vote, checking it against the saved hash
+    fn into_pending_vote(self) -> Result<PendingVote, Error> {
+        let feeder = parse_address(&self.feeder)?;
+        let validator = parse_address(&self.validator)?;
+
+        let vote = MsgAggregateExchangeRateVote {
+            exchange_rates: self.exchange_rates.parse::<ExchangeRates>()?,
+            salt: self.salt,
+            feeder,
+            validator,
+        };
+
+        ensure!(
+            hex_hash(&vote) == self.hash,
+            ErrorKind::Parse,
+            "pending vote doesn't match its prevote hash: {}",
+            self.hash
+        );
+
+        Ok(PendingVote {
+            vote_period: self.vote_period,
+            vote,
+        })
+    }
+}
+
+impl From<&PendingVote> for StateFile {
+    fn from(pending: &PendingVote) -> StateFile {
+        let vote = &pending.vote;
+
+        StateFile {
+            vote_period: pending.vote_period,
+            salt: vote.salt.clone(),
+            exchange_rates: vote.exchange_rates.to_string(),
+            hash: hex_hash(vote),
+            feeder: vote.feeder.to_bech32("terra"),
+            validator: vote.validator.to_bech32("terravaloper"),
+        }
+    }
+}
+
+/// Hex-encoded prevote hash for a vote
+fn hex_hash(vote: &MsgAggregateExchangeRateVote) -> String {
+    vote.prevote().hex_hash()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::PendingVote;
+    use crate::networks::terra::msg::MsgAggregateExchangeRateVote;
+    use std::{fs, os::unix::fs::PermissionsExt};
+    use stdtx::Address;
+
+    fn pending_vote() -> PendingVote {
+        PendingVote {
+            vote_period: Some(42),
+            vote: MsgAggregateExchangeRateVote {
+                exchange_rates: "362.000000000000000000ukrw,1.000000000000000000uusd"
+                    .parse()
+                    .unwrap(),
+                salt: "abcd".to_owned(),
+                feeder: Address([1; 20]),
+                validator: Address([2; 20]),
+            },
+        }
+    }
+
+    #[test]
+    fn save_and_load() {
+        let dir = std::env::temp_dir().join(format!("delphi-pending-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("vote.json");
+
+        assert!(PendingVote::load(&path).unwrap().is_none());
+
+        let pending = pending_vote();
+        pending.save(&path).unwrap();
+
+        let mode = fs::metadata(&path).unwrap().permissions().mode();
+        assert_eq!(mode & 0o777, 0o600);
+
+        // A leftover temporary file doesn't keep its permissions
+        let tmp_path = path.with_extension("tmp");
+        fs::write(&tmp_path, "").unwrap();
+        fs::set_permissions(&tmp_path, fs::Permissions::from_mode(0o644)).unwrap();
+        pending.save(&path).unwrap();
+
+        let mode = fs::metadata(&path).unwrap().permissions().mode();
+        assert_eq!(mode & 0o777, 0o600);
+
+        let loaded = PendingVote::load(&path).unwrap().unwrap();
+        assert_eq!(loaded.vote_period, Some(42));
+        assert_eq!(loaded.vote.salt, "abcd");
+        assert_eq!(loaded.vote.exchange_rates, pending.vote.exchange_rates);
+        assert_eq!(loaded.vote.prevote().hash, pending.vote.prevote().hash);
+
+        PendingVote::remove(&path).unwrap();
+        assert!(PendingVote::load(&path).unwrap().is_none());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn revealable_only_in_target_period() {
+        let pending = pending_vote();
+        assert!(!pending.is_revealable(41));
+        assert!(pending.is_revealable(42));
+        assert!(!pending.is_revealable(43));
+
+        let unknown_period = PendingVote {
+            vote_period: None,
+            ..pending
+        };
+        assert!(!unknown_period.is_revealable(42));
+    }
+}
@@ -3,6 +3,10 @@
 //! Protobuf definitions:
 //! <https://github.com/terra-money/core/blob/main/proto/terra/oracle/v1beta1/oracle.proto>
 //! <https://github.com/terra-money/core/blob/main/proto/terra/oracle/v1beta1/tx.proto>
+//!
+//! Also includes the authz `MsgExec`, which isn't in the Cosmos SDK protos
+//! bundled with `cosmrs`:
+//! <https://github.com/cosmos/cosmos-sdk/blob/v0.44.0/proto/cosmos/authz/v1beta1/tx.proto>
 
 use cosmrs::tx::MsgProto;
 use prost::Message;
@@ -76,6 +80,10 @@ pub struct MsgAggregateExchangeRatePrevote {
     pub validator: String,
 }
 
+impl MsgProto for MsgAggregateExchangeRatePrevote {
+    const TYPE_URL: &'static str = "/terra.oracle.v1beta1.MsgAggregateExchangeRatePrevote";
+}
+
 /// MsgAggregateExchangeRateVote - struct for message to submit aggregate exhcnage rate vote.
 #[derive(Clone, PartialEq, Message)]
 pub struct MsgAggregateExchangeRateVote {
@@ -96,6 +104,64 @@ pub struct MsgAggregateExchangeRateVote {
     pub validator: String,
 }
 
+impl MsgProto for MsgAggregateExchangeRateVote {
+    const TYPE_URL: &'static str = "/terra.oracle.v1beta1.MsgAggregateExchangeRateVote";
+}
+
+/// MsgExchangeRatePrevote - struct for message to submit a (legacy,
+/// per-denom) exchange rate prevote
+#[derive(Clone, PartialEq, Message)]
+pub struct MsgExchangeRatePrevote {
+    /// Hash
+    #[prost(string, tag = "1")]
+    pub hash: String,
+
+    /// Denom
+    #[prost(string, tag = "2")]
+    pub denom: String,
+
+    /// Feeder
+    #[prost(string, tag = "3")]
+    pub feeder: String,
+
+    /// Validator
+    #[prost(string, tag = "4")]
+    pub validator: String,
+}
+
+impl MsgProto for MsgExchangeRatePrevote {
+    const TYPE_URL: &'static str = "/terra.oracle.v1beta1.MsgExchangeRatePrevote";
+}
+
+/// MsgExchangeRateVote - struct for message to submit a (legacy, per-denom)
+/// exchange rate vote
+#[derive(Clone, PartialEq, Message)]
+pub struct MsgExchangeRateVote {
+    /// Exchange rate (`sdk.Dec`, encoded as an integer scaled by 10^18)
+    #[prost(string, tag = "1")]
+    pub exchange_rate: String,
+
+    /// Salt
+    #[prost(string, tag = "2")]
+    pub salt: String,
+
+    /// Denom
+    #[prost(string, tag = "3")]
+    pub denom: String,
+
+    /// Feeder
+    #[prost(string, tag = "4")]
+    pub feeder: String,
+
+    /// Validator
+    #[prost(string, tag = "5")]
+    pub validator: String,
+}
+
+impl MsgProto for MsgExchangeRateVote {
+    const TYPE_URL: &'static str = "/terra.oracle.v1beta1.MsgExchangeRateVote";
+}
+
 /// MsgDelegateFeedConsent - struct for message to delegate oracle voting
 #[derive(Clone, PartialEq, Message)]
 pub struct MsgDelegateFeedConsent {
@@ -107,3 +173,24 @@ pub struct MsgDelegateFeedConsent {
     #[prost(string, tag = "2")]
     pub delegate: String,
 }
+
+impl MsgProto for MsgDelegateFeedConsent {
+    const TYPE_URL: &'static str = "/terra.oracle.v1beta1.MsgDelegateFeedConsent";
+}
+
+/// MsgExec - struct for message to execute messages on behalf of their
+/// signers, who must have granted the grantee authorization (x/authz)
+#[derive(Clone, PartialEq, Message)]
+pub struct MsgExec {
+    /// Grantee
+    #[prost(string, tag = "1")]
+    pub grantee: String,
+
+    /// Messages to execute
+    #[prost(message, repeated, tag = "2")]
+    pub msgs: Vec<prost_types::Any>,
+}
+
+impl MsgProto for MsgExec {
+    const TYPE_URL: &'static str = "/cosmos.authz.v1beta1.MsgExec";
+}
@@ -0,0 +1,221 @@
+//! Account sequence manager
+//!
+//! Tracks the feeder account's number and sequence. They're fetched from the
+//! LCD, incremented locally for each transaction which passes `CheckTx`
+//! (the ante handler increments the on-chain sequence even if `DeliverTx`
+//! later fails), and re-synced whenever a transaction is rejected with an
+//! account sequence mismatch.
+
+use super::{lcd::Account, tx_outcome::Outcome};
+use crate::prelude::*;
+use tendermint::abci::transaction;
+use tendermint_rpc::endpoint::broadcast::tx_commit;
+
+/// Account sequence manager
+#[derive(Clone, Debug, Default)]
+pub struct SequenceManager {
+    /// Account number and next sequence (if synced)
+    account: Option<Account>,
+
+    /// Hash of the last transaction recorded (to ignore repeated responses)
+    last_tx_hash: Option<transaction::Hash>,
+
+    /// Number of sequence mismatches seen
+    mismatches: u64,
+
+    /// Number of times the account was synced
+    syncs: u64,
+}
+
+impl SequenceManager {
+    /// Create a new (unsynced) sequence manager
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Get the account number and next sequence, if synced
+    pub fn account(&self) -> Option<Account> {
+        self.account
+    }
+
+    /// Does the account need to be (re-)synced?
+    pub fn needs_sync(&self) -> bool {
+        self.account.is_none()
+    }
+
+    /// Number of sequence mismatches seen
+    pub fn mismatches(&self) -> u64 {
+        self.mismatches
+    }
+
+    /// Number of times the account was synced
+    pub fn syncs(&self) -> u64 {
+        self.syncs
+    }
+
+    /// Set the account number and sequence as fetched from the chain
+    pub fn sync(&mut self, account: Account) {
+        if self.account != Some(account) {
+            info!(
+                "feeder account number {}, sequence {}",
+                account.account_number, account.sequence
+            );
+        }
+
+        self.account = Some(account);
+        self.syncs += 1;
+    }
+
+    /// Forget the sequence so it's re-synced before the next transaction
+    /// (e.g. when it's unknown whether a broadcast transaction was accepted)
+    pub fn invalidate(&mut self) {
+        self.account = None;
+    }
+
+    /// Update the sequence from the response to a broadcast transaction,
+    /// returning `false` if the response was already recorded
+    pub fn record(&mut self, response: &tx_commit::Response) -> bool {
+        if self.last_tx_hash == Some(response.hash) {
+            return false;
+        }
+
+        self.last_tx_hash = Some(response.hash);
+
+        if response.check_tx.code.is_ok() {
+            if let Some(account) = &mut self.account {
+                account.sequence += 1;
+            }
+
+            return true;
+        }
+
+        if Outcome::classify_result(&response.check_tx, &[]) != Outcome::SequenceMismatch {
+            // Transactions which fail `CheckTx` don't consume a sequence
+            return true;
+        }
+
+        self.mismatches += 1;
+
+        match (
+            &mut self.account,
+            expected_sequence(response.check_tx.log.as_ref()),
+        ) {
+            (Some(account), Some(expected)) => {
+                warn!(
+                    "account sequence mismatch: expected {}, had {}",
+                    expected, account.sequence
+                );
+                account.sequence = expected;
+            }
+            _ => {
+                warn!("account sequence mismatch; re-syncing account");
+                self.account = None;
+            }
+        }
+
+        true
+    }
+}
+
+/// Parse the expected sequence from an account sequence mismatch log, e.g.
+/// `account sequence mismatch, expected 43, got 42: incorrect account sequence`
+fn expected_sequence(log: &str) -> Option<u64> {
+    let (_, rest) = log.split_once("expected ")?;
+
+    rest.split(|c: char| !c.is_ascii_digit())
+        .next()
+        .and_then(|digits| digits.parse().ok())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{expected_sequence, SequenceManager};
+    use crate::networks::terra::lcd::Account;
+    use serde_json::json;
+    use tendermint_rpc::endpoint::broadcast::tx_commit;
+
+    fn response(hash: u8, code: u32, codespace: &str, log: &str) -> tx_commit::Response {
+        let result = |code: u32, codespace: &str, log: &str| {
+            json!({
+                "code": code,
+                "data": null,
+                "log": log,
+                "info": "",
+                "gas_wanted": "200000",
+                "gas_used": "100000",
+                "events": [],
+                "codespace": codespace
+            })
+        };
+
+        serde_json::from_value(json!({
+            "check_tx": result(code, codespace, log),
+            "deliver_tx": result(0, "", ""),
+            "hash": format!("{:064X}", hash),
+            "height": "4853026"
+        }))
+        .unwrap()
+    }
+
+    fn synced() -> SequenceManager {
+        let mut manager = SequenceManager::new();
+        manager.sync(Account {
+            account_number: 1107,
+            sequence: 42,
+        });
+        manager
+    }
+
+    #[test]
+    fn increments_on_check_tx_success() {
+        let mut manager = synced();
+        manager.record(&response(1, 0, "", ""));
+        assert_eq!(manager.account().unwrap().sequence, 43);
+
+        // Repeated responses for the same transaction are ignored
+        assert!(!manager.record(&response(1, 0, "", "")));
+        assert_eq!(manager.account().unwrap().sequence, 43);
+
+        // Other `CheckTx` failures don't consume a sequence
+        manager.record(&response(2, 13, "sdk", "insufficient fees"));
+        assert_eq!(manager.account().unwrap().sequence, 43);
+    }
+
+    #[test]
+    fn corrects_mismatch_from_log() {
+        let mut manager = synced();
+        manager.record(&response(
+            1,
+            32,
+            "sdk",
+            "account sequence mismatch, expected 45, got 42: incorrect account sequence",
+        ));
+
+        assert_eq!(manager.account().unwrap().sequence, 45);
+        assert_eq!(manager.mismatches(), 1);
+        assert!(!manager.needs_sync());
+    }
+
+    #[test]
+    fn resyncs_on_unparseable_mismatch() {
+        let mut manager = synced();
+        manager.record(&response(
+            1,
+            4,
+            "",
+            "signature verification failed; verify correct account sequence and chain-id",
+        ));
+
+        assert!(manager.needs_sync());
+        assert_eq!(manager.mismatches(), 1);
+    }
+
+    #[test]
+    fn parses_expected_sequence() {
+        assert_eq!(
+            expected_sequence("account sequence mismatch, expected 7, got 6: incorrect"),
+            Some(7)
+        );
+        assert_eq!(expected_sequence("unauthorized"), None);
+    }
+}
@@ -0,0 +1,353 @@
+//! Standalone transaction signer
+//!
+//! Normally Tendermint KMS polls Delphi for oracle transactions and signs
+//! them. Alternatively, Delphi can hold the feeder key itself: it signs the
+//! oracle transactions (`SIGN_MODE_DIRECT`) and broadcasts them to a
+//! Tendermint RPC endpoint, which it also polls for new blocks.
+
+use super::{lcd::Account, tx::encode};
+use crate::{
+    config::network::SignerConfig, prelude::*, protos::TxSigningRequest, Error, ErrorKind,
+};
+use cosmrs::{
+    crypto::PublicKey,
+    proto::cosmos::tx::v1beta1::{SignDoc, TxRaw},
+    tx::SignerInfo,
+};
+use k256::ecdsa::{signature::Signer as _, Signature, SigningKey};
+use std::{fs, path::Path, time::Duration};
+use stdtx::Address;
+use tendermint_rpc::{
+    endpoint::{broadcast::tx_commit, status::SyncInfo},
+    Client, HttpClient,
+};
+
+/// Bech32 prefix of Terra account addresses
+pub const ACCOUNT_PREFIX: &str = "terra";
+
+/// Signs oracle transactions with a local key and broadcasts them
+pub struct Signer {
+    /// Feeder signing key
+    signing_key: SigningKey,
+
+    /// Feeder public key
+    public_key: PublicKey,
+
+    /// Feeder address (Bech32)
+    address: String,
+
+    /// Tendermint RPC client
+    rpc_client: HttpClient,
+
+    /// Interval at which to poll the RPC for new blocks
+    poll_interval: Duration,
+}
+
+impl Signer {
+    /// Create a new signer from config, checking the key belongs to the feeder
+    pub fn new(config: &SignerConfig, feeder: &Address) -> Result<Self, Error> {
+        let signing_key = load_signing_key(&config.key_file)?;
+        let public_key = PublicKey::from(&signing_key.verifying_key());
+
+        let address = public_key
+            .account_id(ACCOUNT_PREFIX)
+            .map_err(|e| format_err!(ErrorKind::Config, "invalid feeder key: {}", e))?
+            .to_string();
+
+        ensure!(
+            address == feeder.to_bech32(ACCOUNT_PREFIX),
+            ErrorKind::Config,
+            "key in {} is for {}, not the configured feeder {}",
+            config.key_file.display(),
+            address,
+            feeder.to_bech32(ACCOUNT_PREFIX)
+        );
+
+        let rpc_client = HttpClient::new(config.rpc.as_str())
+            .map_err(|e| format_err!(ErrorKind::Config, "invalid RPC URL {}: {}", config.rpc, e))?;
+
+        Ok(Self {
+            signing_key,
+            public_key,
+            address,
+            rpc_client,
+            poll_interval: Duration::from_millis(config.poll_interval_ms.max(1)),
+        })
+    }
+
+    /// Get the feeder address (Bech32)
+    pub fn address(&self) -> &str {
+        &self.address
+    }
+
+    /// Get the interval at which to poll the RPC for new blocks
+    pub fn poll_interval(&self) -> Duration {
+        self.poll_interval
+    }
+
+    /// Sign a transaction, returning the serialized `TxRaw`
+    pub fn sign(&self, request: TxSigningRequest, account: &Account) -> Result<Vec<u8>, Error> {
+        let chain_id = request
+            .chain_id
+            .ok_or_else(|| format_err!(ErrorKind::Parse, "signing request has no chain ID"))?;
+
+        let signer_info = SignerInfo::single_direct(Some(self.public_key), account.sequence);
+        let mut auth_info = request.auth_info.unwrap_or_default();
+        auth_info.signer_infos = vec![signer_info.into()];
+
+        let body_bytes = encode(&request.tx_body.unwrap_or_default())?;
+        let auth_info_bytes = encode(&auth_info)?;
+
+        let sign_doc = SignDoc {
+            body_bytes: body_bytes.clone(),
+            auth_info_bytes: auth_info_bytes.clone(),
+            chain_id,
+            account_number: account.account_number,
+        };
+
+        let signature: Signature = self.signing_key.sign(&encode(&sign_doc)?);
+
+        encode(&TxRaw {
+            body_bytes,
+            auth_info_bytes,
+            signatures: vec![signature.as_ref().to_vec()],
+        })
+    }
+
+    /// Get the node's sync status
+    pub async fn status(&self) -> Result<SyncInfo, Error> {
+        let response = self
+            .rpc_client
+            .status()
+            .await
+            .map_err(|e| format_err!(ErrorKind::Http, "error getting node status: {}", e))?;
+
+        Ok(response.sync_info)
+    }
+
+    /// Broadcast a signed transaction, waiting for it to be committed
+    pub async fn broadcast(&self, tx: Vec<u8>) -> Result<tx_commit::Response, Error> {
+        self.rpc_client
+            .broadcast_tx_commit(tx.into())
+            .await
+            .map_err(|e| {
+                format_err!(ErrorKind::Http, "error broadcasting transaction: {}", e).into()
+            })
+    }
+}
+
+/// Load a hex-encoded secp256k1 private key (e.g. as exported by
+/// `terrad keys export --unarmored-hex --unsafe`)
+fn load_signing_key(path: &Path) -> Result<SigningKey, Error> {
+    let hex = fs::read_to_string(path).map_err(|e| {
+        format_err!(
+            ErrorKind::Config,
+            "couldn't read key file {}: {}",
+            path.display(),
+            e
+        )
+    })?;
+
+    let bytes = subtle_encoding::hex::decode(hex.trim().to_ascii_lowercase()).map_err(|e| {
+        format_err!(
+            ErrorKind::Config,
+            "malformed key in {}: {}",
+            path.display(),
+            e
+        )
+    })?;
+
+    SigningKey::from_bytes(&bytes).map_err(|e| {
+        format_err!(
+            ErrorKind::Config,
+            "invalid key in {}: {}",
+            path.display(),
+            e
+        )
+        .into()
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Signer, ACCOUNT_PREFIX};
+    use crate::{
+        config::network::SignerConfig,
+        networks::terra::{
+            lcd::Account,
+            msg::{MsgAggregateExchangeRateVote, OracleMsg},
+            tx,
+        },
+        streaming::tests::serve,
+    };
+    use cosmrs::proto::cosmos::tx::v1beta1::{AuthInfo, SignDoc, TxRaw};
+    use k256::ecdsa::{signature::Verifier, Signature, SigningKey};
+    use prost::Message;
+    use serde_json::{json, Value};
+    use std::{
+        convert::TryFrom,
+        fs,
+        path::PathBuf,
+        sync::{Arc, Mutex},
+    };
+    use stdtx::{amino::types::StdFee, Address};
+    use warp::Filter;
+
+    const KEY: [u8; 32] = [1; 32];
+
+    /// Stand-in for a Tendermint RPC endpoint, recording broadcast transactions
+    fn rpc_stand_in(broadcast: Arc<Mutex<Vec<Vec<u8>>>>) -> String {
+        let rpc = warp::post()
+            .and(warp::body::json())
+            .map(move |request: Value| {
+                let result = match request["method"].as_str().unwrap() {
+                    "status" => json!({
+                        "node_info": {
+                            "channels": "40202122233038606100",
+                            "id": "eb277d0093f0a454f87558b962ae0ba6a0675691",
+                            "listen_addr": "tcp://0.0.0.0:26656",
+                            "moniker": "stand-in",
+                            "network": "columbus-5",
+                            "other": {"rpc_address": "tcp://0.0.0.0:26657", "tx_index": "on"},
+                            "protocol_version": {"app": "0", "block": "11", "p2p": "8"},
+                            "version": "v0.34.13"
+                        },
+                        "sync_info": {
+                            "catching_up": false,
+                            "latest_app_hash": "",
+                            "latest_block_hash": "",
+                            "latest_block_height": "4853025",
+                            "latest_block_time": "2021-10-01T12:00:00Z"
+                        },
+                        "validator_info": {
+                            "address": "ABA577531E6D6F4119E7E1E0EE1909B908A8346D",
+                            "pub_key": {
+                                "type": "tendermint/PubKeyEd25519",
+                                "value": "f+7sWZojwd8xbxZ+GJL+x/zKr1wyM0NMJkp8tCnA4t0="
+                            },
+                            "voting_power": "0"
+                        }
+                    }),
+                    "broadcast_tx_commit" => {
+                        let tx = request["params"]["tx"].as_str().unwrap();
+                        broadcast.lock().unwrap().push(base64::decode(tx).unwrap());
+
+                        let result = json!({
+                            "code": 0,
+                            "codespace": "",
+                            "data": null,
+                            "events": [],
+                            "gas_used": "0",
+                            "gas_wanted": "0",
+                            "info": "",
+                            "log": ""
+                        });
+
+                        json!({
+                            "check_tx": result,
+                            "deliver_tx": result,
+                            "hash": "D63F9C23791E610410B576D8C27BB5AEAC93CC1A58522428A7B32A1276085860",
+                            "height": "4853026"
+                        })
+                    }
+                    method => panic!("unexpected RPC method: {}", method),
+                };
+
+                warp::reply::json(&json!({
+                    "id": request["id"],
+                    "jsonrpc": "2.0",
+                    "result": result
+                }))
+            });
+
+        format!("http://{}", serve(rpc))
+    }
+
+    /// Write the test key to a file
+    fn key_file(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("delphi-signer-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join(name);
+        fs::write(&path, format!("{}\n", hex(&KEY))).unwrap();
+        path
+    }
+
+    fn hex(bytes: &[u8]) -> String {
+        bytes.iter().map(|byte| format!("{:02X}", byte)).collect()
+    }
+
+    /// Feeder address for the test key
+    fn feeder() -> Address {
+        let public_key = SigningKey::from_bytes(&KEY).unwrap().verifying_key();
+        let account_id = cosmrs::crypto::PublicKey::from(&public_key)
+            .account_id(ACCOUNT_PREFIX)
+            .unwrap();
+
+        Address::from_bech32(account_id.as_ref()).unwrap().1
+    }
+
+    fn config(key_file: PathBuf, rpc: String) -> SignerConfig {
+        SignerConfig {
+            key_file,
+            rpc,
+            poll_interval_ms: 1000,
+        }
+    }
+
+    #[test]
+    fn rejects_key_for_other_feeder() {
+        let config = config(key_file("other.key"), "http://127.0.0.1:26657".to_owned());
+        assert!(Signer::new(&config, &Address([1; 20])).is_err());
+    }
+
+    #[tokio::test]
+    async fn signs_and_broadcasts() {
+        let broadcast = Arc::new(Mutex::new(vec![]));
+        let config = config(key_file("feeder.key"), rpc_stand_in(broadcast.clone()));
+        let signer = Signer::new(&config, &feeder()).unwrap();
+
+        let status = signer.status().await.unwrap();
+        assert_eq!(status.latest_block_height.value(), 4853025);
+
+        let vote = MsgAggregateExchangeRateVote {
+            exchange_rates: "362.000000000000000000ukrw".parse().unwrap(),
+            salt: "abcd".to_owned(),
+            feeder: feeder(),
+            validator: Address([2; 20]),
+        };
+
+        let msgs = [OracleMsg::AggregatePrevote(vote.prevote())];
+        let fee = StdFee::from(&Default::default());
+        let request = tx::signing_request("columbus-5", &msgs, &fee, &Default::default()).unwrap();
+
+        let account = Account {
+            account_number: 1107,
+            sequence: 42,
+        };
+
+        let tx = signer.sign(request, &account).unwrap();
+        let response = signer.broadcast(tx.clone()).await.unwrap();
+        assert!(response.check_tx.code.is_ok());
+        assert_eq!(response.height.value(), 4853026);
+        assert_eq!(*broadcast.lock().unwrap(), vec![tx.clone()]);
+
+        let tx_raw = TxRaw::decode(&*tx).unwrap();
+        let auth_info = AuthInfo::decode(&*tx_raw.auth_info_bytes).unwrap();
+        assert_eq!(auth_info.signer_infos[0].sequence, 42);
+        assert_eq!(auth_info.fee.unwrap().gas_limit, 200_000);
+
+        let sign_doc = SignDoc {
+            body_bytes: tx_raw.body_bytes,
+            auth_info_bytes: tx_raw.auth_info_bytes,
+            chain_id: "columbus-5".to_owned(),
+            account_number: 1107,
+        };
+
+        let mut sign_bytes = vec![];
+        sign_doc.encode(&mut sign_bytes).unwrap();
+
+        let signature = Signature::try_from(&*tx_raw.signatures[0]).unwrap();
+        let public_key = SigningKey::from_bytes(&KEY).unwrap().verifying_key();
+        assert!(public_key.verify(&sign_bytes, &signature).is_ok());
+    }
+}
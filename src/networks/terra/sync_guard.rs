@@ -0,0 +1,156 @@
+//! Node sync guard
+//!
+//! Prices and vote periods are only meaningful relative to the chain's
+//! current state, so Delphi refuses to vote while the node reports that it
+//! is catching up, or when its latest block time is far from the local
+//! clock (which means either the node or this host is out of sync).
+
+use crate::config::network::SyncGuardConfig;
+use std::{
+    fmt::{self, Display},
+    time::Duration,
+};
+use tendermint::{block, Time};
+use tendermint_rpc::endpoint::status::SyncInfo;
+
+/// Guard against voting while out of sync with the chain
+#[derive(Clone, Debug)]
+pub struct SyncGuard {
+    /// Vote even if the node is catching up
+    allow_catching_up: bool,
+
+    /// Maximum skew between the latest block time and the local clock
+    max_time_skew: Duration,
+}
+
+/// Reason for refusing to vote
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum Refusal {
+    /// Node is catching up with the chain
+    CatchingUp {
+        /// Latest block height the node has
+        height: block::Height,
+    },
+
+    /// Latest block time is too far from the local clock
+    TimeSkew {
+        /// Difference between the block time and the local clock
+        skew: Duration,
+    },
+}
+
+impl Display for Refusal {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Refusal::CatchingUp { height } => {
+                write!(f, "node is catching up (at height {})", height)
+            }
+            Refusal::TimeSkew { skew } => write!(
+                f,
+                "latest block time is {}s from the local clock",
+                skew.as_secs()
+            ),
+        }
+    }
+}
+
+impl SyncGuard {
+    /// Create a new sync guard from config
+    pub fn new(config: &SyncGuardConfig) -> Self {
+        Self {
+            allow_catching_up: config.allow_catching_up,
+            max_time_skew: Duration::from_secs(config.max_time_skew_secs),
+        }
+    }
+
+    /// Check the node's sync status against the given local time
+    pub fn check(&self, status: &SyncInfo, now: Time) -> Result<(), Refusal> {
+        if status.catching_up && !self.allow_catching_up {
+            return Err(Refusal::CatchingUp {
+                height: status.latest_block_height,
+            });
+        }
+
+        let block_time = status.latest_block_time;
+
+        // `duration_since` fails if the first time is earlier than the second
+        let skew = now
+            .duration_since(block_time)
+            .or_else(|_| block_time.duration_since(now))
+            .unwrap_or_default();
+
+        if skew > self.max_time_skew {
+            return Err(Refusal::TimeSkew { skew });
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Refusal, SyncGuard};
+    use crate::config::network::SyncGuardConfig;
+    use std::time::Duration;
+    use tendermint::Time;
+    use tendermint_rpc::endpoint::status::SyncInfo;
+
+    fn sync_info(block_time: &str, catching_up: bool) -> SyncInfo {
+        serde_json::from_value(serde_json::json!({
+            "latest_block_hash": "",
+            "latest_app_hash": "",
+            "latest_block_height": "4853025",
+            "latest_block_time": block_time,
+            "catching_up": catching_up
+        }))
+        .unwrap()
+    }
+
+    fn now() -> Time {
+        "2021-10-01T12:00:00Z".parse().unwrap()
+    }
+
+    #[test]
+    fn accepts_synced_node() {
+        let guard = SyncGuard::new(&SyncGuardConfig::default());
+        let status = sync_info("2021-10-01T11:59:54Z", false);
+        assert_eq!(guard.check(&status, now()), Ok(()));
+    }
+
+    #[test]
+    fn refuses_catching_up() {
+        let guard = SyncGuard::new(&SyncGuardConfig::default());
+        let status = sync_info("2021-10-01T11:59:54Z", true);
+
+        assert_eq!(
+            guard.check(&status, now()),
+            Err(Refusal::CatchingUp {
+                height: 4853025u32.into()
+            })
+        );
+
+        let guard = SyncGuard::new(&SyncGuardConfig {
+            allow_catching_up: true,
+            ..Default::default()
+        });
+        assert_eq!(guard.check(&status, now()), Ok(()));
+    }
+
+    #[test]
+    fn refuses_time_skew() {
+        let guard = SyncGuard::new(&SyncGuardConfig::default());
+
+        // Block time behind the local clock
+        let status = sync_info("2021-10-01T11:58:00Z", false);
+        assert_eq!(
+            guard.check(&status, now()),
+            Err(Refusal::TimeSkew {
+                skew: Duration::from_secs(120)
+            })
+        );
+
+        // Block time ahead of the local clock
+        let status = sync_info("2021-10-01T12:05:00Z", false);
+        assert!(guard.check(&status, now()).is_err());
+    }
+}
@@ -0,0 +1,415 @@
+//! Triangulation consistency checks
+//!
+//! Rates for different denoms are computed independently, so once every
+//! denom is priced the implied cross rates are checked against direct FX
+//! quotes: LUNA/KRW ÷ LUNA/USD should agree with USD/KRW, and so on.
+//!
+//! Most denoms are priced as LUNA/USD × USD/denom, so a wrong LUNA/USD
+//! cancels out of their cross rates: for them the check only compares FX
+//! sources, and an inconsistency blames the denom's FX leg. Only LUNA/KRW
+//! and LUNA/SDR come from their own LUNA markets, so their cross rates are
+//! the independent checks of LUNA/USD. If all of them disagree with FX, the
+//! LUNA/USD leg they have in common is blamed (along with every denom priced
+//! from it); if only some do, their own LUNA legs are. A single inconsistent
+//! independent cross rate can't tell which leg is wrong, so both are blamed.
+
+use super::{
+    deviation::{abstain, relative_deviation, to_decimal},
+    msg::ExchangeRates,
+    Denom,
+};
+use crate::{
+    config::network::{TriangulationAction, TriangulationConfig},
+    prelude::*,
+    sources::Sources,
+    Currency, Map, TradingPair,
+};
+use futures::future::join_all;
+use rust_decimal::Decimal;
+use std::time::Duration;
+use tokio::time::timeout;
+
+/// Consistency checker for implied cross rates
+#[derive(Clone, Debug)]
+pub struct Triangulation {
+    /// Maximum relative difference between implied and direct FX rates
+    tolerance: Decimal,
+
+    /// Action to take on suspect denoms
+    action: TriangulationAction,
+}
+
+/// Implied cross rate which disagrees with the direct FX quote
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Inconsistency {
+    /// Denom whose cross rate with USD is inconsistent
+    pub denom: Denom,
+
+    /// USD/denom implied by LUNA/denom ÷ LUNA/USD
+    pub implied: Decimal,
+
+    /// USD/denom quoted directly
+    pub direct: Decimal,
+
+    /// Relative difference between the implied and direct rates
+    pub deviation: Decimal,
+}
+
+/// Result of checking a set of exchange rates
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct Report {
+    /// Denoms whose cross rates were checked
+    pub checked: Vec<Denom>,
+
+    /// Cross rates outside the tolerance
+    pub inconsistencies: Vec<Inconsistency>,
+}
+
+impl Report {
+    /// Denoms whose price legs are most likely wrong. `uusd` stands for the
+    /// LUNA/USD leg, which every denom priced via USD shares.
+    pub fn suspects(&self) -> Vec<Denom> {
+        let is_inconsistent =
+            |denom: &Denom| self.inconsistencies.iter().any(|i| i.denom == *denom);
+
+        let independent = self
+            .checked
+            .iter()
+            .filter(|denom| !denom.is_priced_via_usd())
+            .collect::<Vec<_>>();
+
+        let inconsistent_independent = independent
+            .iter()
+            .filter(|denom| is_inconsistent(denom))
+            .count();
+
+        let mut suspects = vec![];
+
+        // Blame LUNA/USD if every independent cross rate disagrees with FX.
+        // With several of them, their own LUNA legs are then most likely fine.
+        let blame_luna_usd =
+            inconsistent_independent > 0 && inconsistent_independent == independent.len();
+
+        if blame_luna_usd {
+            suspects.push(Denom::Uusd);
+        }
+
+        for i in &self.inconsistencies {
+            if i.denom.is_priced_via_usd() || !blame_luna_usd || independent.len() == 1 {
+                suspects.push(i.denom.clone());
+            }
+        }
+
+        suspects
+    }
+}
+
+impl Triangulation {
+    /// Create a new triangulation checker from config
+    pub fn new(config: &TriangulationConfig) -> Self {
+        Self {
+            tolerance: config.tolerance,
+            action: config.action,
+        }
+    }
+
+    /// Check implied cross rates against direct USD/denom FX quotes
+    pub fn check(&self, exchange_rates: &ExchangeRates, usd_fx: &Map<Denom, Decimal>) -> Report {
+        let zero = Decimal::from(0u8);
+        let mut report = Report::default();
+
+        let luna_usd = match exchange_rates.get(&Denom::Uusd).map(to_decimal) {
+            Some(luna_usd) if luna_usd > zero => luna_usd,
+            _ => return report,
+        };
+
+        for (denom, &rate) in exchange_rates.iter() {
+            let rate = to_decimal(rate);
+
+            let direct = match usd_fx.get(denom) {
+                Some(&direct) if *denom != Denom::Uusd && rate > zero && direct > zero => direct,
+                _ => continue,
+            };
+
+            let implied = rate / luna_usd;
+            let deviation = relative_deviation(implied, direct);
+            report.checked.push(denom.clone());
+
+            if deviation > self.tolerance {
+                report.inconsistencies.push(Inconsistency {
+                    denom: denom.clone(),
+                    implied,
+                    direct,
+                    deviation,
+                });
+            }
+        }
+
+        report
+    }
+
+    /// Check a set of exchange rates, logging inconsistencies and abstaining
+    /// from the suspect denoms if so configured
+    pub async fn apply(
+        &self,
+        exchange_rates: &ExchangeRates,
+        sources: &Sources,
+        timeout_duration: Duration,
+    ) -> ExchangeRates {
+        let denoms = exchange_rates.iter().map(|(denom, _)| denom.clone());
+
+        let usd_fx = match timeout(timeout_duration, usd_fx_rates(sources, denoms)).await {
+            Ok(usd_fx) => usd_fx,
+            Err(_) => {
+                warn!("timed out getting FX quotes for triangulation");
+                return exchange_rates.clone();
+            }
+        };
+
+        let report = self.check(exchange_rates, &usd_fx);
+
+        for i in &report.inconsistencies {
+            warn!(
+                "{}: implied USD/{} {} differs from direct FX {} by {}",
+                i.denom,
+                Currency::from(&i.denom),
+                i.implied,
+                i.direct,
+                i.deviation
+            );
+        }
+
+        let suspects = report.suspects();
+
+        if suspects.is_empty() {
+            debug!("{} cross rates consistent with FX", report.checked.len());
+            return exchange_rates.clone();
+        }
+
+        let suspect_legs = suspects
+            .iter()
+            .map(|denom| match denom {
+                Denom::Uusd => "LUNA/USD".to_owned(),
+                _ if denom.is_priced_via_usd() => format!("USD/{}", Currency::from(denom)),
+                _ => format!("LUNA/{}", Currency::from(denom)),
+            })
+            .collect::<Vec<_>>()
+            .join(", ");
+
+        warn!(
+            "{} of {} cross rates inconsistent with FX; most likely wrong: {}",
+            report.inconsistencies.len(),
+            report.checked.len(),
+            suspect_legs
+        );
+
+        if self.action == TriangulationAction::Flag {
+            return exchange_rates.clone();
+        }
+
+        abstain_suspects(exchange_rates, &suspects)
+    }
+}
+
+/// Abstain from the suspect denoms, and from every denom priced via USD if
+/// the LUNA/USD leg (`uusd`) is suspect
+fn abstain_suspects(exchange_rates: &ExchangeRates, suspects: &[Denom]) -> ExchangeRates {
+    let luna_usd_suspect = suspects.contains(&Denom::Uusd);
+    let mut checked = ExchangeRates::new();
+
+    for (denom, &rate) in exchange_rates.iter() {
+        let rate = if suspects.contains(denom) || (luna_usd_suspect && denom.is_priced_via_usd()) {
+            warn!("{}: abstaining due to inconsistent cross rates", denom);
+            abstain()
+        } else {
+            rate
+        };
+
+        checked.add(denom.clone(), rate).expect("duplicate denom");
+    }
+
+    checked
+}
+
+/// Get direct USD/denom FX quotes, preferring the ECB's reference rates and
+/// falling back to Currencylayer. Denoms without a quote are omitted.
+pub async fn usd_fx_rates(
+    sources: &Sources,
+    denoms: impl Iterator<Item = Denom>,
+) -> Map<Denom, Decimal> {
+    let ecb = match sources.ecb.daily().await {
+        Ok(rates) => Some(rates),
+        Err(e) => {
+            warn!("error getting ECB reference rates: {}", e);
+            None
+        }
+    };
+
+    let mut usd_fx = Map::new();
+    let mut missing = vec![];
+
+    for denom in denoms.filter(|denom| *denom != Denom::Uusd) {
+        let pair = TradingPair(Currency::Usd, (&denom).into());
+
+        match ecb.as_ref().and_then(|rates| rates.quote(&pair)) {
+            Some(quote) => {
+                usd_fx.insert(denom, quote.price.into());
+            }
+            None => missing.push(denom),
+        }
+    }
+
+    let quotes = join_all(missing.iter().map(|denom| {
+        let pair = TradingPair(Currency::Usd, denom.into());
+        async move { sources.currencylayer.trading_pairs(&pair).await }
+    }))
+    .await;
+
+    for (denom, quote) in missing.into_iter().zip(quotes) {
+        match quote {
+            Ok(price) => {
+                usd_fx.insert(denom, price.into());
+            }
+            Err(e) => debug!("no USD/{} FX quote: {}", Currency::from(&denom), e),
+        }
+    }
+
+    usd_fx
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{abstain_suspects, Triangulation};
+    use crate::{
+        config::network::{TriangulationAction, TriangulationConfig},
+        networks::terra::{
+            deviation::{abstain, from_decimal},
+            msg::ExchangeRates,
+            Denom,
+        },
+        Map,
+    };
+    use rust_decimal::Decimal;
+
+    fn triangulation() -> Triangulation {
+        Triangulation::new(&TriangulationConfig {
+            tolerance: "0.02".parse().unwrap(),
+            action: TriangulationAction::Abstain,
+        })
+    }
+
+    fn dec(s: &str) -> Decimal {
+        s.parse().unwrap()
+    }
+
+    /// Direct USD/denom FX quotes (as from the ECB or Currencylayer)
+    fn usd_fx() -> Map<Denom, Decimal> {
+        [
+            (Denom::Ukrw, "1180"),
+            (Denom::Usdr, "0.708"),
+            (Denom::Ueur, "0.86"),
+            (Denom::Ujpy, "114"),
+        ]
+        .iter()
+        .map(|(denom, rate)| (denom.clone(), dec(rate)))
+        .collect()
+    }
+
+    /// Exchange rates built the way `Denom::builtin_price` builds them:
+    /// LUNA/USD, LUNA/KRW from Upbit/Bithumb, LUNA/SDR from Coinone's
+    /// LUNA/KRW × KRW/SDR, and every other denom as LUNA/USD × USD/denom
+    /// from AlphaVantage's FX rates
+    fn oracle_rates(
+        luna_usd: &str,
+        luna_krw: &str,
+        coinone_luna_krw: &str,
+        alphavantage: &Map<Denom, Decimal>,
+    ) -> ExchangeRates {
+        let luna_usd = dec(luna_usd);
+        let krw_sdr = dec("0.708") / dec("1180");
+
+        let rates = vec![
+            (Denom::Uusd, luna_usd),
+            (Denom::Ukrw, dec(luna_krw)),
+            (Denom::Usdr, dec(coinone_luna_krw) * krw_sdr),
+            (Denom::Ueur, luna_usd * alphavantage[&Denom::Ueur]),
+            (Denom::Ujpy, luna_usd * alphavantage[&Denom::Ujpy]),
+        ]
+        .into_iter()
+        .map(|(denom, rate)| (denom, from_decimal(rate).unwrap()))
+        .collect::<Vec<_>>();
+
+        ExchangeRates::from_exchange_rates(rates.iter()).unwrap()
+    }
+
+    #[test]
+    fn consistent() {
+        let exchange_rates = oracle_rates("40", "47200", "47200", &usd_fx());
+
+        let report = triangulation().check(&exchange_rates, &usd_fx());
+        assert_eq!(report.checked.len(), 4);
+        assert!(report.suspects().is_empty());
+    }
+
+    #[test]
+    fn blames_luna_krw_leg() {
+        // Upbit/Bithumb LUNA/KRW is 5% high: implied USD/KRW is 1239, while
+        // Coinone's LUNA/SDR still agrees with LUNA/USD
+        let exchange_rates = oracle_rates("40", "49560", "47200", &usd_fx());
+
+        let report = triangulation().check(&exchange_rates, &usd_fx());
+        assert_eq!(report.inconsistencies.len(), 1);
+        assert_eq!(report.inconsistencies[0].implied, dec("1239"));
+        assert_eq!(report.suspects(), vec![Denom::Ukrw]);
+    }
+
+    #[test]
+    fn blames_luna_usd_leg() {
+        // LUNA/USD is 5% low. It cancels out of the FX-derived cross rates,
+        // so only the independently priced LUNA/KRW and LUNA/SDR disagree.
+        let exchange_rates = oracle_rates("38", "47200", "47200", &usd_fx());
+
+        let report = triangulation().check(&exchange_rates, &usd_fx());
+        let mut inconsistent = report
+            .inconsistencies
+            .iter()
+            .map(|i| i.denom.clone())
+            .collect::<Vec<_>>();
+        inconsistent.sort();
+        assert_eq!(inconsistent, vec![Denom::Ukrw, Denom::Usdr]);
+
+        let suspects = report.suspects();
+        assert_eq!(suspects, vec![Denom::Uusd]);
+
+        // Every denom priced from LUNA/USD is abstained from
+        let checked = abstain_suspects(&exchange_rates, &suspects);
+        assert_eq!(checked.get(&Denom::Uusd), Some(abstain()));
+        assert_eq!(checked.get(&Denom::Ueur), Some(abstain()));
+        assert_eq!(checked.get(&Denom::Ujpy), Some(abstain()));
+        assert_ne!(checked.get(&Denom::Ukrw), Some(abstain()));
+        assert_ne!(checked.get(&Denom::Usdr), Some(abstain()));
+    }
+
+    #[test]
+    fn blames_both_legs_of_single_independent_cross() {
+        // Without a USD/SDR quote, LUNA/KRW ÷ LUNA/USD is the only
+        // independent check, so either leg could be wrong
+        let mut usd_fx = usd_fx();
+        usd_fx.remove(&Denom::Usdr);
+        let exchange_rates = oracle_rates("38", "47200", "47200", &usd_fx);
+
+        let report = triangulation().check(&exchange_rates, &usd_fx);
+        assert_eq!(report.suspects(), vec![Denom::Uusd, Denom::Ukrw]);
+    }
+
+    #[test]
+    fn blames_fx_leg() {
+        // AlphaVantage's USD/EUR is 5% high
+        let mut alphavantage = usd_fx();
+        alphavantage.insert(Denom::Ueur, dec("0.903"));
+        let exchange_rates = oracle_rates("40", "47200", "47200", &alphavantage);
+
+        let report = triangulation().check(&exchange_rates, &usd_fx());
+        assert_eq!(report.suspects(), vec![Denom::Ueur]);
+    }
+}
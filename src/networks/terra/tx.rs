@@ -0,0 +1,344 @@
+//! Protobuf (`SIGN_MODE_DIRECT`) transactions
+//!
+//! Post-Stargate chains (Columbus-5+) sign protobuf-encoded transactions.
+//! Oracle messages are `Any`-wrapped into a `TxBody`, and the fee goes in
+//! the `AuthInfo`. The signer fills in the signer info (public key and
+//! account sequence) before signing.
+//!
+//! Fees may be paid by another account, either directly (the fee payer, who
+//! must also sign) or via a fee allowance (x/feegrant) from the fee granter.
+
+use super::{msg::OracleMsg, MEMO};
+use crate::{config::network::TxFormat, prelude::*, protos::TxSigningRequest, Error, ErrorKind};
+use cosmrs::{
+    proto::cosmos::{
+        base::v1beta1::Coin,
+        tx::v1beta1::{AuthInfo, Fee, TxRaw},
+    },
+    tx::SignerInfo,
+};
+use prost::Message;
+use serde_json::json;
+use stdtx::amino::types::StdFee;
+
+/// Accounts paying the fee, if not the feeder
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct FeeGrant {
+    /// Account paying the fee (Bech32)
+    pub payer: Option<String>,
+
+    /// Account granting a fee allowance (Bech32)
+    pub granter: Option<String>,
+}
+
+/// Build a request to sign a transaction containing the given messages
+pub fn signing_request(
+    chain_id: &str,
+    msgs: &[OracleMsg],
+    fee: &StdFee,
+    fee_grant: &FeeGrant,
+) -> Result<TxSigningRequest, Error> {
+    let msgs = msgs
+        .iter()
+        .map(OracleMsg::to_proto_msg)
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|e| format_err!(ErrorKind::Parse, "error encoding message: {}", e))?;
+
+    let tx_body = cosmrs::tx::Body::new(msgs, MEMO, 0u32).into_proto();
+
+    let auth_info = AuthInfo {
+        signer_infos: vec![],
+        fee: Some(proto_fee(fee, fee_grant)),
+    };
+
+    Ok(TxSigningRequest {
+        chain_id: Some(chain_id.to_owned()),
+        tx_body: Some(tx_body),
+        auth_info: Some(auth_info),
+    })
+}
+
+/// Encode a signing request as Base64 (for inclusion in JSON responses)
+pub fn encode_signing_request(request: &TxSigningRequest) -> Result<String, Error> {
+    Ok(base64::encode(encode(request)?))
+}
+
+/// Build an unsigned transaction for gas simulation, returning the
+/// serialized `TxRaw`.
+///
+/// Simulation skips signature verification (substituting a placeholder
+/// public key), but the account sequence must still match.
+pub fn simulation_tx(request: TxSigningRequest, sequence: u64) -> Result<Vec<u8>, Error> {
+    let signer_info = SignerInfo::single_direct(None, sequence);
+    let mut auth_info = request.auth_info.unwrap_or_default();
+    auth_info.signer_infos = vec![signer_info.into()];
+
+    encode(&TxRaw {
+        body_bytes: encode(&request.tx_body.unwrap_or_default())?,
+        auth_info_bytes: encode(&auth_info)?,
+        signatures: vec![vec![]],
+    })
+}
+
+/// Encode a protobuf message
+pub fn encode<M: Message>(msg: &M) -> Result<Vec<u8>, Error> {
+    let mut bytes = vec![];
+
+    msg.encode(&mut bytes)
+        .map_err(|e| format_err!(ErrorKind::Parse, "error encoding protobuf message: {}", e))?;
+
+    Ok(bytes)
+}
+
+/// Build an unsigned transaction containing the given messages, for offline
+/// signing in the given format
+pub fn unsigned_tx(
+    format: TxFormat,
+    msgs: &[OracleMsg],
+    fee: &StdFee,
+    fee_grant: &FeeGrant,
+) -> Result<serde_json::Value, Error> {
+    match format {
+        TxFormat::Amino => unsigned_amino_tx(msgs, fee, fee_grant),
+        TxFormat::Protobuf => unsigned_proto_tx(msgs, fee, fee_grant),
+    }
+}
+
+/// Build an unsigned legacy amino `StdTx` (as accepted by `terracli tx sign`)
+pub fn unsigned_amino_tx(
+    msgs: &[OracleMsg],
+    fee: &StdFee,
+    fee_grant: &FeeGrant,
+) -> Result<serde_json::Value, Error> {
+    let msgs = msgs
+        .iter()
+        .map(OracleMsg::to_amino_json)
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|e| format_err!(ErrorKind::Parse, "error encoding message: {}", e))?;
+
+    Ok(json!({
+        "type": "core/StdTx",
+        "value": {
+            "msg": msgs,
+            "fee": amino_fee(fee, fee_grant),
+            "signatures": null,
+            "memo": MEMO,
+        }
+    }))
+}
+
+/// Build an unsigned protobuf `Tx` in its JSON encoding (as accepted by
+/// `terrad tx sign`)
+pub fn unsigned_proto_tx(
+    msgs: &[OracleMsg],
+    fee: &StdFee,
+    fee_grant: &FeeGrant,
+) -> Result<serde_json::Value, Error> {
+    let msgs = msgs
+        .iter()
+        .map(OracleMsg::to_proto_json)
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|e| format_err!(ErrorKind::Parse, "error encoding message: {}", e))?;
+
+    Ok(json!({
+        "body": {
+            "messages": msgs,
+            "memo": MEMO,
+            "timeout_height": "0",
+            "extension_options": [],
+            "non_critical_extension_options": [],
+        },
+        "auth_info": {
+            "signer_infos": [],
+            "fee": {
+                "amount": fee.amount,
+                "gas_limit": fee.gas.to_string(),
+                "payer": fee_grant.payer.clone().unwrap_or_default(),
+                "granter": fee_grant.granter.clone().unwrap_or_default(),
+            },
+        },
+        "signatures": [],
+    }))
+}
+
+/// Serialize a fee as legacy amino JSON, including the fee payer and granter
+pub fn amino_fee(fee: &StdFee, fee_grant: &FeeGrant) -> serde_json::Value {
+    let mut fee_json = json!(fee);
+
+    if let Some(payer) = &fee_grant.payer {
+        fee_json["payer"] = json!(payer);
+    }
+
+    if let Some(granter) = &fee_grant.granter {
+        fee_json["granter"] = json!(granter);
+    }
+
+    fee_json
+}
+
+/// Convert a legacy amino fee into a protobuf fee
+pub fn proto_fee(fee: &StdFee, fee_grant: &FeeGrant) -> Fee {
+    Fee {
+        amount: fee
+            .amount
+            .iter()
+            .map(|coin| Coin {
+                denom: coin.denom.clone(),
+                amount: coin.amount.clone(),
+            })
+            .collect(),
+        gas_limit: fee.gas,
+        payer: fee_grant.payer.clone().unwrap_or_default(),
+        granter: fee_grant.granter.clone().unwrap_or_default(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{amino_fee, encode_signing_request, signing_request, unsigned_tx, FeeGrant};
+    use crate::{
+        config::network::TxFormat,
+        networks::terra::{
+            msg::{MsgAggregateExchangeRateVote, MsgDelegateFeedConsent, OracleMsg},
+            protos, MEMO,
+        },
+        protos::TxSigningRequest,
+    };
+    use prost::Message;
+    use serde_json::json;
+    use stdtx::{
+        amino::types::{Coin, StdFee},
+        Address,
+    };
+
+    fn fee() -> StdFee {
+        StdFee {
+            amount: vec![Coin {
+                denom: "ukrw".to_owned(),
+                amount: "356100".to_owned(),
+            }],
+            gas: 200_000,
+        }
+    }
+
+    fn vote() -> MsgAggregateExchangeRateVote {
+        MsgAggregateExchangeRateVote {
+            exchange_rates: "362.000000000000000000ukrw".parse().unwrap(),
+            salt: "abcd".to_owned(),
+            feeder: Address([1; 20]),
+            validator: Address([2; 20]),
+        }
+    }
+
+    #[test]
+    fn builds_signing_request() {
+        let vote = vote();
+        let msgs = [
+            OracleMsg::AggregateVote(vote.clone()),
+            OracleMsg::AggregatePrevote(vote.prevote()),
+        ];
+
+        let request = signing_request("columbus-5", &msgs, &fee(), &FeeGrant::default()).unwrap();
+        let encoded = encode_signing_request(&request).unwrap();
+        let decoded = TxSigningRequest::decode(&*base64::decode(encoded).unwrap()).unwrap();
+        assert_eq!(decoded, request);
+
+        let tx_body = request.tx_body.unwrap();
+        assert_eq!(tx_body.memo, MEMO);
+        assert_eq!(
+            tx_body.messages[0].type_url,
+            "/terra.oracle.v1beta1.MsgAggregateExchangeRateVote"
+        );
+        assert_eq!(
+            tx_body.messages[1].type_url,
+            "/terra.oracle.v1beta1.MsgAggregateExchangeRatePrevote"
+        );
+
+        let prevote =
+            protos::MsgAggregateExchangeRatePrevote::decode(&*tx_body.messages[1].value).unwrap();
+        assert_eq!(prevote.hash, vote.prevote().hex_hash());
+        assert!(prevote.validator.starts_with("terravaloper1"));
+
+        let fee = request.auth_info.unwrap().fee.unwrap();
+        assert_eq!(fee.gas_limit, 200_000);
+        assert_eq!(fee.amount[0].amount, "356100");
+    }
+
+    #[test]
+    fn fee_grant_and_authz() {
+        let vote = vote();
+        let msgs = [OracleMsg::Exec {
+            grantee: Address([3; 20]),
+            msgs: vec![OracleMsg::AggregatePrevote(vote.prevote())],
+        }];
+
+        let fee_grant = FeeGrant {
+            payer: None,
+            granter: Some(Address([4; 20]).to_bech32("terra")),
+        };
+
+        let request = signing_request("columbus-5", &msgs, &fee(), &fee_grant).unwrap();
+        let proto_fee = request.auth_info.unwrap().fee.unwrap();
+        assert_eq!(Some(proto_fee.granter), fee_grant.granter);
+        assert_eq!(proto_fee.payer, "");
+
+        let tx_body = request.tx_body.unwrap();
+        assert_eq!(
+            tx_body.messages[0].type_url,
+            "/cosmos.authz.v1beta1.MsgExec"
+        );
+
+        let exec = protos::MsgExec::decode(&*tx_body.messages[0].value).unwrap();
+        assert_eq!(exec.grantee, Address([3; 20]).to_bech32("terra"));
+        assert_eq!(
+            exec.msgs[0].type_url,
+            "/terra.oracle.v1beta1.MsgAggregateExchangeRatePrevote"
+        );
+
+        let msg_json = msgs[0].to_amino_json().unwrap();
+        assert_eq!(msg_json["type"], "cosmos-sdk/MsgExec");
+        assert_eq!(
+            msg_json["value"]["msgs"][0]["type"],
+            "oracle/MsgAggregateExchangeRatePrevote"
+        );
+
+        let fee_json = amino_fee(&fee(), &fee_grant);
+        assert_eq!(fee_json["gas"], "200000");
+        assert_eq!(fee_json["granter"], json!(fee_grant.granter));
+        assert!(fee_json.get("payer").is_none());
+    }
+
+    #[test]
+    fn builds_unsigned_delegation_tx() {
+        let msgs = [OracleMsg::DelegateFeedConsent(MsgDelegateFeedConsent {
+            operator: Address([2; 20]),
+            delegate: Address([1; 20]),
+        })];
+
+        let amino_tx = unsigned_tx(TxFormat::Amino, &msgs, &fee(), &FeeGrant::default()).unwrap();
+        assert_eq!(amino_tx["type"], "core/StdTx");
+        assert_eq!(amino_tx["value"]["signatures"], json!(null));
+        assert_eq!(amino_tx["value"]["fee"]["gas"], "200000");
+
+        let msg = &amino_tx["value"]["msg"][0];
+        assert_eq!(msg["type"], "oracle/MsgDelegateFeedConsent");
+        assert_eq!(
+            msg["value"]["operator"],
+            Address([2; 20]).to_bech32("terravaloper")
+        );
+        assert_eq!(
+            msg["value"]["delegate"],
+            Address([1; 20]).to_bech32("terra")
+        );
+
+        let proto_tx =
+            unsigned_tx(TxFormat::Protobuf, &msgs, &fee(), &FeeGrant::default()).unwrap();
+        assert_eq!(proto_tx["signatures"], json!([]));
+        assert_eq!(proto_tx["auth_info"]["fee"]["gas_limit"], "200000");
+
+        let msg = &proto_tx["body"]["messages"][0];
+        assert_eq!(msg["@type"], "/terra.oracle.v1beta1.MsgDelegateFeedConsent");
+        assert_eq!(msg["operator"], Address([2; 20]).to_bech32("terravaloper"));
+        assert_eq!(msg["delegate"], Address([1; 20]).to_bech32("terra"));
+    }
+}
@@ -0,0 +1,486 @@
+//! Transaction outcomes
+//!
+//! Decodes the ABCI code and log of the last transaction's `CheckTx` and
+//! `DeliverTx` results into typed outcomes, and maps each outcome to the
+//! action the oracle takes to recover from it.
+
+use super::Denom;
+use crate::{prelude::*, Map};
+use std::fmt::{self, Display};
+use stdtx::amino::types::StdFee;
+use tendermint_rpc::endpoint::broadcast::tx_commit::{self, TxResult};
+
+/// Cosmos SDK error codespace
+pub const SDK_CODESPACE: &str = "sdk";
+
+/// Terra oracle module error codespace
+pub const ORACLE_CODESPACE: &str = "oracle";
+
+/// Percentage by which gas or fees are increased after a failure
+pub const FEE_BUMP_PERCENT: u64 = 25;
+
+/// Maximum multiple of the configured gas or fee which bumps can reach
+pub const MAX_FEE_MULTIPLE: u64 = 4;
+
+/// Number of consecutive successful transactions after which increased gas
+/// and fees are reset to the configured fee
+pub const FEE_RESET_SUCCESSES: u64 = 10;
+
+/// Outcome of the last oracle transaction
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum Outcome {
+    /// Transaction succeeded
+    Success,
+
+    /// Transaction ran out of gas
+    OutOfGas,
+
+    /// Fee was too low
+    InsufficientFee,
+
+    /// Account sequence didn't match the chain's
+    SequenceMismatch,
+
+    /// Vote was rejected because there's no matching prevote
+    NoPrevote,
+
+    /// Validator has already voted in this period
+    AlreadyVoted,
+
+    /// Vote contains a denom the chain doesn't whitelist
+    UnknownDenom(Option<Denom>),
+
+    /// Any other failure
+    Other {
+        /// ABCI code
+        code: u32,
+    },
+}
+
+/// Action taken in response to an outcome
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum Action {
+    /// Nothing to do
+    None,
+
+    /// Increase the gas limit
+    BumpGas,
+
+    /// Increase the fee amount
+    RaiseFee,
+
+    /// Send a new prevote (rather than revealing the last one)
+    ResendPrevote,
+
+    /// Stop voting on a denom
+    RemoveDenom(Denom),
+}
+
+impl Outcome {
+    /// Classify the result of a transaction. Unknown denoms are only
+    /// identified if they're among the `voted` denoms.
+    pub fn classify(response: &tx_commit::Response, voted: &[Denom]) -> Self {
+        if response.check_tx.code.is_err() {
+            Self::classify_result(&response.check_tx, voted)
+        } else {
+            Self::classify_result(&response.deliver_tx, voted)
+        }
+    }
+
+    /// Classify a single `CheckTx` or `DeliverTx` result
+    pub fn classify_result(result: &TxResult, voted: &[Denom]) -> Self {
+        if result.code.is_ok() {
+            return Outcome::Success;
+        }
+
+        let code = result.code.value();
+        let codespace = result.codespace.as_ref();
+        let log = result.log.as_ref().to_ascii_lowercase();
+
+        match (codespace, code) {
+            (SDK_CODESPACE, 11) => return Outcome::OutOfGas,
+            (SDK_CODESPACE, 13) => return Outcome::InsufficientFee,
+            (SDK_CODESPACE, 32) => return Outcome::SequenceMismatch,
+            (ORACLE_CODESPACE, 3) | (ORACLE_CODESPACE, 11) => return Outcome::NoPrevote,
+            (ORACLE_CODESPACE, 14) => return Outcome::UnknownDenom(find_denom(&log, voted)),
+            _ => (),
+        }
+
+        // Older chains don't set the codespace consistently, so fall back on
+        // the log message
+        if log.contains("out of gas") {
+            Outcome::OutOfGas
+        } else if log.contains("insufficient fee") {
+            Outcome::InsufficientFee
+        } else if log.contains("account sequence") {
+            Outcome::SequenceMismatch
+        } else if log.contains("no prevote")
+            || log.contains("no aggregate prevote")
+            || log.contains("cannot find prevote")
+        {
+            Outcome::NoPrevote
+        } else if log.contains("already voted") || log.contains("already submitted") {
+            Outcome::AlreadyVoted
+        } else if log.contains("unknown denom") {
+            Outcome::UnknownDenom(find_denom(&log, voted))
+        } else {
+            Outcome::Other { code }
+        }
+    }
+
+    /// Get the action to take in response to this outcome
+    pub fn action(&self) -> Action {
+        match self {
+            Outcome::OutOfGas => Action::BumpGas,
+            Outcome::InsufficientFee => Action::RaiseFee,
+            Outcome::SequenceMismatch | Outcome::NoPrevote => Action::ResendPrevote,
+            Outcome::UnknownDenom(Some(denom)) => Action::RemoveDenom(denom.clone()),
+            _ => Action::None,
+        }
+    }
+
+    /// Short name for this outcome (used as a counter key)
+    pub fn name(&self) -> &'static str {
+        match self {
+            Outcome::Success => "success",
+            Outcome::OutOfGas => "out_of_gas",
+            Outcome::InsufficientFee => "insufficient_fee",
+            Outcome::SequenceMismatch => "sequence_mismatch",
+            Outcome::NoPrevote => "no_prevote",
+            Outcome::AlreadyVoted => "already_voted",
+            Outcome::UnknownDenom(_) => "unknown_denom",
+            Outcome::Other { .. } => "other",
+        }
+    }
+}
+
+impl Display for Outcome {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Outcome::UnknownDenom(Some(denom)) => write!(f, "unknown_denom ({})", denom),
+            Outcome::Other { code } => write!(f, "other (code {})", code),
+            _ => f.write_str(self.name()),
+        }
+    }
+}
+
+/// Number of times each outcome has been seen
+#[derive(Clone, Debug, Default)]
+pub struct Counters(Map<&'static str, u64>);
+
+impl Counters {
+    /// Record an outcome
+    pub fn record(&mut self, outcome: &Outcome) {
+        *self.0.entry(outcome.name()).or_default() += 1;
+    }
+
+    /// Get the count for an outcome
+    pub fn get(&self, outcome: &Outcome) -> u64 {
+        self.0.get(outcome.name()).copied().unwrap_or_default()
+    }
+
+    /// Get the counters as a map
+    pub fn as_map(&self) -> &Map<&'static str, u64> {
+        &self.0
+    }
+}
+
+/// Fee adjusted in response to transaction outcomes: increased after
+/// out-of-gas and insufficient fee failures, and reset to the configured fee
+/// after `FEE_RESET_SUCCESSES` consecutive successes
+#[derive(Clone, Debug)]
+pub struct AdjustedFee {
+    /// Configured fee
+    configured: StdFee,
+
+    /// Current (possibly increased) fee
+    current: StdFee,
+
+    /// Has the current fee been increased?
+    increased: bool,
+
+    /// Consecutive successful transactions
+    successes: u64,
+}
+
+impl AdjustedFee {
+    /// Start from the configured fee
+    pub fn new(configured: StdFee) -> Self {
+        Self {
+            current: configured.clone(),
+            configured,
+            increased: false,
+            successes: 0,
+        }
+    }
+
+    /// Get the configured fee
+    pub fn configured(&self) -> &StdFee {
+        &self.configured
+    }
+
+    /// Get the current fee
+    pub fn current(&self) -> &StdFee {
+        &self.current
+    }
+
+    /// Record a successful transaction, resetting an increased fee once
+    /// enough transactions in a row have succeeded
+    pub fn record_success(&mut self) {
+        if !self.increased {
+            return;
+        }
+
+        self.successes += 1;
+
+        if self.successes >= FEE_RESET_SUCCESSES {
+            info!(
+                "{} transactions succeeded; resetting to the configured fee",
+                self.successes
+            );
+            *self = Self::new(self.configured.clone());
+        }
+    }
+
+    /// Record a failed transaction
+    pub fn record_failure(&mut self) {
+        self.successes = 0;
+    }
+
+    /// Increase the gas limit
+    pub fn bump_gas(&mut self) {
+        self.current = bump_gas(&self.current, &self.configured);
+        self.increased = true;
+        info!("increased gas limit to {}", self.current.gas);
+    }
+
+    /// Increase the fee amount
+    pub fn raise_fee(&mut self) {
+        self.current = raise_fee(&self.current, &self.configured);
+        self.increased = true;
+        info!("increased fee to {:?}", self.current.amount);
+    }
+}
+
+/// Increase the gas limit, up to a multiple of the configured gas
+pub fn bump_gas(fee: &StdFee, configured: &StdFee) -> StdFee {
+    StdFee {
+        amount: fee.amount.clone(),
+        gas: bump(fee.gas, configured.gas),
+    }
+}
+
+/// Increase the fee amount, up to a multiple of the configured fee
+pub fn raise_fee(fee: &StdFee, configured: &StdFee) -> StdFee {
+    let amount = fee
+        .amount
+        .iter()
+        .zip(&configured.amount)
+        .map(|(coin, configured_coin)| {
+            let mut coin = coin.clone();
+
+            if let (Ok(amount), Ok(configured_amount)) =
+                (coin.amount.parse(), configured_coin.amount.parse())
+            {
+                coin.amount = bump(amount, configured_amount).to_string();
+            }
+
+            coin
+        })
+        .collect();
+
+    StdFee {
+        amount,
+        gas: fee.gas,
+    }
+}
+
+/// Increase a value by `FEE_BUMP_PERCENT`, capped at `MAX_FEE_MULTIPLE`
+/// times the configured value
+fn bump(value: u64, configured: u64) -> u64 {
+    let bumped = value.saturating_add((value.saturating_mul(FEE_BUMP_PERCENT) / 100).max(1));
+    bumped.min(configured.saturating_mul(MAX_FEE_MULTIPLE))
+}
+
+/// Find a denom we voted on which is mentioned in a log message. Any word
+/// starting with `u` parses as a denom (e.g. `used`), so only the denoms
+/// actually voted on are matched.
+fn find_denom(log: &str, voted: &[Denom]) -> Option<Denom> {
+    log.split(|c: char| !c.is_ascii_alphanumeric())
+        .find_map(|word| voted.iter().find(|denom| denom.as_str() == word))
+        .cloned()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{bump_gas, raise_fee, Action, AdjustedFee, Counters, Outcome, FEE_RESET_SUCCESSES};
+    use crate::networks::terra::Denom;
+    use serde_json::json;
+    use stdtx::amino::types::{Coin, StdFee};
+    use tendermint_rpc::endpoint::broadcast::tx_commit::TxResult;
+
+    fn tx_result(code: u32, codespace: &str, log: &str) -> TxResult {
+        serde_json::from_value(json!({
+            "code": code,
+            "data": null,
+            "log": log,
+            "info": "",
+            "gas_wanted": "200000",
+            "gas_used": "200000",
+            "events": [],
+            "codespace": codespace
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    fn classify_by_code() {
+        let out_of_gas = tx_result(11, "sdk", "out of gas in location: WriteFlat");
+        assert_eq!(
+            Outcome::classify_result(&out_of_gas, &[]),
+            Outcome::OutOfGas
+        );
+
+        let fee = tx_result(13, "sdk", "insufficient fees; got: 1ukrw required: 2ukrw");
+        assert_eq!(
+            Outcome::classify_result(&fee, &[]),
+            Outcome::InsufficientFee
+        );
+
+        let unknown_denom = tx_result(14, "oracle", "ugbp: unknown denom");
+        assert_eq!(
+            Outcome::classify_result(&unknown_denom, &[Denom::Ukrw, Denom::Ugbp]),
+            Outcome::UnknownDenom(Some(Denom::Ugbp))
+        );
+    }
+
+    #[test]
+    fn unknown_denom_only_matches_voted_denoms() {
+        let voted = [Denom::Ukrw, Denom::Umnt];
+
+        // `used` and `unit` parse as denoms but weren't voted on
+        let log = "unknown denom used in vote: unit umnt";
+        let unknown_denom = tx_result(14, "oracle", log);
+        assert_eq!(
+            Outcome::classify_result(&unknown_denom, &voted),
+            Outcome::UnknownDenom(Some(Denom::Umnt))
+        );
+
+        let unknown_denom = tx_result(1, "", "unknown denom used in vote");
+        assert_eq!(
+            Outcome::classify_result(&unknown_denom, &voted),
+            Outcome::UnknownDenom(None)
+        );
+    }
+
+    #[test]
+    fn classify_by_log() {
+        let sequence = tx_result(
+            4,
+            "",
+            "unauthorized: signature verification failed; verify correct account sequence and chain-id",
+        );
+        assert_eq!(
+            Outcome::classify_result(&sequence, &[]),
+            Outcome::SequenceMismatch
+        );
+
+        let no_prevote = tx_result(102, "", "Cannot find prevote for the vote");
+        assert_eq!(
+            Outcome::classify_result(&no_prevote, &[]),
+            Outcome::NoPrevote
+        );
+
+        let no_prevote = tx_result(102, "", "no aggregate prevote: terravaloper1...");
+        assert_eq!(
+            Outcome::classify_result(&no_prevote, &[]),
+            Outcome::NoPrevote
+        );
+        assert_eq!(Outcome::NoPrevote.action(), Action::ResendPrevote);
+
+        let already_voted = tx_result(5, "", "validator already voted");
+        assert_eq!(
+            Outcome::classify_result(&already_voted, &[]),
+            Outcome::AlreadyVoted
+        );
+
+        let other = tx_result(1, "", "internal error");
+        assert_eq!(
+            Outcome::classify_result(&other, &[]),
+            Outcome::Other { code: 1 }
+        );
+
+        let ok = tx_result(0, "", "");
+        assert_eq!(Outcome::classify_result(&ok, &[]), Outcome::Success);
+    }
+
+    #[test]
+    fn counters() {
+        let mut counters = Counters::default();
+        counters.record(&Outcome::OutOfGas);
+        counters.record(&Outcome::OutOfGas);
+        counters.record(&Outcome::Success);
+
+        assert_eq!(counters.get(&Outcome::OutOfGas), 2);
+        assert_eq!(counters.get(&Outcome::Success), 1);
+        assert_eq!(counters.get(&Outcome::NoPrevote), 0);
+    }
+
+    #[test]
+    fn fee_bumps_are_capped() {
+        let configured = StdFee {
+            amount: vec![Coin {
+                denom: "ukrw".to_owned(),
+                amount: "1000".to_owned(),
+            }],
+            gas: 100_000,
+        };
+
+        let bumped = bump_gas(&configured, &configured);
+        assert_eq!(bumped.gas, 125_000);
+
+        let raised = raise_fee(&configured, &configured);
+        assert_eq!(raised.amount[0].amount, "1250");
+
+        let mut fee = configured.clone();
+
+        for _ in 0..20 {
+            fee = raise_fee(&bump_gas(&fee, &configured), &configured);
+        }
+
+        assert_eq!(fee.gas, 400_000);
+        assert_eq!(fee.amount[0].amount, "4000");
+    }
+
+    #[test]
+    fn increased_fee_resets_after_successes() {
+        let mut fee = AdjustedFee::new(StdFee {
+            amount: vec![Coin {
+                denom: "ukrw".to_owned(),
+                amount: "1000".to_owned(),
+            }],
+            gas: 100_000,
+        });
+
+        fee.bump_gas();
+        fee.raise_fee();
+        assert_eq!(fee.current().gas, 125_000);
+
+        // A failure restarts the count of consecutive successes
+        for _ in 1..FEE_RESET_SUCCESSES {
+            fee.record_success();
+        }
+
+        fee.record_failure();
+
+        for _ in 1..FEE_RESET_SUCCESSES {
+            fee.record_success();
+        }
+
+        assert_eq!(fee.current().amount[0].amount, "1250");
+
+        fee.record_success();
+        assert_eq!(fee.current().gas, 100_000);
+        assert_eq!(fee.current().amount[0].amount, "1000");
+    }
+}
@@ -0,0 +1,340 @@
+//! Feeder/validator identities
+//!
+//! A single Delphi can vote on behalf of several validators on the same
+//! network. Exchange rates are collected once per vote period and shared,
+//! while each voter has its own salts, prevotes, feeder account sequence and
+//! fee.
+//! Requests from Tendermint KMS select a voter by their context.
+
+use super::{
+    msg::{ExchangeRates, MsgAggregateExchangeRateVote, OracleMsg},
+    parse_address,
+    pending_vote::PendingVote,
+    sequence::SequenceManager,
+    signer::ACCOUNT_PREFIX,
+    tx_outcome::AdjustedFee,
+};
+use crate::{
+    config::network::{OracleMode, VoterConfig},
+    prelude::*,
+    Error,
+};
+use serde_json::json;
+use std::path::{Path, PathBuf};
+use stdtx::{amino::types::StdFee, Address};
+
+/// Context of the voter configured by the top-level `feeder`/`validator`
+pub const DEFAULT_CONTEXT: &str = "";
+
+/// Feeder/validator identity and its vote state
+pub struct Voter {
+    /// Request context selecting this voter
+    pub context: String,
+
+    /// Feeder address
+    pub feeder: Address,
+
+    /// Validator address
+    pub validator: Address,
+
+    /// Previously unrevealed vote
+    pub unrevealed_vote: Option<PendingVote>,
+
+    /// File in which the unrevealed vote is persisted
+    pub state_file: Option<PathBuf>,
+
+    /// Vote period in which the last prevote was sent
+    pub last_vote_period: Option<u64>,
+
+    /// Did the transaction containing the last prevote succeed?
+    pub prevote_confirmed: bool,
+
+    /// Feeder account number and sequence
+    pub sequence: SequenceManager,
+
+    /// Fee (adjusted in response to this voter's transaction outcomes)
+    pub fee: AdjustedFee,
+}
+
+impl Voter {
+    /// Create a voter from config, restoring its unrevealed vote (if any)
+    pub fn new(config: &VoterConfig, fee: &StdFee) -> Result<Self, Error> {
+        let feeder = parse_address(&config.feeder)?;
+        let validator = parse_address(&config.validator)?;
+        let unrevealed_vote = config.state_file.as_deref().and_then(load_pending_vote);
+
+        // A restored vote was prevoted in the period before its reveal period
+        let last_vote_period = unrevealed_vote
+            .as_ref()
+            .and_then(|pending| pending.vote_period)
+            .map(|period| period.saturating_sub(1));
+
+        Ok(Self {
+            context: config.context.clone(),
+            feeder,
+            validator,
+            unrevealed_vote,
+            state_file: config.state_file.clone(),
+            last_vote_period,
+            prevote_confirmed: false,
+            sequence: SequenceManager::new(),
+            fee: AdjustedFee::new(fee.clone()),
+        })
+    }
+
+    /// Has this voter already sent a prevote in the given vote period?
+    pub fn has_voted_in(&self, current_period: Option<u64>) -> bool {
+        match (current_period, self.last_vote_period) {
+            (Some(current), Some(last)) => current <= last,
+            _ => false,
+        }
+    }
+
+    /// Build this voter's oracle messages for the given exchange rates: the
+    /// reveal of its pending vote (if `reveal` is set, i.e. its prevote is
+    /// known to have succeeded) and a new prevote
+    pub fn vote_msgs(
+        &mut self,
+        exchange_rates: ExchangeRates,
+        current_period: Option<u64>,
+        reveal: bool,
+        oracle_mode: OracleMode,
+        authz: bool,
+    ) -> Vec<OracleMsg> {
+        if let Some(period) = current_period {
+            self.expire_pending_vote(period);
+        }
+
+        // Move all previously unrevealed votes into the result
+        let mut msgs = vec![];
+
+        if let Some(pending) = self.unrevealed_vote.take() {
+            if reveal {
+                // Only include the previous vote if we succeeded in publishing
+                // an oracle prevote. Otherwise DeliverTx fails because we
+                // don't have a corresponding prevote
+                match oracle_mode {
+                    OracleMode::Aggregate => msgs.push(OracleMsg::AggregateVote(pending.vote)),
+                    OracleMode::PerDenom => msgs.extend(
+                        pending
+                            .vote
+                            .per_denom_votes()
+                            .into_iter()
+                            .map(OracleMsg::Vote),
+                    ),
+                }
+            }
+        }
+
+        // With authz, the validator's account sends the oracle messages and
+        // the feeder executes them on its behalf
+        let vote_msg = MsgAggregateExchangeRateVote {
+            exchange_rates,
+            salt: MsgAggregateExchangeRateVote::random_salt(),
+            feeder: if authz { self.validator } else { self.feeder },
+            validator: self.validator,
+        };
+
+        match oracle_mode {
+            OracleMode::Aggregate => msgs.push(OracleMsg::AggregatePrevote(vote_msg.prevote())),
+            OracleMode::PerDenom => msgs.extend(
+                vote_msg
+                    .per_denom_votes()
+                    .iter()
+                    .map(|vote| OracleMsg::Prevote(vote.prevote())),
+            ),
+        }
+
+        let pending = PendingVote {
+            vote_period: current_period.map(|period| period + 1),
+            vote: vote_msg,
+        };
+
+        if let Some(path) = &self.state_file {
+            if let Err(e) = pending.save(path) {
+                error!("error saving unrevealed vote to {}: {}", path.display(), e);
+            }
+        }
+
+        self.unrevealed_vote = Some(pending);
+        self.prevote_confirmed = false;
+
+        if current_period.is_some() {
+            self.last_vote_period = current_period;
+        }
+
+        if authz {
+            msgs = vec![OracleMsg::Exec {
+                grantee: self.feeder,
+                msgs,
+            }];
+        }
+
+        msgs
+    }
+
+    /// Discard the unrevealed vote (and remove its state file) if it can no
+    /// longer be revealed in the current vote period
+    pub fn expire_pending_vote(&mut self, current_period: u64) {
+        match &self.unrevealed_vote {
+            Some(pending) if !pending.is_revealable(current_period) => {
+                info!(
+                    "discarding unrevealed vote for vote period {:?} (current period {})",
+                    pending.vote_period, current_period
+                );
+            }
+            _ => return,
+        }
+
+        self.unrevealed_vote = None;
+
+        if let Some(path) = &self.state_file {
+            if let Err(e) = PendingVote::remove(path) {
+                warn!("error removing {}: {}", path.display(), e);
+            }
+        }
+    }
+
+    /// Get this voter's status
+    pub fn status(&self) -> serde_json::Value {
+        let account = self.sequence.account();
+
+        json!({
+            "context": self.context,
+            "validator": self.validator.to_bech32("terravaloper"),
+            "last_vote_period": self.last_vote_period,
+            "prevote_confirmed": self.prevote_confirmed,
+            "fee": self.fee.current(),
+            "account": {
+                "address": self.feeder.to_bech32(ACCOUNT_PREFIX),
+                "account_number": account.map(|account| account.account_number),
+                "sequence": account.map(|account| account.sequence),
+                "sequence_mismatches": self.sequence.mismatches(),
+                "syncs": self.sequence.syncs(),
+            },
+        })
+    }
+}
+
+/// Load a previously persisted unrevealed vote, logging any errors
+fn load_pending_vote(path: &Path) -> Option<PendingVote> {
+    match PendingVote::load(path) {
+        Ok(Some(pending)) => {
+            info!(
+                "restored unrevealed vote from {} (vote period {:?})",
+                path.display(),
+                pending.vote_period
+            );
+            Some(pending)
+        }
+        Ok(None) => None,
+        Err(e) => {
+            warn!(
+                "discarding unreadable vote state in {}: {}",
+                path.display(),
+                e
+            );
+            None
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Voter;
+    use crate::{
+        config::network::{OracleMode, VoterConfig},
+        networks::terra::msg::{ExchangeRates, OracleMsg},
+    };
+    use stdtx::{amino::types::StdFee, Address};
+
+    fn voter_config(n: u8) -> VoterConfig {
+        VoterConfig {
+            context: format!("validator-{}", n),
+            feeder: Address([n; 20]).to_bech32("terra"),
+            validator: Address([n + 1; 20]).to_bech32("terravaloper"),
+            state_file: None,
+        }
+    }
+
+    fn voter(n: u8) -> Voter {
+        Voter::new(&voter_config(n), &StdFee::default()).unwrap()
+    }
+
+    fn rates() -> ExchangeRates {
+        "362.000000000000000000ukrw".parse().unwrap()
+    }
+
+    fn prevote_hash(msgs: &[OracleMsg]) -> String {
+        match msgs.last().unwrap() {
+            OracleMsg::AggregatePrevote(prevote) => prevote.hex_hash(),
+            other => panic!("expected prevote, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn independent_vote_state() {
+        let (mut voter1, mut voter2) = (voter(1), voter(3));
+
+        let msgs1 = voter1.vote_msgs(rates(), Some(10), true, OracleMode::Aggregate, false);
+        assert_eq!(msgs1.len(), 1);
+        assert!(voter1.has_voted_in(Some(10)));
+        assert!(!voter2.has_voted_in(Some(10)));
+
+        // Each voter commits to the same rates with its own salt and prevote
+        let msgs2 = voter2.vote_msgs(rates(), Some(10), true, OracleMode::Aggregate, false);
+        assert_ne!(prevote_hash(&msgs1), prevote_hash(&msgs2));
+
+        let msgs1 = voter1.vote_msgs(rates(), Some(11), true, OracleMode::Aggregate, false);
+        assert_eq!(msgs1.len(), 2);
+
+        match &msgs1[0] {
+            OracleMsg::AggregateVote(vote) => {
+                assert_eq!(vote.feeder, Address([1; 20]));
+                assert_eq!(vote.validator, Address([2; 20]));
+            }
+            other => panic!("expected vote, got {:?}", other),
+        }
+
+        assert_eq!(
+            voter2.unrevealed_vote.as_ref().unwrap().vote_period,
+            Some(11)
+        );
+    }
+
+    #[test]
+    fn expires_restored_vote() {
+        let path = std::env::temp_dir().join(format!("delphi-voter-{}.json", std::process::id()));
+        let config = VoterConfig {
+            state_file: Some(path.clone()),
+            ..voter_config(1)
+        };
+
+        let mut voter = Voter::new(&config, &StdFee::default()).unwrap();
+        voter.vote_msgs(rates(), Some(10), true, OracleMode::Aggregate, false);
+        assert!(path.exists());
+
+        // Still revealable in the period after the prevote
+        let mut restored = Voter::new(&config, &StdFee::default()).unwrap();
+        restored.expire_pending_vote(11);
+        assert!(restored.unrevealed_vote.is_some());
+
+        // Expired once that period has passed, including on disk
+        let mut restored = Voter::new(&config, &StdFee::default()).unwrap();
+        restored.expire_pending_vote(12);
+        assert!(restored.unrevealed_vote.is_none());
+        assert!(!path.exists());
+    }
+
+    #[test]
+    fn rejects_invalid_address() {
+        let config = VoterConfig {
+            context: "validator-1".to_owned(),
+            feeder: "terra1...".to_owned(),
+            validator: Address([2; 20]).to_bech32("terravaloper"),
+            state_file: None,
+        };
+
+        assert!(Voter::new(&config, &StdFee::default()).is_err());
+    }
+}
@@ -1,17 +1,27 @@
 //! HTTP request router (based on warp)
 //!
+//! Oracle requests are dispatched to the oracle for the request's chain ID
+//! (`network`), so a single Delphi can serve several networks.
+//!
 //! Test with:
 //!
 //! ```text
 //! curl -i -X POST -H "Content-Type: application/json" -d '{"network":"columbus-4"}' http://127.0.0.1:23456/oracles/terra
 //! ```
 
-use crate::{config::listen::Protocol, error::Error, networks::terra, prelude::*};
+use crate::{
+    config::listen::Protocol, error::Error, networks::terra, prelude::*, sources::Sources,
+    ErrorKind, Map,
+};
 use serde::Deserialize;
-use std::convert::Infallible;
+use serde_json::json;
+use std::{collections::BTreeSet, convert::Infallible, sync::Arc};
 use tendermint::chain;
 use tendermint_rpc::endpoint::{broadcast::tx_commit, status::SyncInfo};
-use warp::Filter;
+use warp::{http::StatusCode, Filter, Reply};
+
+/// Terra oracles by chain ID
+pub type TerraOracles = Arc<Map<chain::Id, terra::ExchangeRateOracle>>;
 
 /// HTTP request router
 #[derive(Clone)]
@@ -22,8 +32,11 @@ pub struct Router {
     /// Protocol to listen on
     protocol: Protocol,
 
-    /// Terra oracle
-    terra_oracle: terra::ExchangeRateOracle,
+    /// Market data sources (shared by all networks)
+    sources: Arc<Sources>,
+
+    /// Terra oracles
+    terra_oracles: TerraOracles,
 }
 
 impl Router {
@@ -32,11 +45,56 @@ impl Router {
         let config = APP.config();
         let addr = (config.listen.addr.octets(), config.listen.port);
         let protocol = config.listen.protocol;
-        let terra_oracle = terra::ExchangeRateOracle::new(&config)?;
+        let sources = Arc::new(Sources::new(&config)?);
+
+        ensure!(
+            !config.network.terra.is_empty(),
+            ErrorKind::Config,
+            "missing [network.terra] config"
+        );
+
+        let mut terra_oracles = Map::new();
+        let mut state_files = BTreeSet::new();
+
+        for terra_config in &config.network.terra {
+            let chain_id = terra_config.chain_id.parse::<chain::Id>().map_err(|e| {
+                format_err!(
+                    ErrorKind::Config,
+                    "invalid chain ID {}: {}",
+                    terra_config.chain_id,
+                    e
+                )
+            })?;
+
+            ensure!(
+                !terra_oracles.contains_key(&chain_id),
+                ErrorKind::Config,
+                "multiple [network.terra] configs for {}",
+                chain_id
+            );
+
+            for voter_config in terra_config.voter_configs() {
+                if let Some(state_file) = voter_config.state_file {
+                    ensure!(
+                        !state_files.contains(&state_file),
+                        ErrorKind::Config,
+                        "[network.terra] state_file {} is used by multiple voters",
+                        state_file.display()
+                    );
+
+                    state_files.insert(state_file);
+                }
+            }
+
+            let oracle = terra::ExchangeRateOracle::new(&config, terra_config, sources.clone())?;
+            terra_oracles.insert(chain_id, oracle);
+        }
+
         Ok(Self {
             addr,
             protocol,
-            terra_oracle,
+            sources,
+            terra_oracles: Arc::new(terra_oracles),
         })
     }
 
@@ -44,30 +102,107 @@ impl Router {
     pub async fn route(self) {
         let addr = self.addr;
         let protocol = self.protocol;
-        let terra_oracle_filter = warp::any().map(move || self.terra_oracle.clone());
 
-        let app = warp::post()
+        if let Err(e) = self.sources.streams.spawn() {
+            status_err!("error starting market data streams: {}", e);
+        }
+
+        for (chain_id, oracle) in self.terra_oracles.iter() {
+            info!("serving Terra oracle for {}", chain_id);
+            oracle.sync_params().await;
+            oracle.expire_pending_votes().await;
+            oracle.spawn_params_sync().await;
+            oracle.spawn_signer().await;
+        }
+
+        let terra_oracles = self.terra_oracles;
+        let terra_oracles_filter = warp::any().map(move || terra_oracles.clone());
+
+        let oracle = warp::post()
             .and(warp::path("oracle"))
             .and(warp::path::end())
-            .and(terra_oracle_filter.clone())
+            .and(terra_oracles_filter.clone())
             .and(warp::body::json())
             .and_then(oracle_request);
 
+        let status = warp::get()
+            .and(warp::path("status"))
+            .and(warp::path::end())
+            .and(terra_oracles_filter.clone())
+            .and_then(status_request);
+
+        let network_status = warp::get()
+            .and(warp::path!("status" / String))
+            .and(terra_oracles_filter)
+            .and_then(network_status_request);
+
+        let app = oracle.or(status).or(network_status);
+
         match protocol {
             Protocol::Http => warp::serve(app).run(addr).await,
         }
     }
 }
 
-/// `POST /oracle` - handle incoming oracle requests
+/// `POST /oracle` - handle incoming oracle requests, dispatching them to the
+/// oracle for the request's chain ID
 ///
 /// This endpoint is intended to be triggered by Tendermint KMS
 pub async fn oracle_request(
-    oracle: terra::ExchangeRateOracle,
+    oracles: TerraOracles,
     req: Request,
-) -> Result<impl warp::Reply, Infallible> {
-    // TODO(tarcieri): dispatch incoming requests based on chain ID
-    oracle.handle_request(req).await
+) -> Result<warp::reply::Response, Infallible> {
+    match oracles.get(&req.network) {
+        Some(oracle) => oracle
+            .clone()
+            .handle_request(req)
+            .await
+            .map(Reply::into_response),
+        None => {
+            warn!("no oracle configured for chain ID: {}", req.network);
+            Ok(unknown_chain_id(req.network.as_str()))
+        }
+    }
+}
+
+/// `GET /status` - status of each network's oracle (keyed by chain ID),
+/// including transaction outcome counters
+pub async fn status_request(oracles: TerraOracles) -> Result<impl Reply, Infallible> {
+    let mut statuses = Map::new();
+
+    for (chain_id, oracle) in oracles.iter() {
+        statuses.insert(chain_id.to_string(), oracle.status().await);
+    }
+
+    Ok(warp::reply::json(&statuses))
+}
+
+/// `GET /status/{chain_id}` - status of a single network's oracle
+pub async fn network_status_request(
+    chain_id: String,
+    oracles: TerraOracles,
+) -> Result<warp::reply::Response, Infallible> {
+    let oracle = chain_id
+        .parse::<chain::Id>()
+        .ok()
+        .and_then(|chain_id| oracles.get(&chain_id));
+
+    Ok(match oracle {
+        Some(oracle) => warp::reply::json(&oracle.status().await).into_response(),
+        None => unknown_chain_id(&chain_id),
+    })
+}
+
+/// Error response for a chain ID with no oracle configured
+fn unknown_chain_id(chain_id: &str) -> warp::reply::Response {
+    warp::reply::with_status(
+        warp::reply::json(&json!({
+            "status": "error",
+            "error": format!("unknown chain ID: {}", chain_id),
+        })),
+        StatusCode::NOT_FOUND,
+    )
+    .into_response()
 }
 
 /// Incoming oracle requests from Tendermint KMS (serialized as JSON)
@@ -86,3 +221,23 @@ pub struct Request {
     /// Response from last signed TX (if available)
     pub last_tx_response: Option<tx_commit::Response>,
 }
+
+#[cfg(test)]
+mod tests {
+    use super::{oracle_request, Request};
+    use crate::Map;
+    use serde_json::{json, Value};
+    use std::sync::Arc;
+    use warp::hyper::body;
+
+    #[tokio::test]
+    async fn rejects_unknown_chain_id() {
+        let req: Request = serde_json::from_value(json!({"network": "bombay-12"})).unwrap();
+        let response = oracle_request(Arc::new(Map::new()), req).await.unwrap();
+        assert_eq!(response.status(), 404);
+
+        let body = body::to_bytes(response.into_body()).await.unwrap();
+        let body: Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(body["error"], "unknown chain ID: bombay-12");
+    }
+}
@@ -6,19 +6,28 @@ pub mod bithumb;
 pub mod coinone;
 pub mod currencylayer;
 pub mod dunamu;
+pub mod ecb;
 pub mod gdac;
 pub mod gopax;
 pub mod imf_sdr;
+pub mod json;
+pub mod kraken;
+pub mod upbit;
 
 use self::{
     alphavantage::AlphavantageSource, binance::BinanceSource, bithumb::BithumbSource,
     coinone::CoinoneSource, currencylayer::CurrencylayerSource, dunamu::DunamuSource,
-    gdac::GdacSource, gopax::GopaxSource, imf_sdr::ImfSdrSource,
+    ecb::EcbSource, gdac::GdacSource, gopax::GopaxSource, imf_sdr::ImfSdrSource, json::JsonSource,
+    kraken::KrakenSource, upbit::UpbitSource,
 };
 use crate::{
     config::{source::AlphavantageConfig, DelphiConfig},
-    Error, Price, PriceQuantity,
+    prelude::*,
+    streaming::Streams,
+    Error, Map, Price, PriceQuantity, TradingPair,
 };
+use chrono::{NaiveDate, Utc};
+use futures::future::join_all;
 use rust_decimal::Decimal;
 
 // TODO(shella): factor this into e.g. a common Tower service when we have 2+ oracles
@@ -41,6 +50,10 @@ pub struct Sources {
     /// <https://dunamu.com/views/01_main.html>
     pub dunamu: DunamuSource,
 
+    /// European Central Bank
+    /// <https://www.ecb.europa.eu/>
+    pub ecb: EcbSource,
+
     /// GDAC
     /// <https://www.gdac.com/?locale=en_us>
     pub gdac: GdacSource,
@@ -60,6 +73,20 @@ pub struct Sources {
     /// Currencylayer
     /// <https://api.currencylayer.com>
     pub currencylayer: CurrencylayerSource,
+
+    /// Kraken
+    /// <https://docs.kraken.com/rest/>
+    pub kraken: KrakenSource,
+
+    /// Upbit
+    /// <https://docs.upbit.com/reference>
+    pub upbit: UpbitSource,
+
+    /// Generic JSON-over-HTTP sources (keyed by name)
+    pub json: Map<String, JsonSource>,
+
+    /// Live WebSocket market data
+    pub streams: Streams,
 }
 
 impl Sources {
@@ -79,11 +106,13 @@ impl Sources {
         )?;
         let binance = BinanceSource::new(&config.https)?;
         let coinone = CoinoneSource::new(&config.https)?;
+        let ecb = EcbSource::new(&config.https)?;
         let gdac = GdacSource::new(&config.https)?;
         let dunamu = DunamuSource::new(&config.https)?;
         let gopax = GopaxSource::new(&config.https)?;
         let imf_sdr = ImfSdrSource::new(&config.https)?;
         let bithumb = BithumbSource::new(&config.https)?;
+        let upbit = UpbitSource::new(&config.https)?;
         let currencylayer = CurrencylayerSource::new(
             &config
                 .source
@@ -93,19 +122,83 @@ impl Sources {
                 .access_key,
             &config.https,
         )?;
+        let kraken = KrakenSource::new(
+            &config.source.kraken.clone().unwrap_or_default(),
+            &config.https,
+        )?;
+
+        let json = config
+            .source
+            .json
+            .iter()
+            .map(|(name, json_config)| {
+                JsonSource::new(json_config, &config.https).map(|source| (name.clone(), source))
+            })
+            .collect::<Result<_, _>>()?;
 
         Ok(Sources {
             alphavantage,
             binance,
             coinone,
             dunamu,
+            ecb,
             gdac,
             gopax,
             imf_sdr,
             bithumb,
             currencylayer,
+            kraken,
+            upbit,
+            json,
+            streams: Streams::new(config),
         })
     }
+
+    /// Get prices for the given trading pair from the generic JSON sources
+    /// configured to quote it, logging any errors
+    pub async fn json_prices(&self, pair: &TradingPair) -> Vec<Price> {
+        let sources = self
+            .json
+            .iter()
+            .filter(|(_, source)| source.quotes(pair))
+            .collect::<Vec<_>>();
+
+        let results = join_all(sources.iter().map(|(_, source)| source.trading_pairs(pair))).await;
+
+        sources
+            .iter()
+            .zip(results)
+            .filter_map(|((name, _), result)| match result {
+                Ok(price) => Some(price),
+                Err(e) => {
+                    warn!("JSON source {} error for {}: {}", name, pair, e);
+                    None
+                }
+            })
+            .collect()
+    }
+}
+
+/// Official rate (e.g. from the IMF or ECB) along with its publication date
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub struct OfficialRate {
+    /// Price
+    pub price: Price,
+
+    /// Date the rate was published
+    pub date: NaiveDate,
+}
+
+impl OfficialRate {
+    /// Number of days since this rate was published
+    pub fn age_days(&self) -> i64 {
+        (Utc::today().naive_utc() - self.date).num_days()
+    }
+
+    /// Was this rate published more than `max_age_days` ago?
+    pub fn is_stale(&self, max_age_days: i64) -> bool {
+        self.age_days() > max_age_days
+    }
 }
 
 ///This trait allows writing generic functions over ask orderbook from multiple sources
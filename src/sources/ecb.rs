@@ -0,0 +1,311 @@
+//! European Central Bank Source Provider
+//! <https://www.ecb.europa.eu/stats/policy_and_exchange_rates/euro_reference_exchange_rates/html/index.en.html>
+//!
+//! Parses the ECB's euro foreign exchange reference rates, which are
+//! published as XML on business days around 16:00 CET. Rates are quoted in
+//! currency units per euro, so other pairs (e.g. USD/KRW) are cross rates.
+
+use crate::{
+    config::HttpsConfig,
+    error::{Error, ErrorKind},
+    prelude::*,
+    sources::OfficialRate,
+    Currency, Map, Price, TradingPair,
+};
+use bytes::Buf;
+use chrono::NaiveDate;
+use iqhttp::{HttpsClient, Query};
+use rust_decimal::Decimal;
+use std::{io::Read, str::FromStr};
+
+/// Hostname for the ECB website
+pub const API_HOST: &str = "www.ecb.europa.eu";
+
+/// Path to the latest daily reference rates
+pub const DAILY_PATH: &str = "/stats/eurofxref/eurofxref-daily.xml";
+
+/// Path to the reference rates for the last 90 days
+pub const HISTORY_90D_PATH: &str = "/stats/eurofxref/eurofxref-hist-90d.xml";
+
+/// Source provider for the ECB's euro reference rates
+pub struct EcbSource {
+    https_client: HttpsClient,
+}
+
+impl EcbSource {
+    /// Create a new ECB source provider
+    pub fn new(config: &HttpsConfig) -> Result<Self, Error> {
+        let https_client = config.new_client(API_HOST)?;
+        Ok(Self { https_client })
+    }
+
+    /// Get trading pairs.
+    ///
+    /// Any two listed currencies (or EUR) may be used, e.g. `EUR/KRW` or the
+    /// cross rate `USD/KRW`.
+    pub async fn trading_pairs(&self, pair: &TradingPair) -> Result<OfficialRate, Error> {
+        self.daily()
+            .await?
+            .quote(pair)
+            .ok_or_else(|| format_err!(ErrorKind::Source, "price for {} not found", pair).into())
+    }
+
+    /// Fetch the latest daily reference rates
+    pub async fn daily(&self) -> Result<Rates, Error> {
+        self.get_xml(DAILY_PATH).await?.parse()
+    }
+
+    /// Fetch the reference rates for the last 90 days
+    pub async fn history_90d(&self) -> Result<History, Error> {
+        self.get_xml(HISTORY_90D_PATH).await?.parse()
+    }
+
+    /// Fetch an XML document
+    async fn get_xml(&self, path: &str) -> Result<String, Error> {
+        let mut body = String::new();
+        self.https_client
+            .get_body(path, &Query::new())
+            .await?
+            .reader()
+            .read_to_string(&mut body)?;
+
+        Ok(body)
+    }
+}
+
+/// Reference rates published on a given date
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Rates {
+    /// Publication date
+    pub date: NaiveDate,
+
+    /// Currency units per euro
+    pub rates: Map<Currency, Price>,
+}
+
+impl Rates {
+    /// Get the number of currency units per euro (1 for EUR itself)
+    pub fn per_eur(&self, currency: &Currency) -> Option<Decimal> {
+        match currency {
+            Currency::Eur => Some(Decimal::from(1u8)),
+            other => self.rates.get(other).map(Into::into),
+        }
+    }
+
+    /// Get a quote for the given pair, cross rating via EUR if neither side
+    /// of the pair is EUR
+    pub fn quote(&self, pair: &TradingPair) -> Option<OfficialRate> {
+        let base = self.per_eur(&pair.0)?;
+        let quote = self.per_eur(&pair.1)?;
+
+        if base.is_zero() {
+            return None;
+        }
+
+        Some(OfficialRate {
+            price: Price::new(quote / base).ok()?,
+            date: self.date,
+        })
+    }
+}
+
+impl FromStr for Rates {
+    type Err = Error;
+
+    fn from_str(xml: &str) -> Result<Self, Error> {
+        let history = xml.parse::<History>()?;
+        let mut days = history.days.into_iter();
+
+        match (days.next(), days.next()) {
+            (Some(rates), None) => Ok(rates),
+            _ => fail!(
+                ErrorKind::Parse,
+                "expected exactly one day of ECB reference rates"
+            ),
+        }
+    }
+}
+
+/// Reference rates for a range of dates
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct History {
+    /// Rates for each publication date, most recent first
+    pub days: Vec<Rates>,
+}
+
+impl History {
+    /// Get the most recently published quote for the given pair
+    pub fn latest(&self, pair: &TradingPair) -> Option<OfficialRate> {
+        self.days.iter().find_map(|rates| rates.quote(pair))
+    }
+
+    /// Get the rates published on the given date
+    pub fn on(&self, date: NaiveDate) -> Option<&Rates> {
+        self.days.iter().find(|rates| rates.date == date)
+    }
+}
+
+impl FromStr for History {
+    type Err = Error;
+
+    /// The rates are nested `Cube` elements: an outer `Cube` per publication
+    /// date (with a `time` attribute) containing a `Cube` for each currency
+    /// (with `currency` and `rate` attributes). Everything else is ignored.
+    fn from_str(xml: &str) -> Result<Self, Error> {
+        let mut history = History::default();
+
+        for element in xml.split('<').skip(1) {
+            let tag = match element.find('>') {
+                Some(end) => &element[..end],
+                None => fail!(ErrorKind::Parse, "malformed ECB XML: unterminated tag"),
+            };
+
+            if !tag.starts_with("Cube") {
+                continue;
+            }
+
+            if let Some(time) = attribute(tag, "time") {
+                let date = NaiveDate::parse_from_str(time, "%Y-%m-%d")
+                    .map_err(|e| format_err!(ErrorKind::Parse, "bad ECB date: {}", e))?;
+
+                history.days.push(Rates {
+                    date,
+                    rates: Map::new(),
+                });
+            } else if let (Some(currency), Some(rate)) =
+                (attribute(tag, "currency"), attribute(tag, "rate"))
+            {
+                let rates = match history.days.last_mut() {
+                    Some(rates) => rates,
+                    None => fail!(ErrorKind::Parse, "ECB rate for {} has no date", currency),
+                };
+
+                rates.rates.insert(currency.parse()?, rate.parse()?);
+            }
+        }
+
+        ensure!(
+            !history.days.is_empty(),
+            ErrorKind::Parse,
+            "no reference rates found in ECB XML"
+        );
+
+        Ok(history)
+    }
+}
+
+/// Get the value of an attribute from the contents of an XML tag
+fn attribute<'a>(tag: &'a str, name: &str) -> Option<&'a str> {
+    let mut rest = tag;
+
+    while let Some(pos) = rest.find(name) {
+        let preceded_by_space = rest[..pos].ends_with(char::is_whitespace);
+        let after = rest[pos + name.len()..].trim_start();
+        rest = &rest[pos + name.len()..];
+
+        if !preceded_by_space {
+            continue;
+        }
+
+        let value = match after.strip_prefix('=') {
+            Some(value) => value.trim_start(),
+            None => continue,
+        };
+
+        let quote = value.chars().next().filter(|&c| c == '"' || c == '\'')?;
+        let value = &value[1..];
+        return value.find(quote).map(|end| &value[..end]);
+    }
+
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{EcbSource, History, Rates};
+    use crate::{Currency, Price};
+    use chrono::NaiveDate;
+    use rust_decimal::Decimal;
+
+    const DAILY_XML: &str = r#"<?xml version="1.0" encoding="UTF-8"?>
+<gesmes:Envelope xmlns:gesmes="http://www.gesmes.org/xml/2002-08-01" xmlns="http://www.ecb.int/vocabulary/2002-08-01/eurofxref">
+	<gesmes:subject>Reference rates</gesmes:subject>
+	<gesmes:Sender>
+		<gesmes:name>European Central Bank</gesmes:name>
+	</gesmes:Sender>
+	<Cube>
+		<Cube time='2021-10-15'>
+			<Cube currency='USD' rate='1.1602'/>
+			<Cube currency='JPY' rate='132.51'/>
+			<Cube currency='GBP' rate='0.84475'/>
+			<Cube currency='KRW' rate='1371.76'/>
+		</Cube>
+	</Cube>
+</gesmes:Envelope>"#;
+
+    const HISTORY_XML: &str = r#"<?xml version="1.0" encoding="UTF-8"?>
+<gesmes:Envelope xmlns:gesmes="http://www.gesmes.org/xml/2002-08-01" xmlns="http://www.ecb.int/vocabulary/2002-08-01/eurofxref">
+	<gesmes:subject>Reference rates</gesmes:subject>
+	<Cube>
+		<Cube time="2021-10-15"><Cube currency="USD" rate="1.1602"/><Cube currency="KRW" rate="1371.76"/></Cube>
+		<Cube time="2021-10-14"><Cube currency="USD" rate="1.1602"/></Cube>
+	</Cube>
+</gesmes:Envelope>"#;
+
+    #[test]
+    fn parse_daily() {
+        let rates: Rates = DAILY_XML.parse().unwrap();
+        assert_eq!(rates.date, NaiveDate::from_ymd(2021, 10, 15));
+        assert_eq!(rates.rates.len(), 4);
+        assert_eq!(rates.rates[&Currency::Jpy], "132.51".parse().unwrap());
+
+        let eur_krw = rates.quote(&"EUR/KRW".parse().unwrap()).unwrap();
+        assert_eq!(eur_krw.price, "1371.76".parse().unwrap());
+        assert_eq!(eur_krw.date, rates.date);
+
+        let krw_eur = rates.quote(&"KRW/EUR".parse().unwrap()).unwrap();
+        let product = krw_eur.price * "1371.76".parse::<Price>().unwrap();
+        assert_eq!(product.round_dp(8), Decimal::from(1u8));
+    }
+
+    #[test]
+    fn cross_rate() {
+        let rates: Rates = DAILY_XML.parse().unwrap();
+        let usd_krw = rates.quote(&"USD/KRW".parse().unwrap()).unwrap();
+        assert_eq!(
+            usd_krw.price.round_dp(4),
+            "1182.3479".parse::<Decimal>().unwrap()
+        );
+        assert!(rates.quote(&"USD/MNT".parse().unwrap()).is_none());
+    }
+
+    #[test]
+    fn parse_history() {
+        let history: History = HISTORY_XML.parse().unwrap();
+        assert_eq!(history.days.len(), 2);
+        assert_eq!(history.days[1].date, NaiveDate::from_ymd(2021, 10, 14));
+
+        // KRW was only published on the most recent date
+        let usd_krw = history.latest(&"USD/KRW".parse().unwrap()).unwrap();
+        assert_eq!(usd_krw.date, NaiveDate::from_ymd(2021, 10, 15));
+        assert!(history
+            .on(NaiveDate::from_ymd(2021, 10, 14))
+            .unwrap()
+            .quote(&"USD/KRW".parse().unwrap())
+            .is_none());
+
+        // The history file has multiple dates, so it isn't a daily file
+        assert!(HISTORY_XML.parse::<Rates>().is_err());
+    }
+
+    #[tokio::test]
+    #[ignore]
+    async fn trading_pairs_ok() {
+        let pair = "USD/KRW".parse().unwrap();
+        let _quote = EcbSource::new(&Default::default())
+            .unwrap()
+            .trading_pairs(&pair)
+            .await
+            .unwrap();
+    }
+}
@@ -1,147 +1,312 @@
 //! IMF SDR Source Provider
 //! <https://www.imf.org/>
+//!
+//! Parses the "SDRs per Currency unit and Currency units per SDR, last five
+//! days" report: <https://www.imf.org/external/np/fin/data/rms_five.aspx>
 
 use crate::{
     config::HttpsConfig,
     error::{Error, ErrorKind},
     prelude::*,
-    Currency, Price, TradingPair,
+    sources::OfficialRate,
+    Currency, Map, Price, TradingPair,
 };
 use bytes::Buf;
-use iqhttp::HttpsClient;
-use serde::{Deserialize, Serialize};
-use std::convert::TryFrom;
+use chrono::NaiveDate;
+use iqhttp::{HttpsClient, Query};
+use std::{io::Read, str::FromStr};
 
-//https://www.imf.org/external/np/fin/data/rms_five.aspx?tsvflag=Y"
-
-/// Base URI for requests to the Coinone API
+/// Hostname for the IMF website
 pub const API_HOST: &str = "www.imf.org";
 
-/// The IMF returns tab seperated data is form that is designed to for
+/// Path to the five day SDR rates report
+pub const RATES_PATH: &str = "/external/np/fin/data/rms_five.aspx";
+
+/// Date format used in the report's column headers, e.g. `October 14, 2021`
+const DATE_FORMAT: &str = "%B %d, %Y";
 
+/// Source provider for the IMF's SDR exchange rates
 pub struct ImfSdrSource {
     https_client: HttpsClient,
 }
-/// importing into Excel spreadsheets rather than being machine
-/// friendly.record. The TSV data has irregular structure.
-/// The strategy here is to find the subsection of data we are
-/// looking for by trying to deserialize each row into ImfsdrRow and
-/// ignoring deserialization errors. The IMF provides data for the
-/// last 5 days but data may no be available for all days.
-
-#[derive(Debug, Deserialize)]
-struct ImfsdrRow {
-    currency: String,
-    price_0: Option<Price>,
-    price_1: Option<Price>,
-    price_2: Option<Price>,
-    price_3: Option<Price>,
-    price_4: Option<Price>,
-}
-
-impl ImfsdrRow {
-    /// Best price is the most recent price. Use
-    fn response_from_best_price(&self) -> Option<Response> {
-        if let Some(ref price) = self.price_0 {
-            return Some(Response { price: *price });
-        }
-        if let Some(ref price) = self.price_1 {
-            return Some(Response { price: *price });
-        }
-        if let Some(ref price) = self.price_2 {
-            return Some(Response { price: *price });
-        }
-        if let Some(ref price) = self.price_3 {
-            return Some(Response { price: *price });
-        }
-        if let Some(ref price) = self.price_4 {
-            return Some(Response { price: *price });
-        }
-        None
-    }
-}
 
 impl ImfSdrSource {
-    /// Create a new Dunamu source provider
+    /// Create a new IMF SDR source provider
     pub fn new(config: &HttpsConfig) -> Result<Self, Error> {
         let https_client = config.new_client(API_HOST)?;
         Ok(Self { https_client })
     }
 
-    /// Get trading pairs
-    pub async fn trading_pairs(&self, pair: &TradingPair) -> Result<Response, Error> {
-        if pair.1 != Currency::Sdr {
-            fail!(ErrorKind::Currency, "trading pair must be with IMF SDR");
-        }
+    /// Get trading pairs.
+    ///
+    /// One side of the pair must be the SDR: `XDR/KRW` is quoted in
+    /// currency units per SDR, and `KRW/XDR` in SDRs per currency unit.
+    pub async fn trading_pairs(&self, pair: &TradingPair) -> Result<OfficialRate, Error> {
+        let rates = self.rates().await?;
 
-        let uri = format!(
-            "https://{}/external/np/fin/data/rms_five.aspx?tsvflag=Y",
-            API_HOST
-        );
-
-        let body = self
-            .https_client
-            .get_body(&uri, &Default::default())
-            .await?;
-
-        let mut imf_sdr = csv::ReaderBuilder::new()
-            .has_headers(false)
-            .flexible(true)
-            .delimiter(b'\t')
-            .from_reader(body.reader());
-
-        let mut response_row: Option<ImfsdrRow> = None;
-
-        for result in imf_sdr.records() {
-            let record = result.map_err(|e| {
-                format_err!(ErrorKind::Source, "got error with malformed csv: {}", e)
-            })?;
-
-            let row: Result<ImfsdrRow, csv::Error> = record.deserialize(None);
-
-            match row {
-                Ok(imf_sdr_row) => {
-                    if imf_sdr_row.currency == pair.0.imf_long_name() {
-                        response_row = Some(imf_sdr_row);
-                        break;
-                    }
-                }
-                Err(_e) => continue,
+        let quote = match pair {
+            TradingPair(Currency::Sdr, currency) => rates.currency_units_per_sdr.latest(currency),
+            TradingPair(currency, Currency::Sdr) => rates.sdrs_per_currency_unit.latest(currency),
+            _ => fail!(ErrorKind::Currency, "trading pair must be with IMF SDR"),
+        };
+
+        quote.ok_or_else(|| format_err!(ErrorKind::Source, "price for {} not found", pair).into())
+    }
+
+    /// Fetch and parse the full five day rates report
+    pub async fn rates(&self) -> Result<Rates, Error> {
+        let mut query = Query::new();
+        query.add("tsvflag", "Y");
+
+        let mut body = String::new();
+        self.https_client
+            .get_body(RATES_PATH, &query)
+            .await?
+            .reader()
+            .read_to_string(&mut body)?;
+
+        body.parse()
+    }
+}
+
+/// Rates published by the IMF for the last five business days.
+///
+/// The IMF returns tab separated data in a form designed for importing
+/// into spreadsheets rather than being machine friendly. The report
+/// consists of two sections, each introduced by a title line, followed by
+/// a header row carrying the publication dates and one row per currency.
+/// Rates may be missing (`NA` or blank) for some days.
+#[derive(Clone, Debug, Default)]
+pub struct Rates {
+    /// Currency units per SDR
+    pub currency_units_per_sdr: Section,
+
+    /// SDRs per currency unit
+    pub sdrs_per_currency_unit: Section,
+}
+
+impl FromStr for Rates {
+    type Err = Error;
+
+    fn from_str(tsv: &str) -> Result<Self, Error> {
+        let mut rates = Rates::default();
+        let mut current: Option<&mut Section> = None;
+
+        for line in tsv.lines() {
+            // Cells are positional (blank cells are missing rates), so only
+            // trailing blank cells are dropped
+            let mut fields = line.split('\t').map(str::trim).collect::<Vec<_>>();
+
+            while fields.last() == Some(&"") {
+                fields.pop();
+            }
+
+            let (first, rest) = match fields.split_first() {
+                Some(split) => split,
+                None => continue,
             };
+
+            if first.starts_with("Currency units per SDR for") {
+                current = Some(&mut rates.currency_units_per_sdr);
+            } else if first.starts_with("SDRs per Currency unit for") {
+                current = Some(&mut rates.sdrs_per_currency_unit);
+            } else if let Some(section) = current.as_mut() {
+                if *first == "Currency" {
+                    section.dates = rest
+                        .iter()
+                        .map(|date| NaiveDate::parse_from_str(date, DATE_FORMAT))
+                        .collect::<Result<_, _>>()
+                        .map_err(|e| format_err!(ErrorKind::Parse, "bad IMF date: {}", e))?;
+                } else if let Some(currency) = currency_for_imf_name(first) {
+                    let prices = rest
+                        .iter()
+                        .map(|price| parse_price(price))
+                        .collect::<Result<_, _>>()?;
+
+                    section.rows.insert(currency, prices);
+                } else if !rest.is_empty() {
+                    debug!("ignoring unknown IMF currency: {}", first);
+                }
+            }
         }
 
-        match response_row {
-            Some(resp) => Response::try_from(resp)
-                .map_err(|e| format_err!(ErrorKind::Parse, "{}, {}", e, pair).into()),
-            None => Err(format_err!(ErrorKind::Parse, "price for {} not found", pair).into()),
+        for section in &[&rates.currency_units_per_sdr, &rates.sdrs_per_currency_unit] {
+            ensure!(
+                !section.dates.is_empty(),
+                ErrorKind::Parse,
+                "IMF report is missing a section or its dates"
+            );
         }
+
+        Ok(rates)
     }
 }
-/// Provides a single price point for a currency pair based extracted data
-#[derive(Clone, Debug, Serialize, Deserialize)]
-pub struct Response {
-    /// Price
-    pub price: Price,
+
+/// Section of the IMF report: rates per currency for each publication date
+#[derive(Clone, Debug, Default)]
+pub struct Section {
+    /// Publication dates, most recent first
+    pub dates: Vec<NaiveDate>,
+
+    /// Rates for each currency, in the same order as `dates`
+    pub rows: Map<Currency, Vec<Option<Price>>>,
 }
 
-impl TryFrom<ImfsdrRow> for Response {
-    type Error = &'static str;
-    fn try_from(row: ImfsdrRow) -> Result<Self, Self::Error> {
-        match row.response_from_best_price() {
-            Some(resp) => Ok(resp),
-            None => Err("No price data found for for currency pair"),
-        }
+impl Section {
+    /// Get the most recently published rate for the given currency
+    pub fn latest(&self, currency: &Currency) -> Option<OfficialRate> {
+        self.rows
+            .get(currency)?
+            .iter()
+            .zip(&self.dates)
+            .find_map(|(price, &date)| price.map(|price| OfficialRate { price, date }))
     }
 }
+
+/// Parse a price from the report, e.g. `1,673.59`. Missing rates are `NA` or
+/// blank.
+fn parse_price(s: &str) -> Result<Option<Price>, Error> {
+    if s.is_empty() || s == "NA" {
+        return Ok(None);
+    }
+
+    s.replace(',', "").parse().map(Some)
+}
+
+/// Map the long currency names used by the IMF onto [`Currency`]
+pub fn currency_for_imf_name(name: &str) -> Option<Currency> {
+    let code = match name {
+        "Algerian dinar" => "DZD",
+        "Australian dollar" => "AUD",
+        "Bahrain dinar" => "BHD",
+        "Bolivar Fuerte" => "VEF",
+        "Botswana pula" => "BWP",
+        "Brazilian real" => "BRL",
+        "Brunei dollar" => "BND",
+        "Canadian dollar" => "CAD",
+        "Chilean peso" => "CLP",
+        "Chinese yuan" => "CNY",
+        "Colombian peso" => "COP",
+        "Czech koruna" => "CZK",
+        "Danish krone" => "DKK",
+        "Euro" => "EUR",
+        "Hungarian forint" => "HUF",
+        "Icelandic krona" => "ISK",
+        "Indian rupee" => "INR",
+        "Indonesian rupiah" => "IDR",
+        "Iranian rial" => "IRR",
+        "Israeli New Shekel" => "ILS",
+        "Japanese yen" => "JPY",
+        "Kazakhstani tenge" => "KZT",
+        "Korean won" => "KRW",
+        "Kuwaiti dinar" => "KWD",
+        "Libyan dinar" => "LYD",
+        "Malaysian ringgit" => "MYR",
+        "Mauritian rupee" => "MUR",
+        "Mexican peso" => "MXN",
+        "Nepalese rupee" => "NPR",
+        "New Zealand dollar" => "NZD",
+        "Norwegian krone" => "NOK",
+        "Omani rial" => "OMR",
+        "Pakistani rupee" => "PKR",
+        "Peruvian sol" | "Nuevo Sol" => "PEN",
+        "Philippine peso" => "PHP",
+        "Polish zloty" => "PLN",
+        "Qatari riyal" => "QAR",
+        "Russian ruble" => "RUB",
+        "Saudi Arabian riyal" => "SAR",
+        "Singapore dollar" => "SGD",
+        "South African rand" => "ZAR",
+        "Sri Lankan rupee" => "LKR",
+        "Swedish krona" => "SEK",
+        "Swiss franc" => "CHF",
+        "Thai baht" => "THB",
+        "Trinidadian dollar" => "TTD",
+        "Tunisian dinar" => "TND",
+        "U.A.E. dirham" => "AED",
+        "U.K. pound" => "GBP",
+        "U.S. dollar" => "USD",
+        "Uruguayan peso" => "UYU",
+        _ => return None,
+    };
+
+    code.parse().ok()
+}
+
 #[cfg(test)]
 mod tests {
-    use super::ImfSdrSource;
+    use super::{ImfSdrSource, Rates};
+    use crate::Currency;
+    use chrono::NaiveDate;
+
+    const EXAMPLE_TSV: &str = "SDRs per Currency unit and Currency units per SDR (1)\r
+last five days\r
+\r
+Currency units per SDR for October 2021\r
+\r
+Currency\tOctober 15, 2021\tOctober 14, 2021\tOctober 13, 2021\r
+Chinese yuan\t9.10826\t9.09879\t9.09128\r
+Euro\t1.22027\t1.21935\t1.22515\r
+U.S. dollar\t1.41488\t1.41394\t1.41494\r
+Korean won\tNA\t1,678.12\t1,693.27\r
+\r
+SDRs per Currency unit for October 2021\r
+\r
+Currency\tOctober 15, 2021\tOctober 14, 2021\tOctober 13, 2021\r
+Chinese yuan\t0.1097904\t0.1099047\t0.1099955\r
+Euro\t0.8194907\t0.8201090\t0.8162266\r
+U.S. dollar\t0.7067740\t0.7072436\t0.7067430\r
+Korean won\tNA\t0.0005959\t0.0005906\r
+\r
+(1) Exchange rates are published daily except on IMF holidays.\r
+";
+
+    #[test]
+    fn parse_rates() {
+        let rates: Rates = EXAMPLE_TSV.parse().unwrap();
+        let section = &rates.currency_units_per_sdr;
+        assert_eq!(section.dates.len(), 3);
+        assert_eq!(section.dates[0], NaiveDate::from_ymd(2021, 10, 15));
+        assert_eq!(section.rows.len(), 4);
+
+        let usd = section.latest(&Currency::Usd).unwrap();
+        assert_eq!(usd.price, "1.41488".parse().unwrap());
+        assert_eq!(usd.date, NaiveDate::from_ymd(2021, 10, 15));
+
+        // Most recent KRW rate is missing, so fall back to the previous day
+        let krw = rates.sdrs_per_currency_unit.latest(&Currency::Krw).unwrap();
+        assert_eq!(krw.price, "0.0005959".parse().unwrap());
+        assert_eq!(krw.date, NaiveDate::from_ymd(2021, 10, 14));
+
+        let krw_per_sdr = section.latest(&Currency::Krw).unwrap();
+        assert_eq!(krw_per_sdr.price, "1678.12".parse().unwrap());
+    }
+
+    #[test]
+    fn parse_rates_blank_cells() {
+        let tsv = EXAMPLE_TSV.replace("Euro\t1.22027\t", "Euro\t\t");
+        let rates: Rates = tsv.parse().unwrap();
+
+        // The blank cell is a missing rate, so later rates keep their dates
+        let eur = rates.currency_units_per_sdr.latest(&Currency::Eur).unwrap();
+        assert_eq!(eur.price, "1.21935".parse().unwrap());
+        assert_eq!(eur.date, NaiveDate::from_ymd(2021, 10, 14));
+    }
+
+    #[test]
+    fn parse_rates_missing_section() {
+        let tsv = EXAMPLE_TSV
+            .split("SDRs per Currency unit for")
+            .next()
+            .unwrap();
+        assert!(tsv.parse::<Rates>().is_err());
+    }
 
     /// `trading_pairs()` test with known currency pair
     #[tokio::test]
     #[ignore]
     async fn trading_pairs_ok() {
-        let pair = "KRW/SDR".parse().unwrap();
+        let pair = "KRW/XDR".parse().unwrap();
         let quote = ImfSdrSource::new(&Default::default())
             .unwrap()
             .trading_pairs(&pair)
@@ -149,19 +314,4 @@ mod tests {
             .unwrap();
         dbg!(&quote);
     }
-
-    // `trading_pairs()` with invalid currency pair
-    // #[test]
-    // #[ignore]
-    // fn trading_pairs_404() {
-    //     let pair = "N/A".parse().unwrap();
-    //     let quote_err = block_on(ImfSdrSource::new().trading_pairs(&pair))
-    //         .err()
-    //         .unwrap();
-
-    //    use std::error::Error;
-    //    let err: &ErrorCode = quote_err.source().unwrap().downcast_ref().unwrap();
-
-    //     assert_eq!(err, &ErrorCode::InternalError);
-    // }
 }
@@ -0,0 +1,384 @@
+//! Generic JSON-over-HTTP Source Provider
+//!
+//! Allows onboarding simple REST price APIs from configuration alone.
+//! See [`JsonSourceConfig`] for the available settings.
+
+use crate::{
+    config::{source::TimestampUnit, HttpsConfig, JsonSourceConfig},
+    prelude::*,
+    Error, ErrorKind, Price, TradingPair,
+};
+use bytes::Buf;
+use iqhttp::{
+    hyper::{self, Uri},
+    HttpsClient, Query,
+};
+use percent_encoding::{utf8_percent_encode, NON_ALPHANUMERIC};
+use rust_decimal::{prelude::ToPrimitive, Decimal};
+use serde_json::Value;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// Source provider for generic JSON-over-HTTP APIs
+pub struct JsonSource {
+    https_client: HttpsClient,
+    base_url: String,
+    config: JsonSourceConfig,
+}
+
+impl JsonSource {
+    /// Create a new generic JSON source provider
+    pub fn new(config: &JsonSourceConfig, https_config: &HttpsConfig) -> Result<Self, Error> {
+        ensure!(
+            config.price.is_some() || (config.bid.is_some() && config.ask.is_some()),
+            ErrorKind::Config,
+            "JSON source for {} needs a `price` or both `bid` and `ask` pointers",
+            config.host
+        );
+
+        let base_url = if config.host.contains("://") {
+            config.host.trim_end_matches('/').to_owned()
+        } else {
+            format!("https://{}", config.host)
+        };
+
+        let uri: Uri = base_url.parse().map_err(|e| {
+            format_err!(
+                ErrorKind::Config,
+                "invalid JSON source host {}: {}",
+                base_url,
+                e
+            )
+        })?;
+
+        let host = uri.host().ok_or_else(|| {
+            format_err!(ErrorKind::Config, "JSON source has no host: {}", base_url)
+        })?;
+
+        let https_client = https_config.new_client(host)?;
+
+        Ok(Self {
+            https_client,
+            base_url,
+            config: config.clone(),
+        })
+    }
+
+    /// Is this source configured to be included in exchange rates for the
+    /// given trading pair?
+    pub fn quotes(&self, pair: &TradingPair) -> bool {
+        self.config.pairs.contains(pair)
+    }
+
+    /// Get trading pairs
+    pub async fn trading_pairs(&self, pair: &TradingPair) -> Result<Price, Error> {
+        let quote = self.quote(pair).await?;
+        quote.ensure_fresh(Duration::from_secs(self.config.max_age_secs))?;
+        quote.price()
+    }
+
+    /// Get a quote for the given trading pair
+    pub async fn quote(&self, pair: &TradingPair) -> Result<Quote, Error> {
+        let uri = self.uri(pair);
+        let mut request = hyper::Request::builder().method("GET").uri(&uri);
+
+        if let Some(api_key) = &self.config.api_key {
+            request = request.header(api_key.header.as_str(), api_key.value.as_str());
+        }
+
+        let request = request
+            .body(hyper::Body::empty())
+            .map_err(|e| format_err!(ErrorKind::Http, "error building request: {}", e))?;
+
+        let response = self.https_client.request(request).await?;
+
+        ensure!(
+            response.status().is_success(),
+            ErrorKind::Source,
+            "{} returned {} for {}",
+            self.config.host,
+            response.status(),
+            pair
+        );
+
+        let body = hyper::body::aggregate(response.into_body())
+            .await
+            .map_err(|e| ErrorKind::Http.context(e))?;
+
+        let json: Value = serde_json::from_reader(body.reader())?;
+        Quote::from_json(&self.config, &json)
+    }
+
+    /// Build the request URI for the given trading pair
+    fn uri(&self, pair: &TradingPair) -> String {
+        let path = render_template(&self.config.path, pair);
+
+        if self.config.query.is_empty() {
+            return format!("{}{}", self.base_url, path);
+        }
+
+        let mut query = Query::new();
+
+        for (field, value) in &self.config.query {
+            let value = render_template(value, pair);
+            query.add(
+                field,
+                utf8_percent_encode(&value, NON_ALPHANUMERIC).to_string(),
+            );
+        }
+
+        format!("{}{}?{}", self.base_url, path, query)
+    }
+}
+
+/// Quote extracted from a JSON response
+#[derive(Copy, Clone, Debug, Default, Eq, PartialEq)]
+pub struct Quote {
+    /// Price
+    pub price: Option<Price>,
+
+    /// Best bid
+    pub bid: Option<Price>,
+
+    /// Best ask
+    pub ask: Option<Price>,
+
+    /// Time the quote was produced by the API
+    pub timestamp: Option<SystemTime>,
+}
+
+impl Quote {
+    /// Extract a quote from a JSON response using the configured pointers
+    pub fn from_json(config: &JsonSourceConfig, json: &Value) -> Result<Self, Error> {
+        let price_at = |pointer: &Option<String>| {
+            pointer
+                .as_ref()
+                .map(|pointer| parse_price(lookup(json, pointer)?))
+                .transpose()
+        };
+
+        let timestamp = config
+            .timestamp
+            .as_ref()
+            .map(|pointer| parse_timestamp(lookup(json, pointer)?, config.timestamp_unit))
+            .transpose()?;
+
+        Ok(Self {
+            price: price_at(&config.price)?,
+            bid: price_at(&config.bid)?,
+            ask: price_at(&config.ask)?,
+            timestamp,
+        })
+    }
+
+    /// Ensure the quote (if timestamped) is no older than `max_age`.
+    /// Timestamps slightly in the future (i.e. clock skew) are accepted.
+    pub fn ensure_fresh(&self, max_age: Duration) -> Result<(), Error> {
+        let age = match self.timestamp.map(|t| SystemTime::now().duration_since(t)) {
+            Some(Ok(age)) => age,
+            _ => return Ok(()),
+        };
+
+        ensure!(
+            age <= max_age,
+            ErrorKind::Source,
+            "JSON source quote is stale: {}s old (max {}s)",
+            age.as_secs(),
+            max_age.as_secs()
+        );
+
+        Ok(())
+    }
+
+    /// Get the price, or the midpoint of the bid and ask if no price is quoted
+    pub fn price(&self) -> Result<Price, Error> {
+        match (self.price, self.bid, self.ask) {
+            (Some(price), _, _) => Ok(price),
+            (None, Some(bid), Some(ask)) => Ok((bid + ask) / 2),
+            _ => fail!(ErrorKind::Source, "JSON source quote is missing a price"),
+        }
+    }
+}
+
+/// Substitute trading pair placeholders into a template
+fn render_template(template: &str, pair: &TradingPair) -> String {
+    let base = pair.0.to_string();
+    let quote = pair.1.to_string();
+
+    template
+        .replace("{base}", &base)
+        .replace("{quote}", &quote)
+        .replace("{base_lower}", &base.to_ascii_lowercase())
+        .replace("{quote_lower}", &quote.to_ascii_lowercase())
+}
+
+/// Look up a value in a JSON document by JSON pointer
+fn lookup<'a>(json: &'a Value, pointer: &str) -> Result<&'a Value, Error> {
+    json.pointer(pointer).ok_or_else(|| {
+        format_err!(ErrorKind::Parse, "no value at JSON pointer: {}", pointer).into()
+    })
+}
+
+/// Parse a price which may be encoded as either a JSON number or string
+fn parse_price(value: &Value) -> Result<Price, Error> {
+    match value {
+        Value::Number(n) => n.to_string().parse(),
+        Value::String(s) => s.parse(),
+        other => fail!(ErrorKind::Parse, "expected price, got: {}", other),
+    }
+}
+
+/// Parse a timestamp which may be numeric (including fractional) or an
+/// RFC 3339 string
+fn parse_timestamp(value: &Value, unit: TimestampUnit) -> Result<SystemTime, Error> {
+    let number = match value {
+        Value::Number(n) => Some(n.to_string()),
+        Value::String(s) => Some(s.clone()),
+        _ => None,
+    };
+
+    let number = number.and_then(|n| {
+        n.parse::<Decimal>()
+            .or_else(|_| Decimal::from_scientific(&n))
+            .ok()
+    });
+
+    if let Some(n) = number {
+        // Convert to whole nanoseconds to avoid float rounding errors
+        let nanos = match unit {
+            TimestampUnit::Seconds => n * Decimal::from(1_000_000_000u64),
+            TimestampUnit::Milliseconds => n * Decimal::from(1_000_000u64),
+        };
+
+        return match nanos.trunc().to_u64() {
+            Some(nanos) => Ok(UNIX_EPOCH + Duration::from_nanos(nanos)),
+            None => fail!(ErrorKind::Parse, "invalid timestamp: {}", value),
+        };
+    }
+
+    match value.as_str().map(chrono::DateTime::parse_from_rfc3339) {
+        Some(Ok(datetime)) => Ok(datetime.into()),
+        _ => fail!(ErrorKind::Parse, "expected timestamp, got: {}", value),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{parse_timestamp, render_template, JsonSource, Quote};
+    use crate::config::{source::TimestampUnit, JsonSourceConfig};
+    use serde_json::json;
+    use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+    #[test]
+    fn template_substitution() {
+        let pair = "LUNA/KRW".parse().unwrap();
+        assert_eq!(
+            render_template("/v1/{quote}-{base}/{base_lower}", &pair),
+            "/v1/KRW-LUNA/luna"
+        );
+    }
+
+    #[test]
+    fn request_uri() {
+        let mut config = JsonSourceConfig {
+            host: "api.example.com".to_owned(),
+            path: "/v1/ticker/{base}-{quote}".to_owned(),
+            price: Some("/price".to_owned()),
+            ..Default::default()
+        };
+
+        let pair = "LUNA/KRW".parse().unwrap();
+        let source = JsonSource::new(&config, &Default::default()).unwrap();
+        assert_eq!(
+            source.uri(&pair),
+            "https://api.example.com/v1/ticker/LUNA-KRW"
+        );
+
+        config.host = "http://127.0.0.1:8080/".to_owned();
+        config
+            .query
+            .insert("market".to_owned(), "{quote}".to_owned());
+        let source = JsonSource::new(&config, &Default::default()).unwrap();
+        assert_eq!(
+            source.uri(&pair),
+            "http://127.0.0.1:8080/v1/ticker/LUNA-KRW?market=KRW"
+        );
+    }
+
+    #[test]
+    fn quote_from_json() {
+        let config = JsonSourceConfig {
+            price: Some("/data/0/trade_price".to_owned()),
+            bid: Some("/data/0/bid".to_owned()),
+            ask: Some("/data/0/ask".to_owned()),
+            timestamp: Some("/data/0/timestamp".to_owned()),
+            timestamp_unit: TimestampUnit::Milliseconds,
+            ..Default::default()
+        };
+
+        let response = json!({
+            "data": [{
+                "trade_price": 45210.5,
+                "bid": "45200",
+                "ask": "45220",
+                "timestamp": 1634272800123u64
+            }]
+        });
+
+        let quote = Quote::from_json(&config, &response).unwrap();
+        assert_eq!(quote.price.unwrap(), "45210.5".parse().unwrap());
+        assert_eq!(quote.bid.unwrap(), "45200".parse().unwrap());
+        assert_eq!(
+            quote.timestamp.unwrap(),
+            UNIX_EPOCH + Duration::from_millis(1_634_272_800_123)
+        );
+    }
+
+    #[test]
+    fn quote_midpoint() {
+        let config = JsonSourceConfig {
+            bid: Some("/bid".to_owned()),
+            ask: Some("/ask".to_owned()),
+            ..Default::default()
+        };
+
+        let quote = Quote::from_json(&config, &json!({"bid": "99", "ask": "101"})).unwrap();
+        assert_eq!(quote.price().unwrap(), "100".parse().unwrap());
+        assert!(Quote::from_json(&config, &json!({"bid": "99"})).is_err());
+    }
+
+    #[test]
+    fn fractional_timestamps() {
+        assert_eq!(
+            parse_timestamp(&json!(1634272800.5), TimestampUnit::Seconds).unwrap(),
+            UNIX_EPOCH + Duration::from_millis(1_634_272_800_500)
+        );
+        assert_eq!(
+            parse_timestamp(&json!("1634272800123.25"), TimestampUnit::Milliseconds).unwrap(),
+            UNIX_EPOCH + Duration::from_nanos(1_634_272_800_123_250_000)
+        );
+        assert_eq!(
+            parse_timestamp(&json!(1.6342728e9), TimestampUnit::Seconds).unwrap(),
+            UNIX_EPOCH + Duration::from_secs(1_634_272_800)
+        );
+        assert!(parse_timestamp(&json!(-1.5), TimestampUnit::Seconds).is_err());
+    }
+
+    #[test]
+    fn rejects_stale_quote() {
+        let max_age = Duration::from_secs(60);
+        let quote_at = |timestamp| Quote {
+            timestamp,
+            ..Default::default()
+        };
+
+        let now = SystemTime::now();
+        assert!(quote_at(None).ensure_fresh(max_age).is_ok());
+        assert!(quote_at(Some(now)).ensure_fresh(max_age).is_ok());
+        assert!(quote_at(Some(now + Duration::from_secs(5)))
+            .ensure_fresh(max_age)
+            .is_ok());
+        assert!(quote_at(Some(now - Duration::from_secs(120)))
+            .ensure_fresh(max_age)
+            .is_err());
+    }
+}
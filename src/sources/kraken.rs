@@ -0,0 +1,471 @@
+//! Kraken Source Provider
+//! <https://docs.kraken.com/rest/#tag/Market-Data>
+//!
+//! Kraken names some assets with a legacy `X` (crypto) or `Z` (fiat) prefix,
+//! e.g. `XXBT` and `ZUSD`, and uses `XBT` rather than `BTC` for bitcoin.
+//! These names are mapped onto [`Currency`] by [`currency_for_asset`].
+
+use super::{midpoint, AskBook, BidBook};
+use crate::{
+    config::{source::KrakenConfig, HttpsConfig},
+    prelude::*,
+    Currency, Error, ErrorKind, Map, Price, PriceQuantity, TradingPair,
+};
+use iqhttp::{HttpsClient, Query};
+use rust_decimal::Decimal;
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+
+/// Hostname for the Kraken API
+pub const API_HOST: &str = "api.kraken.com";
+
+/// OHLC intervals (in minutes) supported by Kraken
+pub const OHLC_INTERVALS: &[u32] = &[1, 5, 15, 30, 60, 240, 1440, 10080, 21600];
+
+/// Number of orderbook levels to request from `/0/public/Depth`
+pub const DEPTH_COUNT: u32 = 100;
+
+/// Source provider for Kraken
+pub struct KrakenSource {
+    https_client: HttpsClient,
+    config: KrakenConfig,
+}
+
+impl KrakenSource {
+    /// Create a new Kraken source provider
+    pub fn new(config: &KrakenConfig, https_config: &HttpsConfig) -> Result<Self, Error> {
+        ensure!(
+            OHLC_INTERVALS.contains(&config.ohlc_interval),
+            ErrorKind::Config,
+            "unsupported Kraken OHLC interval: {} (must be one of {:?})",
+            config.ohlc_interval,
+            OHLC_INTERVALS
+        );
+
+        ensure!(
+            config.ohlc_window > 0,
+            ErrorKind::Config,
+            "Kraken OHLC window must be at least 1 candle"
+        );
+
+        ensure!(
+            config.luna_usd_weight >= Decimal::from(0u8)
+                && config.luna_usd_weight <= Decimal::from(1u8),
+            ErrorKind::Config,
+            "Kraken LUNA/USD weight must be between 0 and 1: {}",
+            config.luna_usd_weight
+        );
+
+        let https_client = https_config.new_client(API_HOST)?;
+
+        Ok(Self {
+            https_client,
+            config: config.clone(),
+        })
+    }
+
+    /// Weight given to the windowed VWAP in LUNA/USD
+    pub fn luna_usd_weight(&self) -> Decimal {
+        self.config.luna_usd_weight
+    }
+
+    /// Get the midpoint of the orderbook for the given pair
+    pub async fn trading_pairs(&self, pair: &TradingPair) -> Result<Price, Error> {
+        midpoint(&self.depth(pair).await?)
+    }
+
+    /// Get the volume weighted average price over the last 24 hours
+    pub async fn vwap_24h(&self, pair: &TradingPair) -> Result<Price, Error> {
+        Ok(self.ticker(pair).await?.vwap.1)
+    }
+
+    /// Get the volume weighted average price over the configured OHLC window
+    pub async fn windowed_vwap(&self, pair: &TradingPair) -> Result<Price, Error> {
+        let candles = self.ohlc(pair, self.config.ohlc_interval).await?;
+        vwap(&candles, self.config.ohlc_window)
+    }
+
+    /// `GET /0/public/Ticker` - get ticker information
+    pub async fn ticker(&self, pair: &TradingPair) -> Result<Ticker, Error> {
+        let mut query = Query::new();
+        query.add("pair", pair_name(pair));
+
+        let result: Map<String, Ticker> = self.public("Ticker", &query).await?;
+        only_entry(result, pair)
+    }
+
+    /// `GET /0/public/Depth` - get the orderbook
+    pub async fn depth(&self, pair: &TradingPair) -> Result<Depth, Error> {
+        let mut query = Query::new();
+        query.add("pair", pair_name(pair));
+        query.add("count", DEPTH_COUNT.to_string());
+
+        let result: Map<String, Depth> = self.public("Depth", &query).await?;
+        only_entry(result, pair)
+    }
+
+    /// `GET /0/public/OHLC` - get OHLC candles at the given interval (in
+    /// minutes), oldest first
+    pub async fn ohlc(&self, pair: &TradingPair, interval: u32) -> Result<Vec<Candle>, Error> {
+        let mut query = Query::new();
+        query.add("pair", pair_name(pair));
+        query.add("interval", interval.to_string());
+
+        let result: OhlcResult = self.public("OHLC", &query).await?;
+        only_entry(result.candles, pair)
+    }
+
+    /// Make a request to a public API method
+    async fn public<T: DeserializeOwned>(&self, method: &str, query: &Query) -> Result<T, Error> {
+        let response: Response<T> = self
+            .https_client
+            .get_json(&format!("/0/public/{}", method), query)
+            .await?;
+
+        response.into_result()
+    }
+}
+
+/// Get the Kraken asset name for a currency
+pub fn asset_name(currency: &Currency) -> String {
+    match currency {
+        Currency::Btc => "XBT".to_owned(),
+        other => other.to_string(),
+    }
+}
+
+/// Get the Kraken pair name for a trading pair, e.g. `XBTUSD`
+pub fn pair_name(pair: &TradingPair) -> String {
+    format!("{}{}", asset_name(&pair.0), asset_name(&pair.1))
+}
+
+/// Get the currency for a Kraken asset name, e.g. `XXBT` or `ZUSD`
+pub fn currency_for_asset(name: &str) -> Currency {
+    let name = name.to_ascii_uppercase();
+
+    let code = match name.as_str() {
+        // Legacy prefixed names
+        "XXBT" | "XBT" => "BTC",
+        "XETH" => "ETH",
+        "ZAUD" => "AUD",
+        "ZCAD" => "CAD",
+        "ZEUR" => "EUR",
+        "ZGBP" => "GBP",
+        "ZJPY" => "JPY",
+        "ZUSD" => "USD",
+        other => other,
+    };
+
+    // `Currency` parsing is infallible (unknown assets become `Other`)
+    code.parse().unwrap()
+}
+
+/// Volume weighted average price over the last `window` committed candles.
+///
+/// Kraken's last candle is still in progress (not committed), so it's
+/// excluded.
+pub fn vwap(candles: &[Candle], window: usize) -> Result<Price, Error> {
+    let committed = &candles[..candles.len().saturating_sub(1)];
+    let start = committed.len().saturating_sub(window);
+    let mut price_volume_product = Decimal::from(0u8);
+    let mut total_volume = Decimal::from(0u8);
+
+    for candle in &committed[start..] {
+        price_volume_product += *candle.vwap * candle.volume;
+        total_volume += candle.volume;
+    }
+
+    ensure!(
+        !total_volume.is_zero(),
+        ErrorKind::Source,
+        "no Kraken trading volume in the last {} candles",
+        window
+    );
+
+    Price::new(price_volume_product / total_volume)
+}
+
+/// Get the only entry of a result keyed by Kraken's pair name (which may
+/// differ from the requested name, e.g. `XXBTZUSD` for `XBTUSD`)
+fn only_entry<T>(result: Map<String, T>, pair: &TradingPair) -> Result<T, Error> {
+    let mut entries = result.into_iter();
+
+    match (entries.next(), entries.next()) {
+        (Some((_, value)), None) => Ok(value),
+        _ => fail!(
+            ErrorKind::Source,
+            "expected exactly one Kraken result for {}",
+            pair
+        ),
+    }
+}
+
+/// Kraken API response envelope
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Response<T> {
+    /// Error messages
+    pub error: Vec<String>,
+
+    /// Result (absent on error)
+    pub result: Option<T>,
+}
+
+impl<T> Response<T> {
+    /// Get the result, or an error if the API returned any
+    pub fn into_result(self) -> Result<T, Error> {
+        ensure!(
+            self.error.is_empty(),
+            ErrorKind::Source,
+            "Kraken API error: {}",
+            self.error.join(", ")
+        );
+
+        self.result
+            .ok_or_else(|| format_err!(ErrorKind::Source, "Kraken API returned no result").into())
+    }
+}
+
+/// Ticker information
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Ticker {
+    /// Ask: price, whole lot volume, lot volume
+    #[serde(rename = "a")]
+    pub ask: (Price, Decimal, Decimal),
+
+    /// Bid: price, whole lot volume, lot volume
+    #[serde(rename = "b")]
+    pub bid: (Price, Decimal, Decimal),
+
+    /// Last trade closed: price, lot volume
+    #[serde(rename = "c")]
+    pub last: (Price, Decimal),
+
+    /// Volume: today, last 24 hours
+    #[serde(rename = "v")]
+    pub volume: (Decimal, Decimal),
+
+    /// Volume weighted average price: today, last 24 hours
+    #[serde(rename = "p")]
+    pub vwap: (Price, Price),
+
+    /// Number of trades: today, last 24 hours
+    #[serde(rename = "t")]
+    pub trades: (u64, u64),
+
+    /// Low: today, last 24 hours
+    #[serde(rename = "l")]
+    pub low: (Price, Price),
+
+    /// High: today, last 24 hours
+    #[serde(rename = "h")]
+    pub high: (Price, Price),
+
+    /// Today's opening price
+    #[serde(rename = "o")]
+    pub open: Price,
+}
+
+/// Orderbook
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Depth {
+    /// Asks: price, volume, timestamp
+    pub asks: Vec<(Price, Decimal, u64)>,
+
+    /// Bids: price, volume, timestamp
+    pub bids: Vec<(Price, Decimal, u64)>,
+}
+
+impl AskBook for Depth {
+    fn asks(&self) -> Result<Vec<PriceQuantity>, Error> {
+        Ok(self
+            .asks
+            .iter()
+            .map(|&(price, quantity, _)| PriceQuantity { price, quantity })
+            .collect())
+    }
+}
+
+impl BidBook for Depth {
+    fn bids(&self) -> Result<Vec<PriceQuantity>, Error> {
+        Ok(self
+            .bids
+            .iter()
+            .map(|&(price, quantity, _)| PriceQuantity { price, quantity })
+            .collect())
+    }
+}
+
+/// `/0/public/OHLC` result
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct OhlcResult {
+    /// ID to be used as `since` when polling for new data
+    pub last: u64,
+
+    /// Candles (keyed by pair name)
+    #[serde(flatten)]
+    pub candles: Map<String, Vec<Candle>>,
+}
+
+/// OHLC candle
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(from = "CandleFields")]
+pub struct Candle {
+    /// Start time (seconds since the Unix epoch)
+    pub time: u64,
+
+    /// Opening price
+    pub open: Price,
+
+    /// High price
+    pub high: Price,
+
+    /// Low price
+    pub low: Price,
+
+    /// Closing price
+    pub close: Price,
+
+    /// Volume weighted average price
+    pub vwap: Price,
+
+    /// Volume
+    pub volume: Decimal,
+
+    /// Number of trades
+    pub count: u64,
+}
+
+/// Candles are serialized as arrays of fields
+type CandleFields = (u64, Price, Price, Price, Price, Price, Decimal, u64);
+
+impl From<CandleFields> for Candle {
+    fn from(fields: CandleFields) -> Self {
+        let (time, open, high, low, close, vwap, volume, count) = fields;
+
+        Candle {
+            time,
+            open,
+            high,
+            low,
+            close,
+            vwap,
+            volume,
+            count,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        currency_for_asset, pair_name, vwap, Depth, KrakenSource, OhlcResult, Response, Ticker,
+    };
+    use crate::{config::source::KrakenConfig, sources::midpoint, Currency, Map};
+
+    #[test]
+    fn map_asset_names() {
+        assert_eq!(currency_for_asset("XXBT"), Currency::Btc);
+        assert_eq!(currency_for_asset("XBT"), Currency::Btc);
+        assert_eq!(currency_for_asset("ZUSD"), Currency::Usd);
+        assert_eq!(currency_for_asset("ZEUR"), Currency::Eur);
+        assert_eq!(currency_for_asset("LUNA"), Currency::Luna);
+        assert_eq!(currency_for_asset("USDT"), Currency::Usdt);
+        assert_eq!(pair_name(&"BTC/USD".parse().unwrap()), "XBTUSD");
+        assert_eq!(pair_name(&"LUNA/USD".parse().unwrap()), "LUNAUSD");
+    }
+
+    #[test]
+    fn parse_ticker() {
+        let json = r#"{"error":[],"result":{"XXBTZUSD":{
+            "a":["30300.10000","1","1.000"],
+            "b":["30300.00000","1","1.000"],
+            "c":["30303.20000","0.00067643"],
+            "v":["4083.67001100","4412.73601799"],
+            "p":["30706.77771","30689.13205"],
+            "t":[34619,38907],
+            "l":["29868.30000","29868.30000"],
+            "h":["31631.00000","31631.00000"],
+            "o":"30502.80000"}}}"#;
+
+        let response: Response<Map<String, Ticker>> = serde_json::from_str(json).unwrap();
+        let ticker = &response.into_result().unwrap()["XXBTZUSD"];
+        assert_eq!(ticker.vwap.1, "30689.13205".parse().unwrap());
+        assert_eq!(ticker.trades, (34619, 38907));
+    }
+
+    #[test]
+    fn parse_depth() {
+        let json = r#"{"error":[],"result":{"LUNAUSD":{
+            "asks":[["10.20","12.5",1616663113],["10.30","1.0",1616663112]],
+            "bids":[["10.00","3.0",1616663113]]}}}"#;
+
+        let response: Response<Map<String, Depth>> = serde_json::from_str(json).unwrap();
+        let depth = &response.into_result().unwrap()["LUNAUSD"];
+        assert_eq!(midpoint(depth).unwrap(), "10.10".parse().unwrap());
+    }
+
+    #[test]
+    fn parse_ohlc_and_compute_vwap() {
+        let json = r#"{"error":[],"result":{"LUNAUSD":[
+            [1616662740,"9.0","9.0","9.0","9.0","9.0","100.0",5],
+            [1616662800,"10.0","10.5","9.5","10.0","10.0","1.0",1],
+            [1616662860,"10.0","13.0","10.0","13.0","13.0","2.0",3],
+            [1616662920,"13.0","50.0","13.0","50.0","50.0","1000.0",9]
+            ],"last":1616662860}}"#;
+
+        let response: Response<OhlcResult> = serde_json::from_str(json).unwrap();
+        let result = response.into_result().unwrap();
+        assert_eq!(result.last, 1616662860);
+
+        let candles = &result.candles["LUNAUSD"];
+        assert_eq!(candles.len(), 4);
+        assert_eq!(candles[2].close, "13.0".parse().unwrap());
+
+        // Only the last two committed candles fall within the window
+        assert_eq!(vwap(candles, 2).unwrap(), "12".parse().unwrap());
+    }
+
+    #[test]
+    fn vwap_excludes_in_progress_candle() {
+        let json = r#"{"error":[],"result":{"LUNAUSD":[
+            [1616662800,"10.0","10.0","10.0","10.0","10.0","1.0",1],
+            [1616662860,"10.0","50.0","10.0","50.0","50.0","1000.0",9]
+            ],"last":1616662800}}"#;
+
+        let response: Response<OhlcResult> = serde_json::from_str(json).unwrap();
+        let candles = &response.into_result().unwrap().candles["LUNAUSD"];
+
+        // The in-progress candle is excluded even though it's in the window
+        assert_eq!(vwap(candles, 2).unwrap(), "10".parse().unwrap());
+        assert!(vwap(&candles[1..], 2).is_err());
+    }
+
+    #[test]
+    fn luna_usd_weight_is_opt_in() {
+        let kraken = KrakenSource::new(&Default::default(), &Default::default()).unwrap();
+        assert!(kraken.luna_usd_weight().is_zero());
+
+        let config = KrakenConfig {
+            luna_usd_weight: "1.5".parse().unwrap(),
+            ..Default::default()
+        };
+        assert!(KrakenSource::new(&config, &Default::default()).is_err());
+    }
+
+    #[test]
+    fn api_error() {
+        let json = r#"{"error":["EQuery:Unknown asset pair"]}"#;
+        let response: Response<Map<String, Depth>> = serde_json::from_str(json).unwrap();
+        assert!(response.into_result().is_err());
+    }
+
+    #[ignore]
+    #[tokio::test]
+    async fn luna_usd() {
+        let kraken = KrakenSource::new(&Default::default(), &Default::default()).unwrap();
+        let pair = "LUNA/USD".parse().unwrap();
+
+        dbg!(kraken.trading_pairs(&pair).await.unwrap());
+        dbg!(kraken.vwap_24h(&pair).await.unwrap());
+        dbg!(kraken.windowed_vwap(&pair).await.unwrap());
+    }
+}
@@ -0,0 +1,225 @@
+//! Upbit Source Provider
+//! <https://docs.upbit.com/reference>
+//!
+//! Only KRW markets (e.g. `KRW-LUNA`) are supported.
+
+use super::{midpoint, AskBook, BidBook};
+use crate::{
+    config::HttpsConfig, prelude::*, Currency, Error, ErrorKind, Price, PriceQuantity, TradingPair,
+};
+use iqhttp::{HttpsClient, Query};
+use rust_decimal::Decimal;
+use serde::{de, Deserialize, Serialize};
+
+/// Hostname for the Upbit API
+pub const API_HOST: &str = "api.upbit.com";
+
+/// Source provider for Upbit
+pub struct UpbitSource {
+    https_client: HttpsClient,
+}
+
+impl UpbitSource {
+    /// Create a new Upbit source provider
+    pub fn new(config: &HttpsConfig) -> Result<Self, Error> {
+        let https_client = config.new_client(API_HOST)?;
+        Ok(Self { https_client })
+    }
+
+    /// Get the midpoint of the orderbook for the given pair
+    pub async fn trading_pairs(&self, pair: &TradingPair) -> Result<Price, Error> {
+        midpoint(&self.orderbook(pair).await?)
+    }
+
+    /// `GET /v1/orderbook` - get the orderbook for a KRW market
+    pub async fn orderbook(&self, pair: &TradingPair) -> Result<Orderbook, Error> {
+        let mut query = Query::new();
+        query.add("markets", market_code(pair)?);
+
+        let api_response: Vec<Orderbook> =
+            self.https_client.get_json("/v1/orderbook", &query).await?;
+
+        first_result(api_response, pair)
+    }
+
+    /// `GET /v1/ticker` - get the ticker for a KRW market
+    pub async fn ticker(&self, pair: &TradingPair) -> Result<Ticker, Error> {
+        let mut query = Query::new();
+        query.add("markets", market_code(pair)?);
+
+        let api_response: Vec<Ticker> = self.https_client.get_json("/v1/ticker", &query).await?;
+        first_result(api_response, pair)
+    }
+}
+
+/// Get the Upbit market code for a trading pair, e.g. `KRW-LUNA` for LUNA/KRW
+pub fn market_code(pair: &TradingPair) -> Result<String, Error> {
+    if pair.1 != Currency::Krw {
+        fail!(ErrorKind::Currency, "trading pair must be with KRW");
+    }
+
+    Ok(format!("{}-{}", pair.1, pair.0))
+}
+
+/// Get the first (and only) result of a single-market request
+fn first_result<T>(results: Vec<T>, pair: &TradingPair) -> Result<T, Error> {
+    results
+        .into_iter()
+        .next()
+        .ok_or_else(|| format_err!(ErrorKind::Source, "no Upbit results for {}", pair).into())
+}
+
+/// `/v1/orderbook` response entry
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Orderbook {
+    /// Market code, e.g. `KRW-LUNA`
+    pub market: String,
+
+    /// Timestamp (milliseconds since the Unix epoch)
+    pub timestamp: u64,
+
+    /// Orderbook levels, best first
+    pub orderbook_units: Vec<OrderbookUnit>,
+}
+
+/// Orderbook level: one ask and one bid
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct OrderbookUnit {
+    /// Ask price
+    #[serde(deserialize_with = "deserialize_price")]
+    pub ask_price: Price,
+
+    /// Bid price
+    #[serde(deserialize_with = "deserialize_price")]
+    pub bid_price: Price,
+
+    /// Ask quantity
+    #[serde(deserialize_with = "deserialize_decimal")]
+    pub ask_size: Decimal,
+
+    /// Bid quantity
+    #[serde(deserialize_with = "deserialize_decimal")]
+    pub bid_size: Decimal,
+}
+
+impl AskBook for Orderbook {
+    fn asks(&self) -> Result<Vec<PriceQuantity>, Error> {
+        Ok(self
+            .orderbook_units
+            .iter()
+            .map(|unit| PriceQuantity {
+                price: unit.ask_price,
+                quantity: unit.ask_size,
+            })
+            .collect())
+    }
+}
+
+impl BidBook for Orderbook {
+    fn bids(&self) -> Result<Vec<PriceQuantity>, Error> {
+        Ok(self
+            .orderbook_units
+            .iter()
+            .map(|unit| PriceQuantity {
+                price: unit.bid_price,
+                quantity: unit.bid_size,
+            })
+            .collect())
+    }
+}
+
+/// `/v1/ticker` response entry
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Ticker {
+    /// Market code, e.g. `KRW-LUNA`
+    pub market: String,
+
+    /// Last traded price
+    #[serde(deserialize_with = "deserialize_price")]
+    pub trade_price: Price,
+
+    /// Accumulated trade value over the last 24 hours (KRW)
+    #[serde(deserialize_with = "deserialize_decimal")]
+    pub acc_trade_price_24h: Decimal,
+
+    /// Accumulated trade volume over the last 24 hours
+    #[serde(deserialize_with = "deserialize_decimal")]
+    pub acc_trade_volume_24h: Decimal,
+
+    /// Timestamp (milliseconds since the Unix epoch)
+    pub timestamp: u64,
+}
+
+/// Deserialize a decimal from a JSON number without going through a float
+fn deserialize_decimal<'de, D>(deserializer: D) -> Result<Decimal, D::Error>
+where
+    D: de::Deserializer<'de>,
+{
+    let value = serde_json::Number::deserialize(deserializer)?;
+    value.to_string().parse().map_err(de::Error::custom)
+}
+
+/// Deserialize a price from a JSON number
+fn deserialize_price<'de, D>(deserializer: D) -> Result<Price, D::Error>
+where
+    D: de::Deserializer<'de>,
+{
+    Price::new(deserialize_decimal(deserializer)?).map_err(de::Error::custom)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{market_code, Orderbook, Ticker, UpbitSource};
+    use crate::sources::midpoint;
+
+    #[test]
+    fn market_codes() {
+        assert_eq!(
+            market_code(&"LUNA/KRW".parse().unwrap()).unwrap(),
+            "KRW-LUNA"
+        );
+        assert!(market_code(&"LUNA/USD".parse().unwrap()).is_err());
+    }
+
+    #[test]
+    fn parse_orderbook() {
+        let json = r#"[{
+            "market": "KRW-LUNA",
+            "timestamp": 1616663113211,
+            "total_ask_size": 12.5,
+            "total_bid_size": 3.0,
+            "orderbook_units": [
+                {"ask_price": 15100.0, "bid_price": 15000.0, "ask_size": 10.5, "bid_size": 1.0},
+                {"ask_price": 15200.0, "bid_price": 14900.0, "ask_size": 2.0, "bid_size": 2.0}
+            ]
+        }]"#;
+
+        let orderbooks: Vec<Orderbook> = serde_json::from_str(json).unwrap();
+        assert_eq!(midpoint(&orderbooks[0]).unwrap(), "15050".parse().unwrap());
+    }
+
+    #[test]
+    fn parse_ticker() {
+        let json = r#"[{
+            "market": "KRW-LUNA",
+            "trade_price": 15050.0,
+            "acc_trade_price_24h": 12345678901.5,
+            "acc_trade_volume_24h": 820345.123,
+            "timestamp": 1616663113211
+        }]"#;
+
+        let tickers: Vec<Ticker> = serde_json::from_str(json).unwrap();
+        assert_eq!(tickers[0].trade_price, "15050".parse().unwrap());
+    }
+
+    #[tokio::test]
+    #[ignore]
+    async fn trading_pairs_ok() {
+        let pair = "LUNA/KRW".parse().unwrap();
+        let _price = UpbitSource::new(&Default::default())
+            .unwrap()
+            .trading_pairs(&pair)
+            .await
+            .unwrap();
+    }
+}
@@ -0,0 +1,263 @@
+//! Streaming market data
+//!
+//! Maintains live local orderbooks and tickers from exchange WebSocket feeds.
+//! Each feed runs in its own task which reconnects with exponential backoff.
+//! Consumers read the latest state through [`Streams`] and fall back to
+//! polled REST sources whenever the streamed data is missing or stale.
+
+pub mod binance;
+pub mod bithumb;
+pub mod orderbook;
+pub mod upbit;
+
+pub use self::orderbook::OrderBook;
+
+use crate::{
+    config::{stream::StreamConfig, DelphiConfig, HttpsConfig},
+    prelude::*,
+    Error, Map, Price,
+};
+use std::{
+    future::Future,
+    sync::Arc,
+    time::{Duration, Instant},
+};
+use tokio::sync::RwLock;
+
+/// Orderbook shared between a stream task and its readers
+pub type SharedBook = Arc<RwLock<OrderBook>>;
+
+/// Ticker shared between a stream task and its readers
+pub type SharedTicker = Arc<RwLock<Option<Ticker>>>;
+
+/// Last traded price as reported by a ticker stream
+#[derive(Copy, Clone, Debug)]
+pub struct Ticker {
+    /// Last traded price
+    pub price: Price,
+
+    /// When this ticker was received
+    pub received_at: Instant,
+}
+
+impl Ticker {
+    /// Create a new ticker received just now
+    pub fn new(price: Price) -> Self {
+        Self {
+            price,
+            received_at: Instant::now(),
+        }
+    }
+
+    /// Was this ticker received within `max_age`?
+    pub fn is_fresh(&self, max_age: Duration) -> bool {
+        self.received_at.elapsed() <= max_age
+    }
+}
+
+/// Live market data streams
+pub struct Streams {
+    /// Streaming configuration
+    config: StreamConfig,
+
+    /// HTTPS configuration (for REST snapshots)
+    https: HttpsConfig,
+
+    /// Binance orderbooks (keyed by symbol)
+    binance: Map<String, SharedBook>,
+
+    /// Upbit tickers (keyed by market code)
+    upbit: Map<String, SharedTicker>,
+
+    /// Bithumb tickers (keyed by symbol)
+    bithumb: Map<String, SharedTicker>,
+}
+
+impl Streams {
+    /// Create stream handles from config. Call [`Streams::spawn`] from within
+    /// the async runtime to start receiving data.
+    pub fn new(config: &DelphiConfig) -> Self {
+        let stream_config = &config.stream;
+
+        let binance = stream_config
+            .binance
+            .iter()
+            .flat_map(|binance| &binance.symbols)
+            .map(|symbol| (symbol.to_ascii_uppercase(), SharedBook::default()))
+            .collect();
+
+        let upbit = stream_config
+            .upbit
+            .iter()
+            .flat_map(|upbit| &upbit.markets)
+            .map(|market| (market.to_ascii_uppercase(), SharedTicker::default()))
+            .collect();
+
+        let bithumb = stream_config
+            .bithumb
+            .iter()
+            .flat_map(|bithumb| &bithumb.symbols)
+            .map(|symbol| (symbol.to_ascii_uppercase(), SharedTicker::default()))
+            .collect();
+
+        Self {
+            config: stream_config.clone(),
+            https: config.https.clone(),
+            binance,
+            upbit,
+            bithumb,
+        }
+    }
+
+    /// Spawn a task for each configured stream
+    pub fn spawn(&self) -> Result<(), Error> {
+        if let Some(binance_config) = &self.config.binance {
+            for (symbol, book) in &self.binance {
+                let stream =
+                    binance::DepthStream::new(binance_config, &self.https, symbol, book.clone())?;
+
+                spawn_with_reconnect(&self.config, format!("binance {}", symbol), move || {
+                    stream.clone().run()
+                });
+            }
+        }
+
+        if let Some(upbit_config) = &self.config.upbit {
+            let stream = upbit::TickerStream::new(upbit_config, self.upbit.clone());
+            spawn_with_reconnect(&self.config, "upbit ticker", move || stream.clone().run());
+        }
+
+        if let Some(bithumb_config) = &self.config.bithumb {
+            let stream = bithumb::TickerStream::new(bithumb_config, self.bithumb.clone());
+            spawn_with_reconnect(&self.config, "bithumb ticker", move || stream.clone().run());
+        }
+
+        Ok(())
+    }
+
+    /// Get a copy of a Binance orderbook if it is in sync and fresh
+    pub async fn binance_book(&self, symbol: &str) -> Option<OrderBook> {
+        let book = self.binance.get(symbol)?.read().await;
+
+        if book.is_fresh(self.config.max_age()) {
+            Some(book.clone())
+        } else {
+            None
+        }
+    }
+
+    /// Get the latest Upbit price for a market if it is fresh
+    pub async fn upbit_price(&self, market: &str) -> Option<Price> {
+        fresh_price(self.upbit.get(market)?, self.config.max_age()).await
+    }
+
+    /// Get the latest Bithumb price for a symbol if it is fresh
+    pub async fn bithumb_price(&self, symbol: &str) -> Option<Price> {
+        fresh_price(self.bithumb.get(symbol)?, self.config.max_age()).await
+    }
+}
+
+/// Get the price from a ticker if it is fresh
+async fn fresh_price(ticker: &SharedTicker, max_age: Duration) -> Option<Price> {
+    ticker
+        .read()
+        .await
+        .filter(|ticker| ticker.is_fresh(max_age))
+        .map(|ticker| ticker.price)
+}
+
+/// Exponential backoff for reconnecting streams
+#[derive(Clone, Debug)]
+pub struct Backoff {
+    min: Duration,
+    max: Duration,
+    current: Duration,
+}
+
+impl Backoff {
+    /// Create a new backoff starting at `min` and capped at `max`
+    pub fn new(min: Duration, max: Duration) -> Self {
+        Self {
+            min,
+            max,
+            current: min,
+        }
+    }
+
+    /// Get the next delay, doubling the delay after it
+    pub fn next_delay(&mut self) -> Duration {
+        let delay = self.current;
+        self.current = (self.current * 2).min(self.max);
+        delay
+    }
+
+    /// Reset the delay after a successful connection
+    pub fn reset(&mut self) {
+        self.current = self.min;
+    }
+}
+
+/// Run a stream, reconnecting with backoff whenever it terminates.
+///
+/// The stream future resolves to `Ok(true)` if it received data before
+/// disconnecting, in which case the backoff is reset.
+fn spawn_with_reconnect<F, Fut>(config: &StreamConfig, name: impl Into<String>, mut connect: F)
+where
+    F: FnMut() -> Fut + Send + 'static,
+    Fut: Future<Output = Result<bool, Error>> + Send + 'static,
+{
+    let name = name.into();
+    let mut backoff = Backoff::new(
+        Duration::from_millis(config.reconnect_min_ms),
+        Duration::from_millis(config.reconnect_max_ms),
+    );
+
+    tokio::spawn(async move {
+        loop {
+            match connect().await {
+                Ok(received) => {
+                    if received {
+                        backoff.reset();
+                    }
+
+                    warn!("{} stream disconnected", name);
+                }
+                Err(e) => warn!("{} stream error: {}", name, e),
+            }
+
+            let delay = backoff.next_delay();
+            info!("reconnecting {} stream in {:?}", name, delay);
+            tokio::time::sleep(delay).await;
+        }
+    });
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::Backoff;
+    use std::{net::SocketAddr, time::Duration};
+    use warp::{Filter, Rejection, Reply};
+
+    /// Serve the given routes on an ephemeral local port
+    pub(crate) fn serve<F>(routes: F) -> SocketAddr
+    where
+        F: Filter<Error = Rejection> + Clone + Send + Sync + 'static,
+        F::Extract: Reply,
+    {
+        let (addr, server) = warp::serve(routes).bind_ephemeral(([127, 0, 0, 1], 0));
+        tokio::spawn(server);
+        addr
+    }
+
+    #[test]
+    fn backoff_doubles_until_max() {
+        let mut backoff = Backoff::new(Duration::from_millis(100), Duration::from_millis(350));
+        assert_eq!(backoff.next_delay(), Duration::from_millis(100));
+        assert_eq!(backoff.next_delay(), Duration::from_millis(200));
+        assert_eq!(backoff.next_delay(), Duration::from_millis(350));
+        assert_eq!(backoff.next_delay(), Duration::from_millis(350));
+
+        backoff.reset();
+        assert_eq!(backoff.next_delay(), Duration::from_millis(100));
+    }
+}
@@ -0,0 +1,385 @@
+//! Binance diff-depth stream
+//! <https://github.com/binance/binance-spot-api-docs/blob/master/web-socket-streams.md#diff-depth-stream>
+//!
+//! Maintains a local orderbook by applying depth updates on top of a REST
+//! snapshot, following Binance's guide to managing a local orderbook:
+//!
+//! 1. Open the stream and buffer the events received
+//! 2. Fetch a depth snapshot from `/api/v3/depth`
+//! 3. Drop events where `u` is <= the snapshot's `lastUpdateId`
+//! 4. The first processed event must have `U` <= `lastUpdateId`+1 <= `u`
+//! 5. Each subsequent event's `U` must be the previous event's `u`+1
+//!
+//! Any gap in the sequence triggers a resync from a fresh snapshot.
+
+use super::SharedBook;
+use crate::{
+    config::{stream::BinanceStreamConfig, HttpsConfig},
+    prelude::*,
+    streaming::OrderBook,
+    Error, ErrorKind, Price,
+};
+use bytes::Buf;
+use futures::StreamExt;
+use iqhttp::{hyper, HttpsClient, Uri};
+use rust_decimal::Decimal;
+use serde::Deserialize;
+use std::{
+    fmt::{self, Display},
+    sync::Arc,
+};
+use tokio_tungstenite::{connect_async, tungstenite::Message};
+
+/// Number of price levels to request in depth snapshots
+pub const SNAPSHOT_LIMIT: u32 = 1000;
+
+/// Binance diff-depth stream for a single symbol
+#[derive(Clone)]
+pub struct DepthStream(Arc<Inner>);
+
+struct Inner {
+    /// Trading symbol, e.g. `LUNAUSDT`
+    symbol: String,
+
+    /// WebSocket URL for this symbol's depth stream
+    ws_url: String,
+
+    /// URI for REST depth snapshots
+    snapshot_uri: Uri,
+
+    /// HTTPS client for REST depth snapshots
+    https_client: HttpsClient,
+
+    /// Orderbook maintained by this stream
+    book: SharedBook,
+}
+
+impl DepthStream {
+    /// Create a new depth stream for the given symbol
+    pub fn new(
+        config: &BinanceStreamConfig,
+        https_config: &HttpsConfig,
+        symbol: &str,
+        book: SharedBook,
+    ) -> Result<Self, Error> {
+        let ws_url = format!(
+            "{}/ws/{}@depth@100ms",
+            config.ws_url.trim_end_matches('/'),
+            symbol.to_ascii_lowercase()
+        );
+
+        let snapshot_uri: Uri = format!(
+            "{}/api/v3/depth?symbol={}&limit={}",
+            config.rest_url.trim_end_matches('/'),
+            symbol.to_ascii_uppercase(),
+            SNAPSHOT_LIMIT
+        )
+        .parse()
+        .map_err(|e| format_err!(ErrorKind::Config, "invalid Binance REST URL: {}", e))?;
+
+        let https_client = https_config.new_client(snapshot_uri.host().unwrap_or_default())?;
+
+        Ok(DepthStream(Arc::new(Inner {
+            symbol: symbol.to_ascii_uppercase(),
+            ws_url,
+            snapshot_uri,
+            https_client,
+            book,
+        })))
+    }
+
+    /// Connect to the stream and maintain the orderbook until disconnected.
+    ///
+    /// Returns whether any updates were received.
+    pub async fn run(self) -> Result<bool, Error> {
+        let result = self.sync_book().await;
+        self.0.book.write().await.mark_unsynced();
+        result
+    }
+
+    /// Apply updates to the orderbook, resyncing whenever a gap is detected
+    async fn sync_book(&self) -> Result<bool, Error> {
+        let (mut ws, _) = connect_async(self.0.ws_url.as_str()).await?;
+        info!("connected to Binance depth stream for {}", self.0.symbol);
+
+        // The stream is open, so events buffer while the snapshot is fetched
+        let mut sequence = self.resync().await?;
+        let mut received = false;
+
+        while let Some(msg) = ws.next().await {
+            let update: DepthUpdate = match msg? {
+                Message::Text(text) => serde_json::from_str(&text)?,
+                Message::Binary(bytes) => serde_json::from_slice(&bytes)?,
+                Message::Close(_) => break,
+                _ => continue,
+            };
+
+            received = true;
+            let result = sequence.apply(&mut *self.0.book.write().await, &update);
+
+            if let Err(gap) = result {
+                warn!("Binance {} depth stream {}; resyncing", self.0.symbol, gap);
+                self.0.book.write().await.mark_unsynced();
+                sequence = self.resync().await?;
+            }
+        }
+
+        Ok(received)
+    }
+
+    /// Reset the orderbook from a fresh depth snapshot
+    async fn resync(&self) -> Result<Sequence, Error> {
+        let request = hyper::Request::get(&self.0.snapshot_uri)
+            .body(hyper::Body::empty())
+            .map_err(|e| format_err!(ErrorKind::Http, "error building request: {}", e))?;
+
+        let response = self.0.https_client.request(request).await?;
+
+        ensure!(
+            response.status().is_success(),
+            ErrorKind::Source,
+            "Binance depth snapshot for {} returned {}",
+            self.0.symbol,
+            response.status()
+        );
+
+        let body = hyper::body::aggregate(response.into_body())
+            .await
+            .map_err(|e| ErrorKind::Http.context(e))?;
+
+        let snapshot: DepthSnapshot = serde_json::from_reader(body.reader())?;
+        self.0
+            .book
+            .write()
+            .await
+            .reset(snapshot.bids, snapshot.asks);
+        Ok(Sequence::new(snapshot.last_update_id))
+    }
+}
+
+/// Sequencing state for applying depth updates on top of a snapshot
+#[derive(Copy, Clone, Debug)]
+pub struct Sequence {
+    /// Last update ID applied to the book
+    last_update_id: u64,
+
+    /// Has an update been applied since the snapshot?
+    bridged: bool,
+}
+
+impl Sequence {
+    /// Start sequencing from a snapshot's `lastUpdateId`
+    pub fn new(last_update_id: u64) -> Self {
+        Self {
+            last_update_id,
+            bridged: false,
+        }
+    }
+
+    /// Apply an update to the book if it is next in sequence. Updates which
+    /// predate the snapshot are ignored.
+    pub fn apply(&mut self, book: &mut OrderBook, update: &DepthUpdate) -> Result<(), Gap> {
+        if update.final_update_id <= self.last_update_id {
+            return Ok(());
+        }
+
+        let expected = self.last_update_id + 1;
+
+        let in_sequence = if self.bridged {
+            update.first_update_id == expected
+        } else {
+            update.first_update_id <= expected
+        };
+
+        if !in_sequence {
+            return Err(Gap {
+                expected,
+                found: update.first_update_id,
+            });
+        }
+
+        for &(price, quantity) in &update.bids {
+            book.update_bid(price, quantity);
+        }
+
+        for &(price, quantity) in &update.asks {
+            book.update_ask(price, quantity);
+        }
+
+        book.mark_synced();
+        self.last_update_id = update.final_update_id;
+        self.bridged = true;
+        Ok(())
+    }
+}
+
+/// Gap in the depth update sequence
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub struct Gap {
+    /// Expected first update ID
+    pub expected: u64,
+
+    /// First update ID received
+    pub found: u64,
+}
+
+impl Display for Gap {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "sequence gap (expected update {}, got {})",
+            self.expected, self.found
+        )
+    }
+}
+
+/// Depth update event
+#[derive(Clone, Debug, Deserialize)]
+pub struct DepthUpdate {
+    /// First update ID in event
+    #[serde(rename = "U")]
+    pub first_update_id: u64,
+
+    /// Final update ID in event
+    #[serde(rename = "u")]
+    pub final_update_id: u64,
+
+    /// Bids to be updated
+    #[serde(rename = "b")]
+    pub bids: Vec<(Price, Decimal)>,
+
+    /// Asks to be updated
+    #[serde(rename = "a")]
+    pub asks: Vec<(Price, Decimal)>,
+}
+
+/// `GET /api/v3/depth` response
+#[derive(Clone, Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DepthSnapshot {
+    /// Last update ID included in this snapshot
+    pub last_update_id: u64,
+
+    /// Bids
+    pub bids: Vec<(Price, Decimal)>,
+
+    /// Asks
+    pub asks: Vec<(Price, Decimal)>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{DepthStream, DepthUpdate, Gap, Sequence};
+    use crate::{
+        config::stream::BinanceStreamConfig,
+        sources::midpoint,
+        streaming::{tests::serve, OrderBook, SharedBook},
+    };
+    use futures::SinkExt;
+    use serde_json::json;
+    use std::{
+        sync::{
+            atomic::{AtomicUsize, Ordering},
+            Arc,
+        },
+        time::Duration,
+    };
+    use warp::{ws::Message, Filter};
+
+    fn update(first: u64, last: u64, bids: &[(&str, &str)]) -> DepthUpdate {
+        serde_json::from_value(json!({"U": first, "u": last, "b": bids, "a": []})).unwrap()
+    }
+
+    #[test]
+    fn sequence_drops_stale_and_detects_gaps() {
+        let mut book = OrderBook::default();
+        book.reset(vec![], vec![("101".parse().unwrap(), 1.into())]);
+        let mut sequence = Sequence::new(100);
+
+        // Predates the snapshot: ignored
+        sequence
+            .apply(&mut book, &update(90, 95, &[("1", "1")]))
+            .unwrap();
+        assert_eq!(book.best_bid(), None);
+
+        // Bridges the snapshot: U <= lastUpdateId + 1 <= u
+        sequence
+            .apply(&mut book, &update(99, 101, &[("99", "1")]))
+            .unwrap();
+        assert_eq!(book.best_bid().unwrap(), "99".parse().unwrap());
+
+        sequence
+            .apply(&mut book, &update(102, 102, &[("99.5", "1")]))
+            .unwrap();
+        assert_eq!(book.best_bid().unwrap(), "99.5".parse().unwrap());
+
+        assert_eq!(
+            sequence.apply(&mut book, &update(104, 105, &[])),
+            Err(Gap {
+                expected: 103,
+                found: 104
+            })
+        );
+    }
+
+    /// Drive the stream from a local stand-in server. The stream includes a
+    /// sequence gap, which must trigger a resync from a second snapshot.
+    #[tokio::test]
+    async fn resync_on_gap() {
+        let snapshots = Arc::new(AtomicUsize::new(0));
+        let snapshot_count = snapshots.clone();
+
+        let depth = warp::path!("api" / "v3" / "depth").map(move || {
+            let body = match snapshot_count.fetch_add(1, Ordering::SeqCst) {
+                0 => json!({"lastUpdateId": 100, "bids": [["99", "1"]], "asks": [["101", "1"]]}),
+                _ => json!({"lastUpdateId": 200, "bids": [["90", "1"]], "asks": [["110", "1"]]}),
+            };
+            warp::reply::json(&body)
+        });
+
+        let ws = warp::path!("ws" / String)
+            .and(warp::ws())
+            .map(|_, ws: warp::ws::Ws| {
+                ws.on_upgrade(|mut socket| async move {
+                let events = [
+                    json!({"e": "depthUpdate", "U": 99, "u": 101, "b": [["99.5", "2"]], "a": []}),
+                    json!({"e": "depthUpdate", "U": 110, "u": 111, "b": [], "a": []}),
+                    json!({"e": "depthUpdate", "U": 201, "u": 201, "b": [["95", "1"]], "a": []}),
+                ];
+
+                for event in &events {
+                    socket.send(Message::text(event.to_string())).await.unwrap();
+                }
+
+                tokio::time::sleep(Duration::from_secs(10)).await;
+            })
+            });
+
+        let addr = serve(depth.or(ws));
+        let config = BinanceStreamConfig {
+            symbols: vec!["LUNAUSDT".to_owned()],
+            ws_url: format!("ws://{}", addr),
+            rest_url: format!("http://{}", addr),
+        };
+
+        let book = SharedBook::default();
+        let stream =
+            DepthStream::new(&config, &Default::default(), "LUNAUSDT", book.clone()).unwrap();
+        tokio::spawn(stream.run());
+
+        for _ in 0..100 {
+            {
+                let book = book.read().await;
+                if book.is_fresh(Duration::from_secs(1)) && book.best_bid() == "95".parse().ok() {
+                    assert_eq!(midpoint(&*book).unwrap(), "102.5".parse().unwrap());
+                    assert_eq!(snapshots.load(Ordering::SeqCst), 2);
+                    return;
+                }
+            }
+
+            tokio::time::sleep(Duration::from_millis(50)).await;
+        }
+
+        panic!("orderbook was not resynced");
+    }
+}
@@ -0,0 +1,146 @@
+//! Bithumb ticker stream
+//! <https://apidocs.bithumb.com/docs/websocket_public>
+
+use super::{SharedTicker, Ticker};
+use crate::{config::stream::BithumbStreamConfig, prelude::*, Error, Map, Price};
+use futures::{SinkExt, StreamExt};
+use serde::Deserialize;
+use serde_json::json;
+use std::sync::Arc;
+use tokio_tungstenite::{connect_async, tungstenite::Message};
+
+/// Bithumb ticker stream for a set of symbols
+#[derive(Clone)]
+pub struct TickerStream(Arc<Inner>);
+
+struct Inner {
+    /// WebSocket URL
+    ws_url: String,
+
+    /// Tickers maintained by this stream (keyed by symbol)
+    tickers: Map<String, SharedTicker>,
+}
+
+impl TickerStream {
+    /// Create a new ticker stream which updates the given tickers
+    pub fn new(config: &BithumbStreamConfig, tickers: Map<String, SharedTicker>) -> Self {
+        TickerStream(Arc::new(Inner {
+            ws_url: config.ws_url.clone(),
+            tickers,
+        }))
+    }
+
+    /// Connect to the stream and update tickers until disconnected.
+    ///
+    /// Returns whether any tickers were received.
+    pub async fn run(self) -> Result<bool, Error> {
+        let (mut ws, _) = connect_async(self.0.ws_url.as_str()).await?;
+
+        let symbols = self.0.tickers.keys().collect::<Vec<_>>();
+        let subscribe = json!({"type": "ticker", "symbols": symbols, "tickTypes": ["30M"]});
+
+        ws.send(Message::text(subscribe.to_string())).await?;
+        info!("subscribed to Bithumb tickers: {:?}", symbols);
+
+        let mut received = false;
+
+        while let Some(msg) = ws.next().await {
+            let msg: StreamMessage = match msg? {
+                Message::Text(text) => serde_json::from_str(&text)?,
+                Message::Binary(bytes) => serde_json::from_slice(&bytes)?,
+                Message::Close(_) => break,
+                _ => continue,
+            };
+
+            // Status messages (e.g. subscription acknowledgements) have no content
+            let content = match msg.content {
+                Some(content) => content,
+                None => continue,
+            };
+
+            if let Some(shared) = self.0.tickers.get(&content.symbol) {
+                *shared.write().await = Some(Ticker::new(content.close_price));
+                received = true;
+            }
+        }
+
+        Ok(received)
+    }
+}
+
+/// Message received from the stream
+#[derive(Clone, Debug, Deserialize)]
+pub struct StreamMessage {
+    /// Ticker content (absent from status messages)
+    pub content: Option<TickerContent>,
+}
+
+/// Ticker content
+#[derive(Clone, Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TickerContent {
+    /// Symbol, e.g. `LUNA_KRW`
+    pub symbol: String,
+
+    /// Current price
+    pub close_price: Price,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::TickerStream;
+    use crate::{
+        config::stream::BithumbStreamConfig,
+        streaming::{tests::serve, SharedTicker},
+    };
+    use futures::{SinkExt, StreamExt};
+    use serde_json::json;
+    use std::time::Duration;
+    use warp::{ws::Message, Filter};
+
+    #[tokio::test]
+    async fn updates_ticker() {
+        let ws = warp::ws().map(|ws: warp::ws::Ws| {
+            ws.on_upgrade(|mut socket| async move {
+                socket.next().await.unwrap().unwrap();
+
+                let messages = [
+                    json!({"status": "0000", "resmsg": "Filter Registered Successfully"}),
+                    json!({"type": "ticker", "content": {
+                        "symbol": "LUNA_KRW",
+                        "tickType": "30M",
+                        "closePrice": "49850"
+                    }}),
+                ];
+
+                for msg in &messages {
+                    socket.send(Message::text(msg.to_string())).await.unwrap();
+                }
+
+                tokio::time::sleep(Duration::from_secs(10)).await;
+            })
+        });
+
+        let addr = serve(ws);
+        let config = BithumbStreamConfig {
+            symbols: vec!["LUNA_KRW".to_owned()],
+            ws_url: format!("ws://{}", addr),
+        };
+
+        let ticker = SharedTicker::default();
+        let mut tickers = crate::Map::new();
+        tickers.insert("LUNA_KRW".to_owned(), ticker.clone());
+        tokio::spawn(TickerStream::new(&config, tickers).run());
+
+        for _ in 0..100 {
+            if let Some(ticker) = *ticker.read().await {
+                assert_eq!(ticker.price, "49850".parse().unwrap());
+                return;
+            }
+
+            tokio::time::sleep(Duration::from_millis(50)).await;
+        }
+
+        panic!("ticker was not updated");
+    }
+}
@@ -0,0 +1,157 @@
+//! Local orderbooks maintained from streamed updates
+
+use crate::{
+    prelude::*,
+    sources::{AskBook, BidBook},
+    Error, ErrorKind, Map, Price, PriceQuantity,
+};
+use rust_decimal::Decimal;
+use std::time::{Duration, Instant};
+
+/// Local orderbook: price levels and their quantities
+#[derive(Clone, Debug, Default)]
+pub struct OrderBook {
+    /// Bid price levels
+    bids: Map<Price, Decimal>,
+
+    /// Ask price levels
+    asks: Map<Price, Decimal>,
+
+    /// Is this book in sync with the exchange?
+    synced: bool,
+
+    /// When this book was last known to be in sync
+    updated_at: Option<Instant>,
+}
+
+impl OrderBook {
+    /// Replace the contents of this book with a snapshot
+    pub fn reset<B, A>(&mut self, bids: B, asks: A)
+    where
+        B: IntoIterator<Item = (Price, Decimal)>,
+        A: IntoIterator<Item = (Price, Decimal)>,
+    {
+        self.bids.clear();
+        self.asks.clear();
+
+        for (price, quantity) in bids {
+            self.update_bid(price, quantity);
+        }
+
+        for (price, quantity) in asks {
+            self.update_ask(price, quantity);
+        }
+
+        self.mark_synced();
+    }
+
+    /// Set the quantity at a bid price level (zero removes the level)
+    pub fn update_bid(&mut self, price: Price, quantity: Decimal) {
+        update_level(&mut self.bids, price, quantity);
+    }
+
+    /// Set the quantity at an ask price level (zero removes the level)
+    pub fn update_ask(&mut self, price: Price, quantity: Decimal) {
+        update_level(&mut self.asks, price, quantity);
+    }
+
+    /// Record that this book is in sync as of now
+    pub fn mark_synced(&mut self) {
+        self.synced = true;
+        self.updated_at = Some(Instant::now());
+    }
+
+    /// Record that this book is no longer in sync (e.g. on disconnect)
+    pub fn mark_unsynced(&mut self) {
+        self.synced = false;
+    }
+
+    /// Is this book in sync and updated within `max_age`?
+    pub fn is_fresh(&self, max_age: Duration) -> bool {
+        self.synced
+            && self
+                .updated_at
+                .map(|updated_at| updated_at.elapsed() <= max_age)
+                .unwrap_or(false)
+    }
+
+    /// Best (highest) bid
+    pub fn best_bid(&self) -> Option<Price> {
+        self.bids.keys().next_back().copied()
+    }
+
+    /// Best (lowest) ask
+    pub fn best_ask(&self) -> Option<Price> {
+        self.asks.keys().next().copied()
+    }
+}
+
+impl AskBook for OrderBook {
+    fn asks(&self) -> Result<Vec<PriceQuantity>, Error> {
+        ensure!(
+            !self.asks.is_empty(),
+            ErrorKind::Source,
+            "orderbook has no asks"
+        );
+        Ok(price_quantities(&self.asks))
+    }
+}
+
+impl BidBook for OrderBook {
+    fn bids(&self) -> Result<Vec<PriceQuantity>, Error> {
+        ensure!(
+            !self.bids.is_empty(),
+            ErrorKind::Source,
+            "orderbook has no bids"
+        );
+        Ok(price_quantities(&self.bids))
+    }
+}
+
+/// Set the quantity of a price level, removing it if the quantity is zero
+fn update_level(levels: &mut Map<Price, Decimal>, price: Price, quantity: Decimal) {
+    if quantity.is_zero() {
+        levels.remove(&price);
+    } else {
+        levels.insert(price, quantity);
+    }
+}
+
+/// Convert price levels into [`PriceQuantity`] values
+fn price_quantities(levels: &Map<Price, Decimal>) -> Vec<PriceQuantity> {
+    levels
+        .iter()
+        .map(|(&price, &quantity)| PriceQuantity { price, quantity })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::OrderBook;
+    use crate::sources::midpoint;
+    use std::time::Duration;
+
+    #[test]
+    fn apply_updates() {
+        let mut book = OrderBook::default();
+        assert!(!book.is_fresh(Duration::from_secs(1)));
+
+        book.reset(
+            vec![
+                ("99".parse().unwrap(), 1.into()),
+                ("98".parse().unwrap(), 2.into()),
+            ],
+            vec![("101".parse().unwrap(), 1.into())],
+        );
+        assert!(book.is_fresh(Duration::from_secs(1)));
+        assert_eq!(midpoint(&book).unwrap(), "100".parse().unwrap());
+
+        // Removing the best bid moves the midpoint
+        book.update_bid("99".parse().unwrap(), 0.into());
+        assert_eq!(book.best_bid().unwrap(), "98".parse().unwrap());
+        assert_eq!(midpoint(&book).unwrap(), "99.5".parse().unwrap());
+
+        book.mark_unsynced();
+        assert!(!book.is_fresh(Duration::from_secs(1)));
+    }
+}
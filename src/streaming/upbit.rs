@@ -0,0 +1,156 @@
+//! Upbit ticker stream
+//! <https://docs.upbit.com/docs/upbit-quotation-websocket>
+
+use super::{SharedTicker, Ticker};
+use crate::{config::stream::UpbitStreamConfig, prelude::*, Error, Map, Price};
+use futures::{SinkExt, StreamExt};
+use serde::Deserialize;
+use serde_json::json;
+use std::sync::Arc;
+use tokio_tungstenite::{connect_async, tungstenite::Message};
+
+/// Upbit ticker stream for a set of markets
+#[derive(Clone)]
+pub struct TickerStream(Arc<Inner>);
+
+struct Inner {
+    /// WebSocket URL
+    ws_url: String,
+
+    /// Tickers maintained by this stream (keyed by market code)
+    tickers: Map<String, SharedTicker>,
+}
+
+impl TickerStream {
+    /// Create a new ticker stream which updates the given tickers
+    pub fn new(config: &UpbitStreamConfig, tickers: Map<String, SharedTicker>) -> Self {
+        TickerStream(Arc::new(Inner {
+            ws_url: config.ws_url.clone(),
+            tickers,
+        }))
+    }
+
+    /// Connect to the stream and update tickers until disconnected.
+    ///
+    /// Returns whether any tickers were received.
+    pub async fn run(self) -> Result<bool, Error> {
+        let (mut ws, _) = connect_async(self.0.ws_url.as_str()).await?;
+
+        let codes = self.0.tickers.keys().collect::<Vec<_>>();
+        let subscribe = json!([
+            {"ticket": format!("delphi-{}", rand::random::<u32>())},
+            {"type": "ticker", "codes": codes, "isOnlyRealtime": true}
+        ]);
+
+        ws.send(Message::text(subscribe.to_string())).await?;
+        info!("subscribed to Upbit tickers: {:?}", codes);
+
+        let mut received = false;
+
+        while let Some(msg) = ws.next().await {
+            // Upbit sends JSON in binary frames
+            let ticker: TickerMessage = match msg? {
+                Message::Binary(bytes) => serde_json::from_slice(&bytes)?,
+                Message::Text(text) => serde_json::from_str(&text)?,
+                Message::Close(_) => break,
+                _ => continue,
+            };
+
+            if let Some(shared) = self.0.tickers.get(&ticker.code) {
+                let price = ticker.trade_price.to_string().parse::<Price>()?;
+                *shared.write().await = Some(Ticker::new(price));
+                received = true;
+            }
+        }
+
+        Ok(received)
+    }
+}
+
+/// Ticker message
+#[derive(Clone, Debug, Deserialize)]
+pub struct TickerMessage {
+    /// Market code, e.g. `KRW-LUNA`
+    pub code: String,
+
+    /// Last traded price
+    pub trade_price: serde_json::Number,
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        config::{stream::UpbitStreamConfig, StreamConfig},
+        streaming::{spawn_with_reconnect, tests::serve, SharedTicker},
+    };
+    use futures::{SinkExt, StreamExt};
+    use serde_json::json;
+    use std::{
+        sync::{
+            atomic::{AtomicUsize, Ordering},
+            Arc,
+        },
+        time::Duration,
+    };
+    use warp::{ws::Message, Filter};
+
+    /// The stand-in server hangs up after each ticker, so the stream must
+    /// reconnect to receive the second price.
+    #[tokio::test]
+    async fn reconnects_and_updates_ticker() {
+        let connections = Arc::new(AtomicUsize::new(0));
+        let connection_count = connections.clone();
+
+        let ws = warp::ws().map(move |ws: warp::ws::Ws| {
+            let n = connection_count.fetch_add(1, Ordering::SeqCst);
+
+            ws.on_upgrade(move |mut socket| async move {
+                let subscribe = socket.next().await.unwrap().unwrap();
+                assert!(subscribe.to_str().unwrap().contains("KRW-LUNA"));
+
+                let ticker = json!({
+                    "type": "ticker",
+                    "code": "KRW-LUNA",
+                    "trade_price": 50000 + n,
+                    "stream_type": "REALTIME"
+                });
+
+                socket
+                    .send(Message::binary(ticker.to_string()))
+                    .await
+                    .unwrap();
+            })
+        });
+
+        let addr = serve(ws);
+        let config = StreamConfig {
+            reconnect_min_ms: 10,
+            upbit: Some(UpbitStreamConfig {
+                markets: vec!["KRW-LUNA".to_owned()],
+                ws_url: format!("ws://{}", addr),
+            }),
+            ..Default::default()
+        };
+
+        let ticker = SharedTicker::default();
+        let mut tickers = crate::Map::new();
+        tickers.insert("KRW-LUNA".to_owned(), ticker.clone());
+
+        let stream = super::TickerStream::new(config.upbit.as_ref().unwrap(), tickers);
+        spawn_with_reconnect(&config, "upbit", move || stream.clone().run());
+
+        for _ in 0..100 {
+            if let Some(ticker) = *ticker.read().await {
+                // Prices after the first connection come from a reconnect
+                if ticker.price > "50000".parse().unwrap() {
+                    assert!(connections.load(Ordering::SeqCst) >= 2);
+                    return;
+                }
+            }
+
+            tokio::time::sleep(Duration::from_millis(50)).await;
+        }
+
+        panic!("ticker was not updated after reconnecting");
+    }
+}
@@ -9,6 +9,7 @@ use std::{
 };
 
 /// Trading pairs
+#[derive(Clone, Debug, Eq, PartialEq)]
 pub struct TradingPair(pub Currency, pub Currency);
 
 impl TradingPair {